@@ -0,0 +1,84 @@
+// compare the throughput of sending large files with and without `sendfile(2)`.
+// run: `cargo bench --bench sendfile`
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use spk::h2tp;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+const FILE_SIZE: usize = 256 * 1024 * 1024;
+const ROUNDS: usize = 8;
+
+async fn fetch(stream: &mut TcpStream, buf: &mut Vec<u8>) -> usize {
+	stream.write_all(b"GET /large.bin HTTP/1.1\r\nhost: 127.0.0.1\r\n\r\n").await.unwrap();
+
+	let mut head: Vec<u8> = vec![];
+	loop {
+		head.push(stream.read_u8().await.unwrap());
+		if head.ends_with(b"\r\n\r\n") {
+			break;
+		}
+	}
+	let head = String::from_utf8(head).unwrap();
+	let cl: usize = head
+		.lines()
+		.find_map(|l| l.strip_prefix("content-length: "))
+		.unwrap()
+		.parse()
+		.unwrap();
+
+	let mut remain = cl;
+	while remain > 0 {
+		let size = std::cmp::min(remain, buf.len());
+		let n = stream.read(&mut buf[..size]).await.unwrap();
+		assert!(n > 0);
+		remain -= n;
+	}
+	return cl;
+}
+
+async fn run(zerocopy: bool, port: u16, root: &str) -> f64 {
+	let mut server = h2tp::server();
+	server.zerocopy(zerocopy);
+	let shutdownhandler = server.shutdownhandler();
+	let addr = format!("127.0.0.1:{port}");
+	let reader = Arc::new(h2tp::SimpleOsReader::new(root, ""));
+
+	let laddr = addr.clone();
+	tokio::spawn(async move {
+		server.listen(laddr.as_str(), Some(reader)).await;
+	});
+	tokio::time::sleep(Duration::from_millis(200)).await;
+
+	let mut stream = TcpStream::connect(addr.as_str()).await.unwrap();
+	let mut buf = vec![0; 1024 * 1024];
+	fetch(&mut stream, &mut buf).await;
+
+	let begin = Instant::now();
+	let mut total = 0;
+	for _ in 0..ROUNDS {
+		total += fetch(&mut stream, &mut buf).await;
+	}
+	let elapsed = begin.elapsed();
+	drop(stream);
+
+	h2tp::shutdown(&shutdownhandler, 1000).await;
+	return total as f64 / 1024.0 / 1024.0 / elapsed.as_secs_f64();
+}
+
+#[tokio::main]
+async fn main() {
+	let root = std::env::temp_dir().join(format!("spk-bench-{}", std::process::id()));
+	std::fs::create_dir_all(&root).unwrap();
+	std::fs::write(root.join("large.bin"), vec![7u8; FILE_SIZE]).unwrap();
+	let rootstr = root.to_str().unwrap().to_string();
+
+	let copied = run(false, 18480, &rootstr).await;
+	let zerocopied = run(true, 18481, &rootstr).await;
+
+	println!("buffered copy: {:.1} MiB/s", copied);
+	println!("sendfile:      {:.1} MiB/s", zerocopied);
+
+	std::fs::remove_dir_all(&root).unwrap();
+}
@@ -0,0 +1,27 @@
+use std::sync::Arc;
+use spk::h2tp;
+
+// `curl -T a.png http://127.0.0.1:8080/upload/a.png` or `curl -F f=@a.png http://127.0.0.1:8080/upload/`
+#[tokio::main]
+async fn main() {
+	let mut server = h2tp::server();
+	let shutdownhandler = server.shutdownhandler();
+	server.defer_body(true);
+
+	let root = std::env::args().nth(1).unwrap_or_else(|| ".".to_string());
+	let mut uploader = h2tp::Uploader::new(root.as_str(), "/upload");
+	uploader.set_max_size(256 * 1024 * 1024);
+
+	tokio::spawn(async move {
+		server.listen("127.0.0.1:8080", Some(Arc::new(uploader))).await;
+	});
+
+	match tokio::signal::ctrl_c().await {
+		Ok(()) => {
+			h2tp::shutdown(&shutdownhandler, 5000).await;
+		}
+		Err(err) => {
+			eprintln!("Unable to listen for shutdown signal: {}", err);
+		}
+	}
+}
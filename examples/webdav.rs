@@ -0,0 +1,27 @@
+use std::sync::Arc;
+use spk::h2tp;
+
+// mount it by a WebDAV client, like `cadaver http://127.0.0.1:8080/dav/`
+#[tokio::main]
+async fn main() {
+	let mut server = h2tp::server();
+	let shutdownhandler = server.shutdownhandler();
+	server.defer_body(true);
+
+	let root = std::env::args().nth(1).unwrap_or_else(|| ".".to_string());
+
+	tokio::spawn(async move {
+		server
+			.listen("127.0.0.1:8080", Some(Arc::new(h2tp::WebDav::new(root.as_str(), "/dav"))))
+			.await;
+	});
+
+	match tokio::signal::ctrl_c().await {
+		Ok(()) => {
+			h2tp::shutdown(&shutdownhandler, 5000).await;
+		}
+		Err(err) => {
+			eprintln!("Unable to listen for shutdown signal: {}", err);
+		}
+	}
+}
@@ -2,6 +2,13 @@ use std::sync::atomic::Ordering;
 
 pub const ATOMIC_ORDERING: Ordering = Ordering::Relaxed;
 pub const MESSAGE_BUFFER_SIZE: usize = 4096;
+/// when `Server::defer_body` is enabled, the sized request bodies larger than this are not read into memory,
+/// use `Request::body_reader` to read them.
+pub const MAX_BUFFERED_BODY_SIZE: usize = 1024 * 1024;
+/// the unread request bodies larger than this are not drained after the response, the connection is closed instead.
+pub const MAX_DISCARDED_BODY_SIZE: usize = 64 * 1024;
+/// the `application/x-www-form-urlencoded` bodies larger than this are rejected by `Request::form`.
+pub const MAX_FORM_BODY_SIZE: usize = 256 * 1024;
 
 struct Cfg {
 	atomic_ordering: Ordering,
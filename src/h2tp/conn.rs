@@ -1,12 +1,24 @@
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
 use tokio::net::TcpStream;
 
-use crate::h2tp::cfg::ATOMIC_ORDERING;
+use crate::h2tp::cfg::{ATOMIC_ORDERING, MAX_DISCARDED_BODY_SIZE};
+use crate::h2tp::status_code::StatusCode;
+use crate::h2tp::error::{DefaultErrorFormatter, Error, ErrorFormatter};
+use crate::h2tp::extensions::Extensions;
 use crate::h2tp::handler::Handler;
 use crate::h2tp::request::Request;
-use crate::h2tp::response::Response;
+use crate::h2tp::headers::hns;
+use crate::h2tp::methods;
+use crate::h2tp::response::{IntoResponse, Response};
+use std::any::Any;
+use std::future::Future;
+use std::io;
 use std::net::SocketAddr;
-use std::sync::atomic::AtomicBool;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicU64};
 use std::sync::Arc;
+use std::task::{ready, Context, Poll};
 
 use super::types::{AsyncReader, AsyncWriter, CliTlsStream, ServTlsStream};
 
@@ -16,15 +28,121 @@ pub struct Conn {
 	stream: Option<TcpStream>,
 	servtlsstream: Option<ServTlsStream>,
 	clitlsstream: Option<CliTlsStream>,
+	pub(crate) zerocopy: bool,
+	pub(crate) defer_body: bool,
+	pub(crate) state: Option<Arc<Extensions>>,
+	pub(crate) formatter: Arc<dyn ErrorFormatter>,
+}
+
+/// `TcpRef` read and write a `TcpStream` by a shared reference, so the stream is still
+/// available for the zero-copy writes while the reader and writer are alive.
+struct TcpRef<'a>(&'a TcpStream);
+
+impl<'a> AsyncRead for TcpRef<'a> {
+	fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+		loop {
+			ready!(self.0.poll_read_ready(cx))?;
+			match self.0.try_read(buf.initialize_unfilled()) {
+				Ok(n) => {
+					buf.advance(n);
+					return Poll::Ready(Ok(()));
+				}
+				Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+					continue;
+				}
+				Err(e) => {
+					return Poll::Ready(Err(e));
+				}
+			}
+		}
+	}
+}
+
+impl<'a> AsyncWrite for TcpRef<'a> {
+	fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+		loop {
+			ready!(self.0.poll_write_ready(cx))?;
+			match self.0.try_write(buf) {
+				Ok(n) => {
+					return Poll::Ready(Ok(n));
+				}
+				Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+					continue;
+				}
+				Err(e) => {
+					return Poll::Ready(Err(e));
+				}
+			}
+		}
+	}
+
+	fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+		return Poll::Ready(Ok(()));
+	}
+
+	fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+		return Poll::Ready(Ok(()));
+	}
+}
+
+/// `CatchUnwind` resolve to `Err(payload)` if polling the inner future panics.
+pub(crate) struct CatchUnwind<F> {
+	inner: F,
+}
+
+impl<F: Future + Unpin> Future for CatchUnwind<F> {
+	type Output = Result<F::Output, Box<dyn Any + Send>>;
+
+	fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+		let inner = &mut self.inner;
+		return match catch_unwind(AssertUnwindSafe(|| Pin::new(inner).poll(cx))) {
+			Ok(Poll::Ready(v)) => Poll::Ready(Ok(v)),
+			Ok(Poll::Pending) => Poll::Pending,
+			Err(payload) => Poll::Ready(Err(payload)),
+		};
+	}
+}
+
+/// `panic_message` return the message of a panic payload.
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+	return match payload.downcast_ref::<&'static str>() {
+		Some(v) => v,
+		None => match payload.downcast_ref::<String>() {
+			Some(v) => v.as_str(),
+			None => "Box<dyn Any>",
+		},
+	};
+}
+
+/// `ConnGuard` count a connection while it is alive, the count is decreased even if the connection task panics.
+pub(crate) struct ConnGuard(Arc<AtomicU64>);
+
+impl ConnGuard {
+	pub(crate) fn new(count: Arc<AtomicU64>) -> Self {
+		count.fetch_add(1, ATOMIC_ORDERING);
+		return Self(count);
+	}
+}
+
+impl Drop for ConnGuard {
+	fn drop(&mut self) {
+		self.0.fetch_sub(1, ATOMIC_ORDERING);
+	}
 }
 
 pub(crate) struct ConnStatus {
 	pub(crate) addr: SocketAddr,
+	/// the server name that the client sent in the TLS handshake.
+	pub(crate) sni: Option<String>,
 }
 
 impl ConnStatus {
 	fn new(conn: &Conn) -> Self {
-		return Self { addr: conn.addr };
+		let sni = match conn.servtlsstream.as_ref() {
+			Some(stream) => stream.get_ref().1.sni_hostname().map(|v| v.to_string()),
+			None => None,
+		};
+		return Self { addr: conn.addr, sni };
 	}
 }
 
@@ -40,6 +158,10 @@ impl Conn {
 			stream: None,
 			servtlsstream: Some(stream),
 			clitlsstream: None,
+			zerocopy: false,
+			defer_body: false,
+			state: None,
+			formatter: Arc::new(DefaultErrorFormatter),
 		};
 	}
 
@@ -54,6 +176,10 @@ impl Conn {
 			stream: None,
 			servtlsstream: None,
 			clitlsstream: Some(stream),
+			zerocopy: false,
+			defer_body: false,
+			state: None,
+			formatter: Arc::new(DefaultErrorFormatter),
 		};
 	}
 
@@ -64,18 +190,26 @@ impl Conn {
 			stream: Some(stream),
 			servtlsstream: None,
 			clitlsstream: None,
+			zerocopy: true,
+			defer_body: false,
+			state: None,
+			formatter: Arc::new(DefaultErrorFormatter),
 		};
 	}
 
-	fn rwpair<'a>(&'a mut self) -> (Box<dyn AsyncReader + 'a>, Box<dyn AsyncWriter + 'a>) {
-		let r: Box<dyn AsyncReader>;
+	/// `rwpair` return the reader and writer of the connection, and the `TcpStream` if zero-copy is available.
+	fn rwpair<'a>(&'a mut self) -> (Box<dyn AsyncReader + Sync + 'a>, Box<dyn AsyncWriter + 'a>, Option<&'a TcpStream>) {
+		let r: Box<dyn AsyncReader + Sync>;
 		let w: Box<dyn AsyncWriter>;
+		let mut tcp: Option<&TcpStream> = None;
 
-		match self.stream.as_mut() {
+		match self.stream.as_ref() {
 			Some(sref) => {
-				let (rp, wp) = sref.split();
-				r = Box::new(rp);
-				w = Box::new(wp);
+				r = Box::new(TcpRef(sref));
+				w = Box::new(TcpRef(sref));
+				if self.zerocopy {
+					tcp = Some(sref);
+				}
 			}
 			None => match self.servtlsstream.as_mut() {
 				Some(sref) => {
@@ -95,7 +229,18 @@ impl Conn {
 				},
 			},
 		}
-		return (r, w);
+		return (r, w, tcp);
+	}
+
+	/// `format_error` write the body of the error that the handler responded.
+	fn format_error(formatter: &dyn ErrorFormatter, req: &Request, resp: &mut Response) {
+		match resp.error.take() {
+			Some(err) => {
+				formatter.format(&err, req, resp);
+				resp.error = Some(err);
+			}
+			None => {}
+		}
 	}
 
 	// https://github.com/rustls/rustls/issues/288
@@ -105,35 +250,201 @@ impl Conn {
 
 		let mut req = Request::new();
 		req.msg.conn = Some(&status);
+		req.state = self.state.clone();
 
 		let mut resp = Response::new();
 		let cc = self.server_is_closing.clone();
+		let formatter = self.formatter.clone();
+		let defer = self.defer_body;
 
-		let (mut r, mut w) = self.rwpair();
-		let r = r.as_mut();
+		let (mut r, mut w, tcp) = self.rwpair();
 		let w = w.as_mut();
+		req.msg.attach(r.as_mut());
 
 		loop {
-			match req.from(r).await {
+			match req.msg.from_attached(defer).await {
 				Some(e) => {
 					if !e.is_empty() && !e.is_eof() {
 						println!("{e:?}");
 					}
+					if e.is_bad_request() {
+						resp.clear();
+						Error::from(e).into_response(&mut resp);
+						resp.headers().reset(hns::CONNECTION, "close");
+						Self::format_error(formatter.as_ref(), &req, &mut resp);
+						let _ = resp.msg.to(w, None, None, false).await;
+					}
 					break;
 				}
 				None => {}
 			}
 
-			handler.handle(&mut req, &mut resp).await;
+			let result = CatchUnwind { inner: handler.handle(&mut req, &mut resp) }.await;
+			match result {
+				Ok(_) => {}
+				Err(payload) => {
+					// never log the headers or the query, they may carry credentials.
+					let path = req.path().split(['?', '#']).next().unwrap_or("");
+					println!("handler panicked: {} <- {} {}", panic_message(payload.as_ref()), req.method(), path);
+					// the state of the request body is unknown, respond and close the connection.
+					resp.clear();
+					Error::newstatic(StatusCode::InternalServerError, StatusCode::InternalServerError.msg()).into_response(&mut resp);
+					resp.headers().reset(hns::CONNECTION, "close");
+					Self::format_error(formatter.as_ref(), &req, &mut resp);
+					let _ = resp.msg.to(w, None, None, false).await;
+					break;
+				}
+			}
+			Self::format_error(formatter.as_ref(), &req, &mut resp);
 
 			if cc.load(ATOMIC_ORDERING) {
 				return;
 			}
 
-			resp.msg.to(w).await;
+			// draining a large unread body costs more than a new connection.
+			let close = req.msg.body_unread() > MAX_DISCARDED_BODY_SIZE;
+			if close {
+				resp.headers().reset(hns::CONNECTION, "close");
+			}
+
+			let head = req.method().eq_ignore_ascii_case(methods::HEAD);
+			match resp.msg.to(w, resp.body.as_mut(), tcp, head).await {
+				Ok(_) => {}
+				Err(_) => {
+					// the body is incomplete, the client can not find the start of the next response.
+					break;
+				}
+			}
+
+			if close {
+				break;
+			}
+			match req.msg.discard_body().await {
+				Some(_) => {
+					break;
+				}
+				None => {}
+			}
 
 			req.clear();
 			resp.clear();
 		}
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use std::fmt::Write;
+	use std::sync::atomic::{AtomicBool, AtomicU64};
+	use std::sync::Arc;
+	use crate::h2tp::cfg::ATOMIC_ORDERING;
+	use crate::h2tp::conn::{panic_message, CatchUnwind, Conn, ConnGuard, TcpRef};
+	use crate::h2tp::response::RespBody;
+	use crate::h2tp::{Handler, Request, Response};
+	use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+	use tokio::net::{TcpListener, TcpStream};
+
+	#[tokio::test]
+	async fn test_catch_unwind() {
+		let handler = crate::func!(req, _, {
+			tokio::task::yield_now().await;
+			if req.path() == "/boom" {
+				panic!("boom at {}", req.path());
+			}
+		});
+		let mut req = Request::new();
+		let mut resp = Response::new();
+		assert!(CatchUnwind { inner: handler.handle(&mut req, &mut resp) }.await.is_ok());
+		req.msg.startline.1.push_str("/boom");
+		let payload = CatchUnwind { inner: handler.handle(&mut req, &mut resp) }.await.err().unwrap();
+		assert_eq!(panic_message(payload.as_ref()), "boom at /boom");
+
+		let count = Arc::new(AtomicU64::new(0));
+		let c = count.clone();
+		let task = tokio::spawn(async move {
+			let _guard = ConnGuard::new(c);
+			panic!("conn");
+		});
+		assert!(task.await.is_err());
+		assert_eq!(count.load(ATOMIC_ORDERING), 0);
+	}
+
+	#[tokio::test]
+	async fn test_head() {
+		let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+		let addr = listener.local_addr().unwrap();
+		let client = tokio::spawn(async move {
+			let mut stream = TcpStream::connect(addr).await.unwrap();
+			stream.write_all(b"HEAD / HTTP/1.1\r\nhost: a\r\n\r\n").await.unwrap();
+			let mut received = Vec::new();
+			while !received.windows(4).any(|w| w == b"\r\n\r\n") {
+				let mut buf = [0u8; 256];
+				let n = stream.read(&mut buf).await.unwrap();
+				received.extend_from_slice(&buf[..n]);
+			}
+			// the next request is sent after the response of `HEAD`, it must not see any body bytes.
+			stream.write_all(b"GET / HTTP/1.1\r\nhost: a\r\n\r\n").await.unwrap();
+			stream.shutdown().await.unwrap();
+			stream.read_to_end(&mut received).await.unwrap();
+			return received;
+		});
+		let (stream, addr) = listener.accept().await.unwrap();
+		let mut conn = Conn::new(addr, stream, Arc::new(AtomicBool::new(false)));
+		let handler: Arc<dyn Handler> = Arc::new(crate::func!(_, resp, {
+			let _ = resp.write_str("hello");
+		}));
+		conn.as_server(handler).await;
+		drop(conn);
+
+		let received = String::from_utf8(client.await.unwrap()).unwrap();
+		let head = "HTTP/1.1 200 OK\r\ncontent-length: 5\r\n\r\n";
+		assert_eq!(received, format!("{head}{head}hello"));
+	}
+
+	#[tokio::test]
+	async fn test_sendfile() {
+		let path = std::env::temp_dir().join(format!("spk-sendfile-{}", std::process::id()));
+		let data: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+		std::fs::write(&path, &data).unwrap();
+
+		let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+		let addr = listener.local_addr().unwrap();
+		let client = tokio::spawn(async move {
+			let mut stream = TcpStream::connect(addr).await.unwrap();
+			let mut received = Vec::new();
+			stream.read_to_end(&mut received).await.unwrap();
+			return received;
+		});
+		let (stream, _) = listener.accept().await.unwrap();
+
+		let mut resp = Response::new();
+		let mut body = RespBody::File(tokio::fs::File::open(&path).await.unwrap());
+		assert!(resp.msg.to(&mut TcpRef(&stream), Some(&mut body), Some(&stream), false).await.is_ok());
+
+		let mut file = tokio::fs::File::open(&path).await.unwrap();
+		file.seek(std::io::SeekFrom::Start(1000)).await.unwrap();
+		let mut body = RespBody::FilePart(file, 5000);
+		assert!(resp.msg.to(&mut TcpRef(&stream), Some(&mut body), Some(&stream), false).await.is_ok());
+
+		// the file is shorter than the declared part, the response is incomplete.
+		let mut file = tokio::fs::File::open(&path).await.unwrap();
+		file.seek(std::io::SeekFrom::Start(199_000)).await.unwrap();
+		let mut body = RespBody::FilePart(file, 5000);
+		assert!(resp.msg.to(&mut TcpRef(&stream), Some(&mut body), Some(&stream), false).await.is_err());
+		drop(stream);
+
+		let received = client.await.unwrap();
+		let head = b"HTTP/1.1 200 OK\r\ncontent-length: 200000\r\n\r\n";
+		assert!(received.starts_with(head));
+		let rest = &received[head.len()..];
+		assert_eq!(&rest[..data.len()], data.as_slice());
+		let head = b"HTTP/1.1 200 OK\r\ncontent-length: 5000\r\n\r\n";
+		let rest = &rest[data.len()..];
+		assert!(rest.starts_with(head));
+		assert_eq!(&rest[head.len()..head.len() + 5000], &data[1000..6000]);
+		let rest = &rest[head.len() + 5000..];
+		assert!(rest.starts_with(head));
+		assert_eq!(&rest[head.len()..], &data[199_000..]);
+		std::fs::remove_file(&path).unwrap();
+	}
+}
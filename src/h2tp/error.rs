@@ -1,11 +1,20 @@
 use core::fmt;
-use std::fmt::Formatter;
+use std::fmt::{Formatter, Write};
+use std::io::ErrorKind;
+use crate::h2tp::headers::mime;
+use crate::h2tp::request::Request;
+use crate::h2tp::response::Response;
 use crate::h2tp::status_code::StatusCode;
+use crate::h2tp::utils::{json, xml};
 
+/// `Error` is the error of the handlers, it is a status with a message that sent to the client, the headers
+/// of the response, and an optional source that only for logging.
 pub struct Error {
 	code: StatusCode,
 	msgref: &'static str,
 	msg: Option<String>,
+	headers: Vec<(String, String)>,
+	source: Option<Box<dyn std::error::Error + Send + Sync>>,
 }
 
 impl Error {
@@ -14,6 +23,8 @@ impl Error {
 			code,
 			msg: Some(msg.to_string()),
 			msgref: "",
+			headers: vec![],
+			source: None,
 		};
 	}
 
@@ -22,11 +33,33 @@ impl Error {
 			code,
 			msgref: msg,
 			msg: None,
+			headers: vec![],
+			source: None,
 		};
 	}
 
+	/// `with_source` attach the underlying error.
+	pub fn with_source<E: std::error::Error + Send + Sync + 'static>(mut self, source: E) -> Self {
+		self.source = Some(Box::new(source));
+		return self;
+	}
+
+	/// `with_header` add a header to the response, like the `www-authenticate` of a `401`.
+	pub fn with_header(mut self, k: &str, v: &str) -> Self {
+		self.headers.push((k.to_string(), v.to_string()));
+		return self;
+	}
+
 	pub fn statuscode(&self) -> StatusCode { self.code }
 
+	/// `set_statuscode` change the status, the message is changed too if it is the reason phrase of the old status.
+	pub(crate) fn set_statuscode(&mut self, code: StatusCode) {
+		if self.msg.is_none() && self.msgref == self.code.msg() {
+			self.msgref = code.msg();
+		}
+		self.code = code;
+	}
+
 	pub fn msg(&self) -> &str {
 		return match self.msg.as_ref() {
 			Some(mref) => {
@@ -37,10 +70,134 @@ impl Error {
 			}
 		};
 	}
+
+	pub fn headers(&self) -> &[(String, String)] {
+		return &self.headers;
+	}
 }
 
 impl fmt::Debug for Error {
 	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-		write!(f, "HttpError({:?}, {})", self.code, self.msg())
+		write!(f, "HttpError({:?}, {})", self.code, self.msg())?;
+		match self.source.as_ref() {
+			Some(source) => write!(f, " <- {:?}", source),
+			None => Ok(()),
+		}
 	}
-}
\ No newline at end of file
+}
+
+impl fmt::Display for Error {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		write!(f, "{} {}", self.code.code(), self.msg())
+	}
+}
+
+impl std::error::Error for Error {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		return match self.source.as_ref() {
+			Some(v) => Some(v.as_ref()),
+			None => None,
+		};
+	}
+}
+
+/// `io_status` return the status of an io error kind, it is the mapping of `From<std::io::Error>`.
+pub(crate) fn io_status(kind: ErrorKind) -> StatusCode {
+	return match kind {
+		ErrorKind::NotFound => StatusCode::NotFound,
+		ErrorKind::PermissionDenied => StatusCode::Forbidden,
+		ErrorKind::AlreadyExists => StatusCode::Conflict,
+		ErrorKind::InvalidInput | ErrorKind::InvalidData | ErrorKind::UnexpectedEof => StatusCode::BadRequest,
+		ErrorKind::FileTooLarge => StatusCode::PayloadTooLarge,
+		ErrorKind::TimedOut => StatusCode::GatewayTimeout,
+		ErrorKind::Unsupported => StatusCode::NotImplemented,
+		_ => StatusCode::InternalServerError,
+	};
+}
+
+/// the status is chosen by the kind, the message is the reason phrase of the status, the io error is the source.
+impl From<std::io::Error> for Error {
+	fn from(e: std::io::Error) -> Self {
+		let code = io_status(e.kind());
+		return Error::newstatic(code, code.msg()).with_source(e);
+	}
+}
+
+/// `ErrorFormatter` write the body of an `Error` that returned by a handler, or passed to `Response::ioe`.
+/// the status and the headers of the error are already written.
+pub trait ErrorFormatter: Send + Sync {
+	fn format(&self, err: &Error, req: &Request, resp: &mut Response);
+}
+
+/// `DefaultErrorFormatter` write the body by the media type that the client prefers by the `accept` header:
+/// - `application/json`: `{"code":404,"message":"..."}`;
+/// - `text/html`: an error page;
+/// - otherwise the message as plain text.
+pub struct DefaultErrorFormatter;
+
+impl ErrorFormatter for DefaultErrorFormatter {
+	fn format(&self, err: &Error, req: &Request, resp: &mut Response) {
+		let preferred = match req.headers() {
+			Some(headers) => headers.preferred(&[mime::TEXT, mime::HTML, mime::JSON]),
+			None => None,
+		};
+		resp.resetbody();
+		let mut body = String::new();
+		match preferred {
+			Some(mime::JSON) => {
+				let _ = write!(body, "{{\"code\":{},\"message\":", err.code.code());
+				json::escape(&mut body, err.msg());
+				body.push('}');
+				resp.headers().content_type(mime::JSON);
+			}
+			Some(mime::HTML) => {
+				let _ = write!(
+					body,
+					"<!DOCTYPE html><html><head><title>{0} {1}</title></head><body><h1>{0} {1}</h1>",
+					err.code.code(),
+					err.code.msg()
+				);
+				if err.msg() != err.code.msg() {
+					body.push_str("<p>");
+					xml::escape(&mut body, err.msg());
+					body.push_str("</p>");
+				}
+				body.push_str("</body></html>");
+				resp.headers().content_type("text/html; charset=utf-8");
+			}
+			_ => {
+				body.push_str(err.msg());
+				resp.headers().content_type("text/plain; charset=utf-8");
+			}
+		}
+		let _ = resp.write_str(&body);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::io::ErrorKind;
+	use crate::h2tp::error::{DefaultErrorFormatter, Error, ErrorFormatter};
+	use crate::h2tp::response::IntoResponse;
+	use crate::h2tp::{Request, Response, StatusCode};
+
+	#[test]
+	fn test_error() {
+		let e = Error::from(std::io::Error::new(ErrorKind::PermissionDenied, "/etc/shadow"));
+		assert_eq!(e.to_string(), "403 Forbidden");
+		assert_eq!(std::error::Error::source(&e).unwrap().to_string(), "/etc/shadow");
+		assert_eq!(Error::from(std::io::Error::from(ErrorKind::NotFound)).statuscode().code(), 404);
+		assert_eq!(Error::from(std::io::Error::from(ErrorKind::Other)).statuscode().code(), 500);
+
+		let e = Error::newstatic(StatusCode::Unauthorized, "say \"please\"").with_header("www-authenticate", "Basic");
+		let mut req = Request::new();
+		req.msg.headers_builder().append("accept", "application/json");
+		let mut resp = Response::new();
+		e.into_response(&mut resp);
+		let e = resp.error.take().unwrap();
+		DefaultErrorFormatter.format(&e, &req, &mut resp);
+		assert_eq!(resp.msg.startline.1, "401");
+		assert_eq!(resp.msg.headers.as_ref().unwrap().getone("www-authenticate").unwrap(), "Basic");
+		assert_eq!(resp.msg.body.as_deref().unwrap(), br#"{"code":401,"message":"say \"please\""}"#);
+	}
+}
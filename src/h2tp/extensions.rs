@@ -0,0 +1,102 @@
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::fmt;
+
+/// `Extensions` is a map of values keyed by their types, it holds the server state and the per-request data
+/// that passed between the middleware and the handlers(the authenticated user, the request id ...).
+#[derive(Default)]
+pub struct Extensions {
+	map: HashMap<TypeId, Box<dyn Any + Send + Sync>>,
+}
+
+impl fmt::Debug for Extensions {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "Extensions <{} values>", self.map.len())
+	}
+}
+
+impl Extensions {
+	pub fn new() -> Self {
+		return Self::default();
+	}
+
+	/// `insert` store `v`, return the old value of the same type.
+	pub fn insert<T: Send + Sync + 'static>(&mut self, v: T) -> Option<T> {
+		return match self.map.insert(TypeId::of::<T>(), Box::new(v)) {
+			Some(old) => old.downcast::<T>().ok().map(|v| *v),
+			None => None,
+		};
+	}
+
+	pub fn get<T: Send + Sync + 'static>(&self) -> Option<&T> {
+		return match self.map.get(&TypeId::of::<T>()) {
+			Some(v) => v.downcast_ref::<T>(),
+			None => None,
+		};
+	}
+
+	pub fn get_mut<T: Send + Sync + 'static>(&mut self) -> Option<&mut T> {
+		return match self.map.get_mut(&TypeId::of::<T>()) {
+			Some(v) => v.downcast_mut::<T>(),
+			None => None,
+		};
+	}
+
+	pub fn remove<T: Send + Sync + 'static>(&mut self) -> Option<T> {
+		return match self.map.remove(&TypeId::of::<T>()) {
+			Some(v) => v.downcast::<T>().ok().map(|v| *v),
+			None => None,
+		};
+	}
+
+	pub fn contains<T: Send + Sync + 'static>(&self) -> bool {
+		return self.map.contains_key(&TypeId::of::<T>());
+	}
+
+	pub fn len(&self) -> usize {
+		return self.map.len();
+	}
+
+	pub fn is_empty(&self) -> bool {
+		return self.map.is_empty();
+	}
+
+	pub fn clear(&mut self) {
+		self.map.clear();
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::sync::Arc;
+	use crate::h2tp::extensions::Extensions;
+	use crate::h2tp::Request;
+
+	#[derive(Debug, PartialEq)]
+	struct User(&'static str);
+
+	#[test]
+	fn test_extensions() {
+		let mut ext = Extensions::new();
+		assert!(ext.insert(User("a")).is_none());
+		assert_eq!(ext.insert(User("b")).unwrap(), User("a"));
+		ext.insert(7u32);
+		assert_eq!(ext.len(), 2);
+		*ext.get_mut::<u32>().unwrap() += 1;
+		assert_eq!(*ext.get::<u32>().unwrap(), 8);
+		assert!(ext.get::<u64>().is_none());
+		assert_eq!(ext.remove::<User>().unwrap(), User("b"));
+		assert!(!ext.contains::<User>());
+
+		let mut state = Extensions::new();
+		state.insert(String::from("pool"));
+		let mut req = Request::new();
+		assert!(req.state::<String>().is_none());
+		req.state = Some(Arc::new(state));
+		req.extensions_mut().insert(User("c"));
+		assert_eq!(req.extensions().get::<User>().unwrap(), &User("c"));
+		req.clear();
+		assert!(req.extensions().is_empty());
+		assert_eq!(req.state::<String>().unwrap(), "pool");
+	}
+}
@@ -0,0 +1,402 @@
+use std::future::Future;
+use std::marker::PhantomData;
+use std::str::FromStr;
+use async_trait::async_trait;
+use serde::de::DeserializeOwned;
+use crate::h2tp::cfg::MAX_BUFFERED_BODY_SIZE;
+use crate::h2tp::error::Error;
+use crate::h2tp::handler::Handler;
+use crate::h2tp::headers::Headers;
+use crate::h2tp::request::Request;
+use crate::h2tp::response::{IntoResponse, Response};
+use crate::h2tp::status_code::StatusCode;
+
+/// `FromRequest` is a value that extracted from the request, it is the argument type of the extractor handlers.
+/// the error is written to the response and the handler is not called.
+#[async_trait]
+pub trait FromRequest: Sized + Send {
+	async fn from_request(req: &mut Request<'_>) -> Result<Self, Error>;
+}
+
+/// `Path` extract the path parameters, a scalar takes the last parameter, and a tuple takes the last parameters
+/// in order, so the parameters of the outer routers are skipped. the parse failures are `400`.
+pub struct Path<T>(pub T);
+
+/// `FromParams` is implemented for the types that `Path` can extract.
+pub trait FromParams: Sized {
+	fn from_params(params: &[(String, String)]) -> Result<Self, Error>;
+}
+
+fn parse_param<T: FromStr>(param: Option<&(String, String)>) -> Result<T, Error> {
+	return match param {
+		Some((k, v)) => match v.parse::<T>() {
+			Ok(v) => Ok(v),
+			Err(_) => Err(Error::new(StatusCode::BadRequest, format!("bad path parameter `{}`", k).as_str())),
+		},
+		None => Err(Error::newstatic(StatusCode::BadRequest, "missing path parameter")),
+	};
+}
+
+macro_rules! impl_from_params_scalar {
+	($($ty:ty),*) => {
+		$(
+			impl FromParams for $ty {
+				fn from_params(params: &[(String, String)]) -> Result<Self, Error> {
+					return parse_param(params.last());
+				}
+			}
+		)*
+	};
+}
+
+impl_from_params_scalar!(String, bool, char, u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64);
+
+macro_rules! impl_from_params_tuple {
+	($size:expr; $($ty:ident),*) => {
+		impl<$($ty: FromStr),*> FromParams for ($($ty,)*) {
+			fn from_params(params: &[(String, String)]) -> Result<Self, Error> {
+				let mut iter = params[params.len().saturating_sub($size)..].iter();
+				if params.len() < $size {
+					return Err(Error::newstatic(StatusCode::BadRequest, "missing path parameter"));
+				}
+				return Ok(($(parse_param::<$ty>(iter.next())?,)*));
+			}
+		}
+	};
+}
+
+impl_from_params_tuple!(1; A);
+impl_from_params_tuple!(2; A, B);
+impl_from_params_tuple!(3; A, B, C);
+impl_from_params_tuple!(4; A, B, C, D);
+
+#[async_trait]
+impl<T: FromParams + Send> FromRequest for Path<T> {
+	async fn from_request(req: &mut Request<'_>) -> Result<Self, Error> {
+		return Ok(Path(T::from_params(req.params())?));
+	}
+}
+
+/// `Query` deserialize the query string, a repeated key can be deserialized to a `Vec`, see `Request::query` for the errors.
+pub struct Query<T>(pub T);
+
+#[async_trait]
+impl<T: DeserializeOwned + Send> FromRequest for Query<T> {
+	async fn from_request(req: &mut Request<'_>) -> Result<Self, Error> {
+		return Ok(Query(req.query::<T>()?));
+	}
+}
+
+/// `Form` deserialize the `application/x-www-form-urlencoded` body, see `Request::form` for the errors.
+/// as a response, it serializes `T` to the body.
+pub struct Form<T>(pub T);
+
+#[async_trait]
+impl<T: DeserializeOwned + Send> FromRequest for Form<T> {
+	async fn from_request(req: &mut Request<'_>) -> Result<Self, Error> {
+		return Ok(Form(req.form_as::<T>().await?));
+	}
+}
+
+/// `Json` deserialize the `application/json` body, see `Request::json` for the errors.
+/// as a response, it serializes `T` to the body.
+#[cfg(feature = "json")]
+pub struct Json<T>(pub T);
+
+#[cfg(feature = "json")]
+#[async_trait]
+impl<T: DeserializeOwned + Send> FromRequest for Json<T> {
+	async fn from_request(req: &mut Request<'_>) -> Result<Self, Error> {
+		return Ok(Json(req.json::<T>().await?));
+	}
+}
+
+/// `State` extract a clone of the server state that stored by `Server::state`, it is a `500` if missing.
+pub struct State<T>(pub T);
+
+#[async_trait]
+impl<T: Clone + Send + Sync + 'static> FromRequest for State<T> {
+	async fn from_request(req: &mut Request<'_>) -> Result<Self, Error> {
+		return match req.state::<T>() {
+			Some(v) => Ok(State(v.clone())),
+			None => Err(Error::new(
+				StatusCode::InternalServerError,
+				format!("missing state `{}`", std::any::type_name::<T>()).as_str(),
+			)),
+		};
+	}
+}
+
+/// `Extension` extract a clone of the request extension that inserted by the middleware, it is a `500` if missing.
+pub struct Extension<T>(pub T);
+
+#[async_trait]
+impl<T: Clone + Send + Sync + 'static> FromRequest for Extension<T> {
+	async fn from_request(req: &mut Request<'_>) -> Result<Self, Error> {
+		return match req.extensions().get::<T>() {
+			Some(v) => Ok(Extension(v.clone())),
+			None => Err(Error::new(
+				StatusCode::InternalServerError,
+				format!("missing extension `{}`", std::any::type_name::<T>()).as_str(),
+			)),
+		};
+	}
+}
+
+#[async_trait]
+impl FromRequest for Headers {
+	async fn from_request(req: &mut Request<'_>) -> Result<Self, Error> {
+		return Ok(match req.headers() {
+			Some(headers) => headers.clone(),
+			None => Headers::new(),
+		});
+	}
+}
+
+/// the whole body, a `413` if it is larger than `MAX_BUFFERED_BODY_SIZE`.
+#[async_trait]
+impl FromRequest for Vec<u8> {
+	async fn from_request(req: &mut Request<'_>) -> Result<Self, Error> {
+		return req.read_body(MAX_BUFFERED_BODY_SIZE).await;
+	}
+}
+
+/// the whole body as utf-8, a `400` if it is not valid.
+#[async_trait]
+impl FromRequest for String {
+	async fn from_request(req: &mut Request<'_>) -> Result<Self, Error> {
+		return match String::from_utf8(Vec::<u8>::from_request(req).await?) {
+			Ok(v) => Ok(v),
+			Err(_) => Err(Error::newstatic(StatusCode::BadRequest, "body is not valid utf-8")),
+		};
+	}
+}
+
+/// `Option<T>` is `None` if the extraction fails.
+#[async_trait]
+impl<T: FromRequest> FromRequest for Option<T> {
+	async fn from_request(req: &mut Request<'_>) -> Result<Self, Error> {
+		return Ok(T::from_request(req).await.ok());
+	}
+}
+
+/// `Result<T, Error>` let the handler deal with the extraction error.
+#[async_trait]
+impl<T: FromRequest> FromRequest for Result<T, Error> {
+	async fn from_request(req: &mut Request<'_>) -> Result<Self, Error> {
+		return Ok(T::from_request(req).await);
+	}
+}
+
+/// `ExtractFn` is implemented for the async functions that take up to 6 `FromRequest` arguments and
+/// return an `IntoResponse`.
+#[async_trait]
+pub trait ExtractFn<Args>: Send + Sync {
+	async fn call(&self, req: &mut Request<'_>, resp: &mut Response<'_>);
+}
+
+macro_rules! impl_extract_fn {
+	($($ty:ident),*) => {
+		#[async_trait]
+		#[allow(non_snake_case, unused_variables)]
+		impl<F, Fut, $($ty),*> ExtractFn<($($ty,)*)> for F
+		where
+			F: Fn($($ty),*) -> Fut + Send + Sync,
+			Fut: Future + Send,
+			Fut::Output: IntoResponse,
+			$($ty: FromRequest,)*
+		{
+			async fn call(&self, req: &mut Request<'_>, resp: &mut Response<'_>) {
+				$(
+					let $ty = match $ty::from_request(req).await {
+						Ok(v) => v,
+						Err(e) => {
+							e.into_response(resp);
+							return;
+						}
+					};
+				)*
+				(self)($($ty),*).await.into_response(resp);
+			}
+		}
+	};
+}
+
+impl_extract_fn!();
+impl_extract_fn!(A);
+impl_extract_fn!(A, B);
+impl_extract_fn!(A, B, C);
+impl_extract_fn!(A, B, C, D);
+impl_extract_fn!(A, B, C, D, E);
+impl_extract_fn!(A, B, C, D, E, G);
+
+/// `Extract` is the handler of an extractor function, like `Extract::new(show_user)` for
+/// `async fn show_user(Path(id): Path<u64>, State(db): State<Db>) -> Result<String, Error>`.
+pub struct Extract<F, Args> {
+	f: F,
+	_args: PhantomData<fn() -> Args>,
+}
+
+impl<F: ExtractFn<Args>, Args> Extract<F, Args> {
+	pub fn new(f: F) -> Self {
+		return Self { f, _args: PhantomData };
+	}
+}
+
+#[async_trait]
+impl<F: ExtractFn<Args>, Args> Handler for Extract<F, Args> {
+	async fn handle<'a, 'c, 'h: 'a>(&'h self, req: &'a mut Request<'c>, resp: &'a mut Response<'c>) -> () {
+		self.f.call(req, resp).await
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::sync::Arc;
+	use bytes::BytesMut;
+	use crate::h2tp::extensions::Extensions;
+	use crate::h2tp::cfg::MAX_FORM_BODY_SIZE;
+	use crate::h2tp::extract::{Extension, Extract, Form, Path, Query, State};
+	use crate::h2tp::{Error, Handler, Headers, RadixRouter, Request, Response, StatusCode};
+	use serde::Deserialize;
+
+	#[derive(Clone)]
+	struct Prefix(&'static str);
+
+	async fn show(Path((uid, pid)): Path<(u32, String)>, State(prefix): State<Prefix>) -> String {
+		return format!("{}{} {}", prefix.0, uid, pid);
+	}
+
+	async fn echo(headers: Headers, user: Option<Extension<u64>>, body: String) -> Result<String, Error> {
+		if body.is_empty() {
+			return Err(Error::newstatic(StatusCode::UnprocessableEntity, "empty body"));
+		}
+		let ua = headers.getone("user-agent").cloned().unwrap_or_default();
+		return Ok(format!("{} {} {}", ua, user.map(|v| v.0).unwrap_or(0), body));
+	}
+
+	#[derive(Deserialize)]
+	struct Search {
+		q: String,
+		#[serde(default)]
+		tag: Vec<String>,
+		page: Option<u32>,
+	}
+
+	async fn search(Query(search): Query<Search>) -> String {
+		return format!("{} {:?} {}", search.q, search.tag, search.page.unwrap_or(1));
+	}
+
+	#[derive(Deserialize)]
+	struct Signup {
+		name: String,
+		#[serde(default)]
+		agree: bool,
+	}
+
+	async fn signup(Form(signup): Form<Signup>) -> String {
+		return format!("{} {}", signup.name, signup.agree);
+	}
+
+	async fn call(router: &RadixRouter, path: &str, body: &str) -> (String, String) {
+		return call_with(router, path, "", body).await;
+	}
+
+	async fn call_with(router: &RadixRouter, path: &str, content_type: &str, body: &str) -> (String, String) {
+		let mut state = Extensions::new();
+		state.insert(Prefix("#"));
+		let mut req = Request::new();
+		req.msg.startline.0.push_str("GET");
+		req.msg.startline.1.push_str(path);
+		req.msg.headers_builder().append("user-agent", "test");
+		if !content_type.is_empty() {
+			req.msg.headers_builder().append("content-type", content_type);
+		}
+		if !body.is_empty() {
+			req.msg.body = Some(BytesMut::from(body));
+		}
+		req.state = Some(Arc::new(state));
+		let mut resp = Response::new();
+		router.handle(&mut req, &mut resp).await;
+		let body = String::from_utf8_lossy(resp.msg.body.as_deref().unwrap_or_default()).to_string();
+		return (resp.msg.startline.1.clone(), body);
+	}
+
+	#[tokio::test]
+	async fn test_extract() {
+		let mut router = RadixRouter::new();
+		router
+			.register("GET", "/users/:uid/posts/:pid", Box::new(Extract::new(show)))
+			.unwrap()
+			.register("GET", "/echo", Box::new(Extract::new(echo)))
+			.unwrap()
+			.register("GET", "/search", Box::new(Extract::new(search)))
+			.unwrap()
+			.register("GET", "/signup", Box::new(Extract::new(signup)))
+			.unwrap();
+
+		assert_eq!(call(&router, "/users/7/posts/a%20b", "").await, ("".to_string(), "#7 a b".to_string()));
+		assert_eq!(call(&router, "/users/x/posts/1", "").await, ("400".to_string(), "bad path parameter `uid`".to_string()));
+		assert_eq!(call(&router, "/echo", "hi").await.1, "test 0 hi");
+		assert_eq!(call(&router, "/echo", "").await, ("422".to_string(), "empty body".to_string()));
+		assert_eq!(call(&router, "/search?q=a+b&tag=x&tag=y", "").await.1, r#"a b ["x", "y"] 1"#);
+		assert_eq!(
+			call(&router, "/search?page=x&q=", "").await,
+			("422".to_string(), "bad query: invalid value `x` for `page`".to_string())
+		);
+		assert_eq!(call(&router, "/search?page=2", "").await, ("422".to_string(), "bad query: missing field `q`".to_string()));
+
+		let form = "application/x-www-form-urlencoded";
+		assert_eq!(call_with(&router, "/signup", form, "name=a+b&agree=on").await.1, "a b true");
+		assert_eq!(call_with(&router, "/signup", "application/x-www-form-urlencoded; charset=ISO-8859-1", "name=%E9").await.1, "é false");
+		assert_eq!(call_with(&router, "/signup", "text/plain", "name=a").await.0, "415");
+		assert_eq!(call_with(&router, "/signup", "application/x-www-form-urlencoded; charset=koi8-r", "name=a").await.0, "415");
+		assert_eq!(
+			call_with(&router, "/signup", form, "agree=1").await,
+			("422".to_string(), "bad form: missing field `name`".to_string())
+		);
+		assert_eq!(
+			call_with(&router, "/signup", form, "name=%z1&agree=on").await,
+			("422".to_string(), "bad form: missing field `name`".to_string())
+		);
+		let large = format!("name={}", "a".repeat(MAX_FORM_BODY_SIZE));
+		assert_eq!(call_with(&router, "/signup", form, &large).await.0, "413");
+	}
+
+	#[cfg(feature = "json")]
+	#[tokio::test]
+	async fn test_extract_json() {
+		use crate::h2tp::extract::Json;
+
+		#[derive(serde::Deserialize, serde::Serialize)]
+		struct User {
+			name: String,
+			age: u8,
+		}
+
+		async fn create(Json(mut user): Json<User>) -> (StatusCode, Json<User>) {
+			user.age += 1;
+			return (StatusCode::Created, Json(user));
+		}
+
+		let mut router = RadixRouter::new();
+		router.register("GET", "/users", Box::new(Extract::new(create))).unwrap();
+
+		let json = "application/json; charset=utf-8";
+		assert_eq!(
+			call_with(&router, "/users", json, r#"{"name":"a","age":7}"#).await,
+			("201".to_string(), r#"{"name":"a","age":8}"#.to_string())
+		);
+		assert_eq!(call_with(&router, "/users", "application/vnd.api+json", r#"{"name":"a","age":1}"#).await.0, "201");
+		assert_eq!(call_with(&router, "/users", "text/plain", r#"{"name":"a","age":7}"#).await.0, "415");
+		assert_eq!(
+			call_with(&router, "/users", json, "{\n\"name\": 1}").await,
+			("422".to_string(), "bad json: invalid type: integer `1`, expected a string at line 2 column 9".to_string())
+		);
+		assert_eq!(call_with(&router, "/users", json, r#"{"name":"a"}"#).await.0, "422");
+		assert_eq!(call_with(&router, "/users", json, r#"{"name":"a","#).await.0, "400");
+		assert_eq!(call_with(&router, "/users", json, r#"{"name" "a"}"#).await.0, "400");
+		let large = format!(r#"{{"name":"{}","age":1}}"#, "a".repeat(crate::h2tp::cfg::MAX_BUFFERED_BODY_SIZE));
+		assert_eq!(call_with(&router, "/users", json, &large).await.0, "413");
+	}
+}
@@ -0,0 +1,187 @@
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind};
+use std::time::{Duration, UNIX_EPOCH};
+use async_trait::async_trait;
+use crate::h2tp::fs::read::{clean, strip_prefix, Meta, Readable};
+use crate::h2tp::handler::Handler;
+use crate::h2tp::headers::mime;
+use crate::h2tp::response::RespBody;
+use crate::h2tp::{Request, Response};
+
+/// `EmbeddedFile` is a file compiled into the binary, usually made by the `embed_dir!` macro.
+pub struct EmbeddedFile {
+	path: &'static str,
+	data: &'static [u8],
+	etag: &'static str,
+	mime: &'static str,
+	modified: u64,
+}
+
+impl EmbeddedFile {
+	/// `path` is relative to the embedded directory and separated by `/`, `modified` is the unix timestamp in seconds.
+	pub fn new(path: &'static str, data: &'static [u8], etag: &'static str, modified: u64) -> Self {
+		return Self {
+			path,
+			data,
+			etag,
+			mime: mime::by_path(path),
+			modified,
+		};
+	}
+
+	fn meta(&self) -> Meta {
+		return Meta {
+			size: self.data.len() as u64,
+			modified: Some(UNIX_EPOCH + Duration::from_secs(self.modified)),
+			is_dir: false,
+			etag: Some(self.etag.to_string()),
+		};
+	}
+}
+
+/// `EmbeddedReader` serves the files that compiled into the binary, like `EmbeddedReader::new("/static", embed_dir!("dist"))`.
+pub struct EmbeddedReader {
+	prefix: String,
+	files: HashMap<&'static str, EmbeddedFile>,
+	precompressed: bool,
+}
+
+impl EmbeddedReader {
+	/// `new` create a reader that serves `files`, the `prefix` will be stripped from the request path.
+	pub fn new(prefix: &str, files: Vec<EmbeddedFile>) -> Self {
+		let mut map = HashMap::with_capacity(files.len());
+		for file in files {
+			map.insert(file.path, file);
+		}
+		return Self {
+			prefix: prefix.trim_end_matches('/').to_string(),
+			files: map,
+			precompressed: true,
+		};
+	}
+
+	pub fn set_precompressed(&mut self, v: bool) -> &mut Self {
+		self.precompressed = v;
+		return self;
+	}
+
+	fn is_dir(&self, path: &str) -> bool {
+		if path.is_empty() {
+			return true;
+		}
+		return self.files.keys().any(|k| k.len() > path.len() && k.starts_with(path) && k.as_bytes()[path.len()] == b'/');
+	}
+}
+
+#[async_trait]
+impl Handler for EmbeddedReader {
+	async fn handle<'a, 'c, 'h: 'a>(&'h self, req: &'a mut Request<'c>, resp: &'a mut Response<'c>) -> () {
+		Readable::handle(self, req, resp).await
+	}
+}
+
+#[async_trait]
+impl Readable for EmbeddedReader {
+	async fn meta(&self, req: &Request) -> Result<(String, Meta), Error> {
+		let path = match strip_prefix(&self.prefix, req.path()) {
+			Some(v) => v,
+			None => {
+				return Err(Error::new(ErrorKind::NotFound, ""));
+			}
+		};
+		let path = match clean(path) {
+			Some(segs) => segs.join("/"),
+			None => {
+				return Err(Error::new(ErrorKind::PermissionDenied, ""));
+			}
+		};
+		let meta = self.stat(&path).await?;
+		return Ok((path, meta));
+	}
+
+	async fn stat(&self, path: &str) -> Result<Meta, Error> {
+		match self.files.get(path) {
+			Some(file) => {
+				return Ok(file.meta());
+			}
+			None => {}
+		}
+		if self.is_dir(path) {
+			return Ok(Meta {
+				size: 0,
+				modified: None,
+				is_dir: true,
+				etag: None,
+			});
+		}
+		return Err(Error::new(ErrorKind::NotFound, ""));
+	}
+
+	async fn open(&self, path: &str, _meta: &Meta, range: Option<(u64, u64)>) -> Result<RespBody, Error> {
+		return match self.files.get(path) {
+			Some(file) => match range {
+				Some((begin, end)) => Ok(RespBody::Static(&file.data[begin as usize..end as usize + 1])),
+				None => Ok(RespBody::Static(file.data)),
+			},
+			None => Err(Error::new(ErrorKind::NotFound, "")),
+		};
+	}
+
+	fn mime(&self, path: &str) -> &'static str {
+		return match self.files.get(path) {
+			Some(file) => file.mime,
+			None => mime::by_path(path),
+		};
+	}
+
+	fn precompressed(&self) -> bool {
+		return self.precompressed;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::h2tp::fs::embed::EmbeddedReader;
+	use crate::h2tp::fs::read::Readable;
+	use crate::h2tp::response::RespBody;
+	use crate::h2tp::{hns, Request, Response};
+
+	#[tokio::test]
+	async fn test_embedded() {
+		let reader = EmbeddedReader::new("/gen", crate::h2tp::embed_dir!(r"gen", crate = "crate"));
+
+		let mut req = Request::new();
+		req.msg.startline.1.push_str("/gen/./status_code.jinja2?v=1");
+		let mut resp = Response::new();
+		Readable::handle(&reader, &mut req, &mut resp).await;
+		let etag = resp.msg.headers.as_ref().unwrap().m.getone(hns::E_TAG).unwrap().clone();
+		match resp.body {
+			Some(RespBody::Static(data)) => {
+				assert_eq!(data, include_bytes!("../../../gen/status_code.jinja2"));
+			}
+			_ => unreachable!(),
+		}
+
+		let mut req = Request::new();
+		req.msg.startline.1.push_str("/gen/status_code.jinja2");
+		req.msg.headers_builder().append(hns::IF_NONE_MATCH, &etag);
+		let mut resp = Response::new();
+		Readable::handle(&reader, &mut req, &mut resp).await;
+		assert_eq!(resp.msg.startline.1, "304");
+
+		let mut req = Request::new();
+		req.msg.startline.1.push_str("/gen/base.py");
+		req.msg.headers_builder().append(hns::RANGE, "bytes=0-1");
+		let mut resp = Response::new();
+		Readable::handle(&reader, &mut req, &mut resp).await;
+		assert_eq!(resp.msg.startline.1, "206");
+		assert!(matches!(resp.body, Some(RespBody::Static(data)) if data.len() == 2));
+
+		let mut req = Request::new();
+		req.msg.startline.1.push_str("/genbase.py");
+		let mut resp = Response::new();
+		Readable::handle(&reader, &mut req, &mut resp).await;
+		assert_eq!(resp.msg.startline.1, "404");
+		assert!(resp.body.is_none());
+	}
+}
@@ -1,2 +1,5 @@
-mod read;
+pub mod read;
+pub mod embed;
+pub mod webdav;
 
+pub mod upload;
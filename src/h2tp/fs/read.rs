@@ -1,31 +1,74 @@
-use std::fs::Metadata;
-use std::io::{Error, ErrorKind};
+use std::io::{Error, ErrorKind, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
 use async_trait::async_trait;
+use tokio::io::AsyncSeekExt;
+use crate::h2tp::error;
 use crate::h2tp::handler::Handler;
-use crate::h2tp::{Request, Response};
-use crate::h2tp::response::RespBody;
+use crate::h2tp::{hns, Request, Response};
+use crate::h2tp::headers::mime;
+use crate::h2tp::response::{IntoResponse, RespBody};
+use crate::h2tp::status_code::StatusCode;
+use crate::h2tp::utils::uricoding;
 
 pub enum ReadResult {
 	File(String),
 	Dir(String),
 }
 
+/// content-codings and the suffixes of their sidecar files, in preference order.
+pub const PRECOMPRESSED_SIDECARS: [(&str, &str); 2] = [("br", ".br"), ("gzip", ".gz")];
+
+/// `Meta` is the metadata of a file, it does not care about where the file stored.
+pub struct Meta {
+	pub size: u64,
+	pub modified: Option<SystemTime>,
+	pub is_dir: bool,
+	/// the precomputed entity-tag, if `None`, it will be made from `size` and `modified`.
+	pub etag: Option<String>,
+}
+
+impl From<std::fs::Metadata> for Meta {
+	fn from(v: std::fs::Metadata) -> Self {
+		return Self {
+			size: v.len(),
+			modified: v.modified().ok(),
+			is_dir: v.is_dir(),
+			etag: None,
+		};
+	}
+}
+
 #[async_trait]
 pub trait Readable: Handler {
-	async fn meta(&self, req: &Request) -> Result<(String, Metadata), std::io::Error>;
+	/// `meta` find the file for the request, return its path and metadata.
+	async fn meta(&self, req: &Request) -> Result<(String, Meta), Error>;
+
+	/// `stat` return the metadata of the file at `path`.
+	async fn stat(&self, path: &str) -> Result<Meta, Error>;
+
+	/// `open` return the body of the file at `path`, `range` is the inclusive bounds of the bytes to send.
+	async fn open(&self, path: &str, meta: &Meta, range: Option<(u64, u64)>) -> Result<RespBody, Error>;
+
+	/// `mime` return the content-type of the file at `path`.
+	fn mime(&self, path: &str) -> &'static str {
+		return mime::by_path(path);
+	}
+
+	/// `precompressed` return whether to look for the sidecar files(`app.js.br`, `app.js.gz`)
+	/// of a file and send them instead when the `accept-encoding` of the request allows it.
+	fn precompressed(&self) -> bool {
+		return false;
+	}
 
 	async fn handle<'a, 'c, 'h: 'a>(&'h self, req: &'a mut Request<'c>, resp: &'a mut Response<'c>) {
 		match self.meta(req).await {
 			Ok((ref path, ref meta, )) => {
-				if meta.is_file() {
-					self.send_file(path, meta, req, resp).await;
-					return;
-				}
-				if meta.is_dir() {
+				if meta.is_dir {
 					self.render_dir(path, meta, req, resp).await;
 					return;
 				}
-				resp.ioe(std::io::Error::new(ErrorKind::NotFound, ""));
+				self.send_file(path, meta, req, resp).await;
 			}
 			Err(e) => {
 				resp.ioe(e);
@@ -33,54 +76,264 @@ pub trait Readable: Handler {
 		}
 	}
 
-	async fn render_dir<'a, 'm>(&self, path: &'m String, metadate: &'m Metadata, req: &'a Request, resp: &'a mut Response) {
-		if cached(metadate, req, resp) {
-			return;
+	/// `render_dir` respond the directory at `path`, the listing is not supported by default, so it is a `403`.
+	async fn render_dir<'a, 'm>(&self, _path: &'m str, _metadate: &'m Meta, _req: &'a Request, resp: &'a mut Response) {
+		error::Error::newstatic(StatusCode::Forbidden, "directory listing is not allowed").into_response(resp);
+	}
+
+	/// `find_precompressed` return the path, metadata and content-coding of the most preferred sidecar file
+	/// of `path` that accepted by the request. sidecar files older than the original file are ignored.
+	async fn find_precompressed<'a, 'm>(&self, path: &'m str, metadate: &'m Meta, req: &'a Request) -> Option<(String, Meta, &'static str)> {
+		let headers = req.headers()?;
+		for (encoding, suffix) in PRECOMPRESSED_SIDECARS {
+			if !headers.accepts_encoding(encoding) {
+				continue;
+			}
+			let sidecar = format!("{path}{suffix}");
+			match self.stat(&sidecar).await {
+				Ok(meta) => {
+					if meta.is_dir {
+						continue;
+					}
+					match (meta.modified, metadate.modified) {
+						(Some(sm), Some(om)) if sm < om => {
+							continue;
+						}
+						_ => {}
+					}
+					return Some((sidecar, meta, encoding));
+				}
+				Err(_) => {}
+			}
 		}
-		println!("{path}")
+		return None;
 	}
 
-	async fn send_file<'a, 'm>(&self, path: &'m String, metadate: &'m Metadata, req: &'a Request, resp: &'a mut Response) {
-		if req.headers().is_some() && cached(metadate, req, resp) {
+	async fn send_file<'a, 'm>(&self, path: &'m str, metadate: &'m Meta, req: &'a Request, resp: &'a mut Response) {
+		let mut target: Option<(String, Meta, &'static str)> = None;
+		if self.precompressed() {
+			target = self.find_precompressed(path, metadate, req).await;
+			resp.headers().reset(hns::VARY, hns::ACCEPT_ENCODING);
+		}
+
+		let (fpath, fmeta, encoding) = match target.as_ref() {
+			Some((p, m, e)) => (p.as_str(), m, Some(*e)),
+			None => (path, metadate, None),
+		};
+
+		let etag = etag(fmeta, encoding);
+		{
+			let mut headers = resp.headers();
+			headers.content_type(self.mime(path));
+			headers.reset(hns::E_TAG, etag.as_str());
+			headers.reset(hns::ACCEPT_RANGE, "bytes");
+			match encoding {
+				Some(encoding) => {
+					headers.reset(hns::CONTENT_ENCODING, encoding);
+				}
+				None => {}
+			}
+		}
+
+		if cached(etag.as_str(), req, resp) {
 			return;
 		}
 
-		let result = tokio::fs::File::open(path).await;
-		match result {
-			Ok(file) => {
-				let range: Option<(String, usize, usize)>;
-				match req.headers() {
-					Some(headers) => {
-						range = headers.range();
-					}
-					None => {
-						range = None;
+		let mut range: Option<(u64, u64)> = None;
+		match req.headers() {
+			Some(headers) => match headers.range() {
+				Some((unit, begin, end)) => {
+					let if_range_ok = match headers.if_range() {
+						Some(v) => v.as_str() == etag.as_str(),
+						None => true,
+					};
+					if unit == "bytes" && if_range_ok {
+						match satisfiable(fmeta.size, begin, end) {
+							Some(v) => {
+								range = Some(v);
+							}
+							None => {
+								resp.status(StatusCode::RangeNotSatisfiable);
+								resp.headers().reset(hns::CONTENT_RANGE, format!("bytes */{}", fmeta.size).as_str());
+								return;
+							}
+						}
 					}
 				}
+				None => {}
+			},
+			None => {}
+		}
+
+		match self.open(fpath, fmeta, range).await {
+			Ok(body) => {
 				match range {
-					None => {
-						resp.body = Some(RespBody::File(file));
-					}
-					Some(range) => {
-						println!("{:?}", range);
+					Some((begin, end)) => {
+						resp.status(StatusCode::PartialContent);
+						resp.headers().reset(hns::CONTENT_RANGE, format!("bytes {}-{}/{}", begin, end, fmeta.size).as_str());
 					}
+					None => {}
 				}
+				resp.body = Some(body);
 			}
 			Err(e) => {
 				resp.ioe(e);
-				return;
 			}
 		}
 	}
 }
 
-pub fn cached<'a, 'm>(metadate: &'m Metadata, req: &'a Request, resp: &'a mut Response) -> bool {
-	println!("{:?} {:?} {:p}", metadate, req, resp);
-	return false;
+/// `satisfiable` resolve the bounds of a byte range against the file `size`, return the inclusive bounds.
+fn satisfiable(size: u64, begin: Option<u64>, end: Option<u64>) -> Option<(u64, u64)> {
+	if size == 0 {
+		return None;
+	}
+	return match (begin, end) {
+		(Some(begin), end) => {
+			if begin >= size {
+				return None;
+			}
+			let end = match end {
+				Some(end) if end < size => end,
+				_ => size - 1,
+			};
+			if end < begin {
+				return None;
+			}
+			Some((begin, end))
+		}
+		(None, Some(suffix)) => {
+			if suffix == 0 {
+				return None;
+			}
+			Some((size.saturating_sub(suffix), size - 1))
+		}
+		(None, None) => None,
+	};
+}
+
+/// `etag` make a strong entity-tag from the size and modified time of a file,
+/// the content-coding is a part of the tag, because the representations are different.
+pub fn etag(metadate: &Meta, encoding: Option<&str>) -> String {
+	match metadate.etag.as_ref() {
+		Some(v) => {
+			return v.clone();
+		}
+		None => {}
+	}
+	let mtime = match metadate.modified {
+		Some(t) => match t.duration_since(UNIX_EPOCH) {
+			Ok(d) => d.as_nanos(),
+			Err(_) => 0,
+		},
+		None => 0,
+	};
+	return match encoding {
+		Some(encoding) => format!("\"{:x}-{:x}-{}\"", mtime, metadate.size, encoding),
+		None => format!("\"{:x}-{:x}\"", mtime, metadate.size),
+	};
+}
+
+/// `cached` compare the `etag` with the `if-none-match` of the request,
+/// if matched, the response status will be set to `304 Not Modified`.
+pub fn cached<'a>(etag: &str, req: &'a Request, resp: &'a mut Response) -> bool {
+	let matched = match req.headers() {
+		Some(headers) => match headers.if_none_match() {
+			Some(inm) => inm.split(',').any(|item| {
+				let item = item.trim();
+				return item == "*" || item.trim_start_matches("W/") == etag.trim_start_matches("W/");
+			}),
+			None => false,
+		},
+		None => false,
+	};
+	if matched {
+		resp.status(StatusCode::NotModified);
+	}
+	return matched;
+}
+
+/// `clean` decode the request path and split it into segments, the query and fragment are dropped.
+/// return `None` if the path is not valid or try to escape from the root.
+pub(crate) fn clean(reqpath: &str) -> Option<Vec<String>> {
+	let mut reqpath = reqpath;
+	match reqpath.find(['?', '#']) {
+		Some(idx) => {
+			reqpath = &reqpath[..idx];
+		}
+		None => {}
+	}
+
+	let mut buf: Vec<u8> = Vec::with_capacity(reqpath.len());
+	if !uricoding::decode_uri(&mut buf, reqpath) {
+		return None;
+	}
+	let decoded = std::str::from_utf8(&buf).ok()?;
+
+	let mut segs = vec![];
+	for seg in decoded.split('/') {
+		match seg {
+			"" | "." => {}
+			".." => {
+				return None;
+			}
+			_ => {
+				if seg.contains('\\') || seg.contains('\0') {
+					return None;
+				}
+				segs.push(seg.to_string());
+			}
+		}
+	}
+	return Some(segs);
+}
+
+/// `confine` join the request path to `root`, see `clean`.
+pub(crate) fn confine(root: &Path, reqpath: &str) -> Option<PathBuf> {
+	let mut path = root.to_path_buf();
+	for seg in clean(reqpath)? {
+		path.push(seg);
+	}
+	return Some(path);
+}
+
+/// `strip_prefix` return the rest of the request path after `prefix`, `None` if the path is not under the prefix.
+pub(crate) fn strip_prefix<'a>(prefix: &str, reqpath: &'a str) -> Option<&'a str> {
+	let rest = reqpath.strip_prefix(prefix)?;
+	if !rest.is_empty() && !rest.starts_with(['/', '?', '#']) {
+		return None;
+	}
+	return Some(rest);
+}
+
+/// `key_of` return the path relative to `root` of a path that returned by `confine`, the segments are joined by `/`.
+pub(crate) fn key_of(root: &Path, path: &Path) -> String {
+	return match path.strip_prefix(root) {
+		Ok(rel) => rel.iter().map(|seg| seg.to_string_lossy()).collect::<Vec<_>>().join("/"),
+		Err(_) => String::new(),
+	};
 }
 
 pub struct SimpleOsReader {
-	root: String,
+	root: PathBuf,
+	prefix: String,
+	precompressed: bool,
+}
+
+impl SimpleOsReader {
+	/// `new` create a reader that serves the files under `root`, the `prefix` will be stripped from the request path.
+	pub fn new(root: &str, prefix: &str) -> Self {
+		return Self {
+			root: PathBuf::from(root),
+			prefix: prefix.trim_end_matches('/').to_string(),
+			precompressed: true,
+		};
+	}
+
+	pub fn set_precompressed(&mut self, v: bool) -> &mut Self {
+		self.precompressed = v;
+		return self;
+	}
 }
 
 #[async_trait]
@@ -92,7 +345,132 @@ impl Handler for SimpleOsReader {
 
 #[async_trait]
 impl Readable for SimpleOsReader {
-	async fn meta(&self, req: &Request) -> Result<(String, Metadata), Error> {
-		todo!()
+	async fn meta(&self, req: &Request) -> Result<(String, Meta), Error> {
+		let path = match strip_prefix(&self.prefix, req.path()) {
+			Some(v) => v,
+			None => {
+				return Err(Error::new(ErrorKind::NotFound, ""));
+			}
+		};
+		let path = match confine(&self.root, path) {
+			Some(v) => v,
+			None => {
+				return Err(Error::new(ErrorKind::PermissionDenied, ""));
+			}
+		};
+		let meta = tokio::fs::metadata(&path).await?;
+		return match path.into_os_string().into_string() {
+			Ok(path) => Ok((path, Meta::from(meta))),
+			Err(_) => Err(Error::new(ErrorKind::InvalidData, "")),
+		};
+	}
+
+	async fn stat(&self, path: &str) -> Result<Meta, Error> {
+		return Ok(Meta::from(tokio::fs::metadata(path).await?));
+	}
+
+	async fn open(&self, path: &str, _meta: &Meta, range: Option<(u64, u64)>) -> Result<RespBody, Error> {
+		let mut file = tokio::fs::File::open(path).await?;
+		return match range {
+			Some((begin, end)) => {
+				file.seek(SeekFrom::Start(begin)).await?;
+				Ok(RespBody::FilePart(file, end - begin + 1))
+			}
+			None => Ok(RespBody::File(file)),
+		};
+	}
+
+	fn precompressed(&self) -> bool {
+		return self.precompressed;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::path::Path;
+	use crate::h2tp::fs::read::{confine, key_of, strip_prefix, Readable, SimpleOsReader};
+	use crate::h2tp::{hns, Request, Response};
+	use crate::h2tp::response::RespBody;
+
+	#[test]
+	fn test_confine() {
+		let root = Path::new("/srv");
+		assert_eq!(confine(root, "/a/./b.js?v=1"), Some(root.join("a").join("b.js")));
+		assert_eq!(confine(root, "/a%20b"), Some(root.join("a b")));
+		assert_eq!(confine(root, "/a/../../etc/passwd"), None);
+		assert_eq!(confine(root, "/%2e%2e/etc/passwd"), None);
+
+		assert_eq!(strip_prefix("/static", "/static/a.js"), Some("/a.js"));
+		assert_eq!(strip_prefix("/static", "/static?v=1"), Some("?v=1"));
+		assert_eq!(strip_prefix("/static", "/staticx/a.js"), None);
+		let path = confine(root, "/a/b%20c").unwrap();
+		assert_eq!(key_of(root, &path), "a/b c");
+		assert_eq!(key_of(root, root), "");
+	}
+
+	#[tokio::test]
+	async fn test_precompressed() {
+		let root = std::env::temp_dir().join(format!("spk-read-{}", std::process::id()));
+		std::fs::create_dir_all(&root).unwrap();
+		std::fs::write(root.join("app.js"), "console.log(1)").unwrap();
+		std::fs::write(root.join("app.js.gz"), "gz").unwrap();
+
+		let reader = SimpleOsReader::new(root.to_str().unwrap(), "/static");
+
+		let mut req = Request::new();
+		req.msg.startline.1.push_str("/static/app.js");
+		req.msg.headers_builder().append(hns::ACCEPT_ENCODING, "br, gzip;q=0.8");
+		let mut resp = Response::new();
+		Readable::handle(&reader, &mut req, &mut resp).await;
+
+		let headers = resp.msg.headers.as_ref().unwrap();
+		assert_eq!(headers.m.getone(hns::CONTENT_ENCODING).unwrap(), "gzip");
+		assert_eq!(headers.m.getone(hns::CONTENT_TYPE).unwrap(), "text/javascript");
+		assert_eq!(headers.m.getone(hns::VARY).unwrap(), hns::ACCEPT_ENCODING);
+		assert!(matches!(resp.body, Some(RespBody::File(_))));
+		let etag = headers.m.getone(hns::E_TAG).unwrap().clone();
+
+		let mut req = Request::new();
+		req.msg.startline.1.push_str("/static/app.js");
+		req.msg.headers_builder().append(hns::ACCEPT_ENCODING, "gzip;q=0");
+		let mut resp = Response::new();
+		Readable::handle(&reader, &mut req, &mut resp).await;
+
+		let headers = resp.msg.headers.as_ref().unwrap();
+		assert!(headers.m.getone(hns::CONTENT_ENCODING).is_none());
+		assert_ne!(headers.m.getone(hns::E_TAG).unwrap(), &etag);
+
+		let mut req = Request::new();
+		req.msg.startline.1.push_str("/static/app.js");
+		req.msg.headers_builder().append(hns::ACCEPT_ENCODING, "gzip").append(hns::IF_NONE_MATCH, &etag);
+		let mut resp = Response::new();
+		Readable::handle(&reader, &mut req, &mut resp).await;
+		assert_eq!(resp.msg.startline.1, "304");
+		assert!(resp.body.is_none());
+
+		let mut req = Request::new();
+		req.msg.startline.1.push_str("/static/app.js");
+		req.msg.headers_builder().append(hns::RANGE, "bytes=-3");
+		let mut resp = Response::new();
+		Readable::handle(&reader, &mut req, &mut resp).await;
+		assert_eq!(resp.msg.startline.1, "206");
+		assert_eq!(resp.msg.headers.as_ref().unwrap().m.getone(hns::CONTENT_RANGE).unwrap(), "bytes 11-13/14");
+		assert!(matches!(resp.body, Some(RespBody::FilePart(_, 3))));
+
+		let mut req = Request::new();
+		req.msg.startline.1.push_str("/static/app.js");
+		req.msg.headers_builder().append(hns::RANGE, "bytes=14-");
+		let mut resp = Response::new();
+		Readable::handle(&reader, &mut req, &mut resp).await;
+		assert_eq!(resp.msg.startline.1, "416");
+
+		let mut req = Request::new();
+		req.msg.startline.1.push_str("/static/");
+		let mut resp = Response::new();
+		Readable::handle(&reader, &mut req, &mut resp).await;
+		assert_eq!(resp.msg.startline.1, "403");
+		assert!(resp.body.is_none());
+
+		std::fs::remove_dir_all(&root).unwrap();
 	}
 }
@@ -0,0 +1,454 @@
+use std::fmt::Write;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use async_trait::async_trait;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use crate::h2tp::error;
+use crate::h2tp::fs::read::{confine, key_of, strip_prefix};
+use crate::h2tp::handler::Handler;
+use crate::h2tp::multipart::{boundary, Multipart};
+use crate::h2tp::response::IntoResponse;
+use crate::h2tp::status_code::StatusCode;
+use crate::h2tp::utils::sha256::Sha256;
+use crate::h2tp::utils::{json, uricoding};
+use crate::h2tp::{hns, methods, Request, Response};
+
+const ALLOW: &str = "PUT, POST";
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// `Uploader` stores the uploaded files under a directory, `PUT /prefix/a/b.txt` stores the body to `a/b.txt`,
+/// `POST /prefix/a` with a multipart/form-data body stores every file part to `a/<filename>`.
+/// the files are written to temporary files first, then fsynced and renamed into place, so the readers
+/// never see the partial files. the response is a json like `{"path":"/prefix/a/b.txt","size":5,"sha256":"..."}`,
+/// an array of them for the multipart uploads.
+/// enable `Server::defer_body`, so the large uploads are streamed to disk instead of being buffered in memory.
+pub struct Uploader {
+	root: PathBuf,
+	prefix: String,
+	max_size: u64,
+	exts: Option<Vec<String>>,
+}
+
+/// `Stored` is a file that written to the temporary file and not renamed yet.
+struct Stored {
+	tmp: PathBuf,
+	dest: PathBuf,
+	key: String,
+	size: u64,
+	digest: String,
+}
+
+/// `io_error` convert the io error like `h2tp::Error::from`, except that a missing parent directory is a `409`.
+fn io_error(e: Error) -> error::Error {
+	let code = match e.kind() {
+		ErrorKind::NotFound => StatusCode::Conflict,
+		kind => error::io_status(kind),
+	};
+	return error::Error::newstatic(code, code.msg()).with_source(e);
+}
+
+fn not_allowed() -> error::Error {
+	return error::Error::newstatic(StatusCode::MethodNotAllowed, StatusCode::MethodNotAllowed.msg())
+		.with_header(hns::ALLOW, ALLOW);
+}
+
+/// `tmp_name` return a unique hidden name for the temporary file that renamed into place later.
+pub(crate) fn tmp_name() -> String {
+	static COUNTER: AtomicU64 = AtomicU64::new(0);
+	return format!(".upload-{}-{}.tmp", std::process::id(), COUNTER.fetch_add(1, Ordering::Relaxed));
+}
+
+/// `basename` return the last segment of a client file name, `None` if it is empty or hidden.
+fn basename(filename: &str) -> Option<&str> {
+	let name = filename.rsplit(['/', '\\']).next()?;
+	if name.is_empty() || name.starts_with('.') || name.chars().any(|c| c.is_control()) {
+		return None;
+	}
+	return Some(name);
+}
+
+pub(crate) async fn sync_dir(dir: &Path) {
+	// fsync the directory so the rename is durable, not supported on some platforms.
+	match tokio::fs::File::open(dir).await {
+		Ok(f) => {
+			let _ = f.sync_all().await;
+		}
+		Err(_) => {}
+	}
+}
+
+impl Uploader {
+	/// `new` create an uploader that stores the files under `root`, the `prefix` will be stripped from the request path.
+	pub fn new(root: &str, prefix: &str) -> Self {
+		return Self {
+			root: PathBuf::from(root),
+			prefix: prefix.trim_end_matches('/').to_string(),
+			max_size: 32 * 1024 * 1024,
+			exts: None,
+		};
+	}
+
+	/// `set_max_size` set the max size of a single file in bytes, the default is 32MiB.
+	pub fn set_max_size(&mut self, v: u64) -> &mut Self {
+		self.max_size = v;
+		return self;
+	}
+
+	/// `set_exts` only accept the files with these extensions, like `&["png", "jpg"]`, case insensitive.
+	pub fn set_exts(&mut self, exts: &[&str]) -> &mut Self {
+		self.exts = Some(exts.iter().map(|e| e.trim_start_matches('.').to_ascii_lowercase()).collect());
+		return self;
+	}
+
+	fn too_large(&self) -> error::Error {
+		let msg = format!("file is larger than {} bytes", self.max_size);
+		return error::Error::new(StatusCode::PayloadTooLarge, msg.as_str());
+	}
+
+	fn unsupported(name: &str) -> error::Error {
+		let msg = format!("file type of `{}` is not allowed", name);
+		return error::Error::new(StatusCode::UnsupportedMediaType, msg.as_str());
+	}
+
+	fn bad_name(name: &str) -> error::Error {
+		return error::Error::new(StatusCode::BadRequest, format!("bad file name `{}`", name).as_str());
+	}
+
+	fn allowed(&self, name: &str) -> bool {
+		return match self.exts.as_ref() {
+			Some(exts) => match name.rsplit_once('.') {
+				Some((_, ext)) => exts.iter().any(|e| e.eq_ignore_ascii_case(ext)),
+				None => false,
+			},
+			None => true,
+		};
+	}
+
+	/// `resolve` return the key relative to the root of the request path, `None` if it is outside the root.
+	fn resolve(&self, reqpath: &str) -> Option<String> {
+		let path = confine(&self.root, strip_prefix(&self.prefix, reqpath)?)?;
+		return Some(key_of(&self.root, &path));
+	}
+
+	fn path_of(&self, key: &str) -> PathBuf {
+		let mut path = self.root.clone();
+		for seg in key.split('/').filter(|s| !s.is_empty()) {
+			path.push(seg);
+		}
+		return path;
+	}
+
+	/// `store` write the source to a temporary file next to `key`, fail if it is larger than `max_size`.
+	async fn store<R: AsyncRead + Unpin>(&self, key: String, source: &mut R) -> Result<Stored, error::Error> {
+		let dest = self.path_of(&key);
+		let dir = match dest.parent() {
+			Some(v) => v.to_path_buf(),
+			None => {
+				return Err(error::Error::newstatic(StatusCode::Forbidden, "can not write to the root"));
+			}
+		};
+		match tokio::fs::metadata(&dest).await {
+			Ok(meta) if meta.is_dir() => {
+				return Err(error::Error::new(StatusCode::Conflict, format!("`{}` is a directory", key).as_str()));
+			}
+			_ => {}
+		}
+
+		let tmp = dir.join(tmp_name());
+		let mut file = match tokio::fs::OpenOptions::new().write(true).create_new(true).open(&tmp).await {
+			Ok(f) => f,
+			Err(e) => {
+				return Err(io_error(e));
+			}
+		};
+
+		let mut hasher = Sha256::new();
+		let mut size: u64 = 0;
+		let mut buf = vec![0u8; CHUNK_SIZE];
+		let result: Result<(), error::Error> = async {
+			loop {
+				let n = source.read(&mut buf).await.map_err(io_error)?;
+				if n == 0 {
+					break;
+				}
+				size += n as u64;
+				if size > self.max_size {
+					return Err(self.too_large());
+				}
+				hasher.update(&buf[..n]);
+				file.write_all(&buf[..n]).await.map_err(io_error)?;
+			}
+			file.sync_all().await.map_err(io_error)?;
+			return Ok(());
+		}
+		.await;
+		drop(file);
+
+		match result {
+			Ok(_) => {
+				return Ok(Stored {
+					tmp,
+					dest,
+					key,
+					size,
+					digest: hasher.finish_hex(),
+				});
+			}
+			Err(e) => {
+				let _ = tokio::fs::remove_file(&tmp).await;
+				return Err(e);
+			}
+		}
+	}
+
+	/// `commit` rename the temporary files into place, the temporary files are removed if any rename failed.
+	async fn commit(&self, stored: &[Stored]) -> Result<(), error::Error> {
+		for (idx, item) in stored.iter().enumerate() {
+			match tokio::fs::rename(&item.tmp, &item.dest).await {
+				Ok(_) => {}
+				Err(e) => {
+					self.discard(&stored[idx..]).await;
+					return Err(io_error(e));
+				}
+			}
+		}
+		let mut dirs: Vec<&Path> = stored.iter().filter_map(|s| s.dest.parent()).collect();
+		dirs.dedup();
+		for dir in dirs {
+			sync_dir(dir).await;
+		}
+		return Ok(());
+	}
+
+	async fn discard(&self, stored: &[Stored]) {
+		for item in stored {
+			let _ = tokio::fs::remove_file(&item.tmp).await;
+		}
+	}
+
+	fn write_stored(&self, dest: &mut String, item: &Stored) {
+		let mut buf: Vec<u8> = vec![];
+		for seg in item.key.split('/') {
+			buf.push(b'/');
+			uricoding::encode_uri_component(&mut buf, seg);
+		}
+		let mut href = self.prefix.clone();
+		href.push_str(std::str::from_utf8(&buf).unwrap_or(""));
+		dest.push_str("{\"path\":");
+		json::escape(dest, &href);
+		let _ = write!(dest, ",\"size\":{},\"sha256\":\"{}\"}}", item.size, item.digest);
+	}
+
+	async fn put(&self, req: &mut Request<'_>, key: String) -> Result<String, error::Error> {
+		if key.is_empty() {
+			return Err(not_allowed());
+		}
+		let name = key.rsplit('/').next().unwrap_or("");
+		if basename(name).is_none() {
+			return Err(Self::bad_name(name));
+		}
+		if !self.allowed(name) {
+			return Err(Self::unsupported(name));
+		}
+		match req.headers().and_then(|h| h.content_length()) {
+			Some(cl) if cl as u64 > self.max_size => {
+				return Err(self.too_large());
+			}
+			_ => {}
+		}
+
+		let stored = self.store(key, &mut req.body_reader()).await?;
+		let stored = [stored];
+		self.commit(&stored).await?;
+		let mut body = String::new();
+		self.write_stored(&mut body, &stored[0]);
+		return Ok(body);
+	}
+
+	async fn post(&self, req: &mut Request<'_>, key: String) -> Result<String, error::Error> {
+		let boundary = match req.headers().and_then(|h| h.content_type()).and_then(|ct| boundary(ct)) {
+			Some(v) => v,
+			None => {
+				return Err(error::Error::newstatic(StatusCode::UnsupportedMediaType, "expect `multipart/form-data` body"));
+			}
+		};
+		match tokio::fs::metadata(self.path_of(&key)).await {
+			Ok(meta) if meta.is_dir() => {}
+			Ok(_) => {
+				return Err(error::Error::new(StatusCode::Conflict, format!("`{}` is not a directory", key).as_str()));
+			}
+			Err(e) => {
+				return Err(io_error(e));
+			}
+		}
+
+		let mut reader = req.body_reader();
+		let mut mp = Multipart::new(&mut reader, &boundary);
+		let mut stored: Vec<Stored> = vec![];
+		let result: Result<(), error::Error> = async {
+			loop {
+				let part = match mp.next_part().await {
+					Ok(Some(part)) => part,
+					Ok(None) => {
+						break;
+					}
+					Err(e) => {
+						return Err(io_error(e));
+					}
+				};
+				let filename = match part.filename.as_ref() {
+					Some(v) => v,
+					None => {
+						continue;
+					}
+				};
+				let name = match basename(filename) {
+					Some(v) => v,
+					None => {
+						return Err(Self::bad_name(filename));
+					}
+				};
+				if !self.allowed(name) {
+					return Err(Self::unsupported(name));
+				}
+				let ckey = if key.is_empty() { name.to_string() } else { format!("{}/{}", key, name) };
+				stored.push(self.store(ckey, &mut mp.reader()).await?);
+			}
+			return Ok(());
+		}
+		.await;
+
+		match result {
+			Ok(_) => {}
+			Err(e) => {
+				self.discard(&stored).await;
+				return Err(e);
+			}
+		}
+		if stored.is_empty() {
+			return Err(error::Error::newstatic(StatusCode::BadRequest, "no file part"));
+		}
+		self.commit(&stored).await?;
+
+		let mut body = String::from("[");
+		for (idx, item) in stored.iter().enumerate() {
+			if idx > 0 {
+				body.push(',');
+			}
+			self.write_stored(&mut body, item);
+		}
+		body.push(']');
+		return Ok(body);
+	}
+}
+
+#[async_trait]
+impl Handler for Uploader {
+	async fn handle<'a, 'c, 'h: 'a>(&'h self, req: &'a mut Request<'c>, resp: &'a mut Response<'c>) -> () {
+		let key = match self.resolve(req.path()) {
+			Some(v) => v,
+			None => {
+				error::Error::newstatic(StatusCode::Forbidden, "path is outside the root").into_response(resp);
+				return;
+			}
+		};
+		let method = req.method().to_ascii_uppercase();
+		let result = match method.as_str() {
+			methods::PUT => self.put(req, key).await,
+			methods::POST => self.post(req, key).await,
+			_ => Err(not_allowed()),
+		};
+		match result {
+			Ok(body) => {
+				resp.status(StatusCode::Created);
+				resp.headers().content_type("application/json");
+				let _ = resp.write_str(&body);
+			}
+			Err(e) => {
+				e.into_response(resp);
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use bytes::BytesMut;
+	use crate::h2tp::fs::upload::Uploader;
+	use crate::h2tp::handler::Handler;
+	use crate::h2tp::{hns, Request, Response};
+
+	async fn call(up: &Uploader, method: &str, path: &str, ct: Option<&str>, body: &str) -> (String, String) {
+		let mut req = Request::new();
+		req.msg.startline.0.push_str(method);
+		req.msg.startline.1.push_str(path);
+		match ct {
+			Some(ct) => {
+				req.msg.headers_builder().append(hns::CONTENT_TYPE, ct);
+			}
+			None => {}
+		}
+		req.msg.body = Some(BytesMut::from(body.as_bytes()));
+		let mut resp = Response::new();
+		up.handle(&mut req, &mut resp).await;
+		let body = match resp.msg.body.as_ref() {
+			Some(b) => String::from_utf8(b.to_vec()).unwrap(),
+			None => String::new(),
+		};
+		return (resp.msg.startline.1.clone(), body);
+	}
+
+	#[tokio::test]
+	async fn test_upload() {
+		let root = std::env::temp_dir().join(format!("spk-upload-{}", std::process::id()));
+		std::fs::create_dir_all(root.join("a")).unwrap();
+		let mut up = Uploader::new(root.to_str().unwrap(), "/up");
+		up.set_max_size(8).set_exts(&["txt", ".PNG"]);
+
+		let (code, body) = call(&up, "PUT", "/up/a/x%20y.txt", None, "abc").await;
+		assert_eq!(code, "201");
+		assert_eq!(
+			body,
+			r#"{"path":"/up/a/x%20y.txt","size":3,"sha256":"ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"}"#
+		);
+		assert_eq!(std::fs::read(root.join("a/x y.txt")).unwrap(), b"abc");
+
+		assert_eq!(
+			call(&up, "PUT", "/up/a/big.txt", None, "123456789").await,
+			("413".to_string(), "file is larger than 8 bytes".to_string())
+		);
+		assert!(!root.join("a/big.txt").exists());
+		assert_eq!(
+			call(&up, "PUT", "/up/a/x.exe", None, "abc").await,
+			("415".to_string(), "file type of `x.exe` is not allowed".to_string())
+		);
+		assert_eq!(call(&up, "PUT", "/up/../x.txt", None, "abc").await.0, "403");
+		assert_eq!(
+			call(&up, "PUT", "/up/a/.hidden.txt", None, "abc").await,
+			("400".to_string(), "bad file name `.hidden.txt`".to_string())
+		);
+		assert_eq!(call(&up, "PUT", "/up/a/%01.txt", None, "abc").await.0, "400");
+		assert!(!root.join("a/.hidden.txt").exists());
+		assert_eq!(call(&up, "PUT", "/up/b/x.txt", None, "abc").await.0, "409");
+		assert_eq!(call(&up, "GET", "/up/a/x.txt", None, "").await.0, "405");
+
+		let ct = Some("multipart/form-data; boundary=XX");
+		let form = "--XX\r\ncontent-disposition: form-data; name=\"t\"\r\n\r\nignored\r\n\
+			--XX\r\ncontent-disposition: form-data; name=\"f\"; filename=\"C:\\\\dir\\\\p.png\"\r\n\r\nPNG\r\n\
+			--XX\r\ncontent-disposition: form-data; name=\"f\"; filename=\"q.txt\"\r\n\r\nq\r\n--XX--\r\n";
+		let (code, body) = call(&up, "POST", "/up/a", ct, form).await;
+		assert_eq!(code, "201");
+		assert!(body.starts_with(r#"[{"path":"/up/a/p.png","size":3,"#));
+		assert!(body.contains(r#"{"path":"/up/a/q.txt","size":1,"#));
+		assert_eq!(std::fs::read(root.join("a/p.png")).unwrap(), b"PNG");
+
+		let form = "--XX\r\ncontent-disposition: form-data; name=\"f\"; filename=\"r.txt\"\r\n\r\nr\r\n\
+			--XX\r\ncontent-disposition: form-data; name=\"f\"; filename=\"s.txt\"\r\n\r\n123456789\r\n--XX--\r\n";
+		assert_eq!(call(&up, "POST", "/up/a", ct, form).await.0, "413");
+		assert!(!root.join("a/r.txt").exists());
+		assert!(std::fs::read_dir(root.join("a")).unwrap().all(|e| !e.unwrap().file_name().to_str().unwrap().starts_with(".upload-")));
+
+		std::fs::remove_dir_all(&root).unwrap();
+	}
+}
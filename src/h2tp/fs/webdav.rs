@@ -0,0 +1,1057 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use async_trait::async_trait;
+use crate::h2tp::error::{self, Error};
+use crate::h2tp::fs::read::{confine, etag, key_of, strip_prefix, Meta, Readable, SimpleOsReader};
+use crate::h2tp::fs::upload::{sync_dir, tmp_name};
+use crate::h2tp::handler::Handler;
+use crate::h2tp::headers::mime;
+use crate::h2tp::response::IntoResponse;
+use crate::h2tp::status_code::StatusCode;
+use crate::h2tp::url::Url;
+use crate::h2tp::utils::{httpdate, uricoding, xml};
+use crate::h2tp::{hns, methods, Request, Response};
+
+const DAV_NS: &str = "DAV:";
+const ALLOW: &str = "OPTIONS, GET, HEAD, PUT, DELETE, MKCOL, COPY, MOVE, PROPFIND, PROPPATCH, LOCK, UNLOCK";
+const XML_CONTENT_TYPE: &str = "application/xml; charset=utf-8";
+const DEFAULT_LOCK_TIMEOUT: u64 = 3600;
+const MAX_LOCK_TIMEOUT: u64 = 86400 * 7;
+const LIVE_PROPS: [&str; 9] = [
+	"creationdate",
+	"displayname",
+	"getcontentlength",
+	"getcontenttype",
+	"getetag",
+	"getlastmodified",
+	"resourcetype",
+	"supportedlock",
+	"lockdiscovery",
+];
+
+struct DeadProp {
+	ns: String,
+	name: String,
+	value: String,
+}
+
+struct Lock {
+	token: String,
+	owner: String,
+	exclusive: bool,
+	infinite: bool,
+	timeout: u64,
+	expires: Instant,
+}
+
+impl Lock {
+	/// `covers` check if the lock that on `key` applies to `path`.
+	fn covers(&self, key: &str, path: &str) -> bool {
+		return key == path || (self.infinite && is_descendant(key, path));
+	}
+}
+
+/// `is_descendant` check if `path` is under the collection `parent`, both are the keys relative to the root.
+fn is_descendant(parent: &str, path: &str) -> bool {
+	if parent.is_empty() {
+		return !path.is_empty();
+	}
+	return path.len() > parent.len() && path.starts_with(parent) && path.as_bytes()[parent.len()] == b'/';
+}
+
+/// `prune` remove the expired locks.
+fn prune(locks: &mut HashMap<String, Vec<Lock>>) {
+	let now = Instant::now();
+	locks.retain(|_, ls| {
+		ls.retain(|l| l.expires > now);
+		return !ls.is_empty();
+	});
+}
+
+fn parent_of(key: &str) -> &str {
+	return match key.rfind('/') {
+		Some(idx) => &key[..idx],
+		None => "",
+	};
+}
+
+/// `WebDav` serves a directory over WebDAV(RFC 4918, class 1 and 2), the `GET` and `HEAD`
+/// are handled by a `SimpleOsReader`. the dead properties and locks are kept in memory.
+/// enable `Server::defer_body`, so the large `PUT` bodies are streamed to disk instead of being buffered in memory.
+pub struct WebDav {
+	reader: SimpleOsReader,
+	root: PathBuf,
+	prefix: String,
+	props: Mutex<HashMap<String, Vec<DeadProp>>>,
+	/// the locks by the resource key, a resource can have many shared locks.
+	locks: Mutex<HashMap<String, Vec<Lock>>>,
+}
+
+fn status_line(code: StatusCode) -> String {
+	return format!("HTTP/1.1 {} {}", code.code(), code.msg());
+}
+
+/// `io_error` convert the io error like `h2tp::Error::from`, except that creating an existing resource is a `405`.
+fn io_error(e: std::io::Error) -> Error {
+	let code = match e.kind() {
+		ErrorKind::AlreadyExists => StatusCode::MethodNotAllowed,
+		kind => error::io_status(kind),
+	};
+	return Error::newstatic(code, code.msg()).with_source(e);
+}
+
+/// `create_error` is the `io_error` of creating a resource, a missing parent collection is a `409`.
+fn create_error(e: std::io::Error) -> Error {
+	if e.kind() == ErrorKind::NotFound {
+		return Error::newstatic(StatusCode::Conflict, "parent collection does not exist").with_source(e);
+	}
+	return io_error(e);
+}
+
+fn locked_error() -> Error {
+	return Error::newstatic(StatusCode::Locked, "resource is locked");
+}
+
+fn not_allowed(msg: &'static str) -> Error {
+	return Error::newstatic(StatusCode::MethodNotAllowed, msg).with_header(hns::ALLOW, ALLOW);
+}
+
+fn header<'a>(req: &'a Request, k: &str) -> Option<&'a str> {
+	return req.headers().and_then(|h| h.getone(k)).map(|v| v.trim());
+}
+
+fn lock_token() -> String {
+	static COUNTER: AtomicU64 = AtomicU64::new(0);
+	let nanos = match SystemTime::now().duration_since(UNIX_EPOCH) {
+		Ok(d) => d.as_nanos() as u64,
+		Err(_) => 0,
+	};
+	let seq = COUNTER.fetch_add(1, Ordering::Relaxed);
+	let a = nanos ^ (std::process::id() as u64).rotate_left(32);
+	let b = seq.wrapping_mul(0x9e3779b97f4a7c15) ^ nanos.rotate_left(17);
+	return format!(
+		"opaquelocktoken:{:08x}-{:04x}-{:04x}-{:04x}-{:012x}",
+		a >> 32,
+		(a >> 16) & 0xffff,
+		a & 0xffff,
+		b >> 48,
+		b & 0xffffffffffff
+	);
+}
+
+fn write_prop_open(dest: &mut String, ns: &str, name: &str) {
+	if ns == DAV_NS {
+		let _ = write!(dest, "<D:{}", name);
+	} else {
+		let _ = write!(dest, "<{} xmlns=\"", name);
+		xml::escape(dest, ns);
+		dest.push('"');
+	}
+}
+
+fn write_prop_close(dest: &mut String, ns: &str, name: &str) {
+	if ns == DAV_NS {
+		let _ = write!(dest, "</D:{}>", name);
+	} else {
+		let _ = write!(dest, "</{}>", name);
+	}
+}
+
+fn write_empty_prop(dest: &mut String, ns: &str, name: &str) {
+	write_prop_open(dest, ns, name);
+	dest.push_str("/>");
+}
+
+fn write_propstat(dest: &mut String, props: &str, code: StatusCode) {
+	let _ = write!(
+		dest,
+		"<D:propstat><D:prop>{}</D:prop><D:status>{}</D:status></D:propstat>",
+		props,
+		status_line(code)
+	);
+}
+
+async fn copy_tree(src: &Path, dst: &Path, recursive: bool) -> std::io::Result<()> {
+	let meta = tokio::fs::metadata(src).await?;
+	if !meta.is_dir() {
+		tokio::fs::copy(src, dst).await?;
+		return Ok(());
+	}
+	let mut stack = vec![(src.to_path_buf(), dst.to_path_buf())];
+	while let Some((s, d)) = stack.pop() {
+		tokio::fs::create_dir(&d).await?;
+		if !recursive {
+			break;
+		}
+		let mut entries = tokio::fs::read_dir(&s).await?;
+		while let Some(entry) = entries.next_entry().await? {
+			let from = entry.path();
+			let to = d.join(entry.file_name());
+			if entry.file_type().await?.is_dir() {
+				stack.push((from, to));
+			} else {
+				tokio::fs::copy(&from, &to).await?;
+			}
+		}
+	}
+	return Ok(());
+}
+
+async fn remove(path: &Path) -> std::io::Result<()> {
+	let meta = tokio::fs::metadata(path).await?;
+	if meta.is_dir() {
+		return tokio::fs::remove_dir_all(path).await;
+	}
+	return tokio::fs::remove_file(path).await;
+}
+
+impl WebDav {
+	/// `new` create a WebDAV handler on the directory `root`, the `prefix` will be stripped from the request path.
+	pub fn new(root: &str, prefix: &str) -> Self {
+		let mut reader = SimpleOsReader::new(root, prefix);
+		reader.set_precompressed(false);
+		return Self {
+			reader,
+			root: PathBuf::from(root),
+			prefix: prefix.trim_end_matches('/').to_string(),
+			props: Mutex::new(HashMap::new()),
+			locks: Mutex::new(HashMap::new()),
+		};
+	}
+
+	/// `resolve` return the key(the path relative to the root, without leading and trailing slashes)
+	/// and the filesystem path of a request path or a `Destination` uri.
+	fn resolve(&self, target: &str) -> Option<(String, PathBuf)> {
+		let url;
+		let mut target = target;
+		if target.contains("://") {
+			url = Url::parse(target).ok()?;
+			target = url.path();
+		}
+		let path = confine(&self.root, strip_prefix(&self.prefix, target)?)?;
+		return Some((key_of(&self.root, &path), path));
+	}
+
+	fn href(&self, key: &str, is_dir: bool) -> String {
+		let mut buf: Vec<u8> = vec![];
+		for seg in key.split('/') {
+			if seg.is_empty() {
+				continue;
+			}
+			buf.push(b'/');
+			uricoding::encode_uri_component(&mut buf, seg);
+		}
+		let mut href = self.prefix.clone();
+		href.push_str(std::str::from_utf8(&buf).unwrap_or(""));
+		if is_dir || href.is_empty() {
+			href.push('/');
+		}
+		return href;
+	}
+
+	/// `locked` check if `key`(or its descendants when `deep`) is locked by a lock
+	/// whose token is not submitted by the `If` header of the request.
+	fn locked(&self, key: &str, deep: bool, req: &Request) -> bool {
+		let submitted = header(req, hns::IF).unwrap_or("");
+		let mut locks = self.locks.lock().unwrap();
+		prune(&mut locks);
+		return locks.iter().any(|(k, ls)| {
+			return ls.iter().any(|l| {
+				let applies = l.covers(k, key) || (deep && is_descendant(key, k));
+				return applies && !submitted.contains(format!("<{}>", l.token).as_str());
+			});
+		});
+	}
+
+	fn drop_state(&self, key: &str) {
+		self.props.lock().unwrap().retain(|k, _| k != key && !is_descendant(key, k));
+		self.locks.lock().unwrap().retain(|k, _| k != key && !is_descendant(key, k));
+	}
+
+	fn copy_props(&self, from: &str, to: &str, moving: bool) {
+		let mut props = self.props.lock().unwrap();
+		props.retain(|k, _| k != to && !is_descendant(to, k));
+		let keys: Vec<String> = props.keys().filter(|k| *k == from || is_descendant(from, k)).cloned().collect();
+		for k in keys {
+			let nk = format!("{}{}", to, &k[from.len()..]);
+			let vals = if moving {
+				props.remove(&k).unwrap()
+			} else {
+				props[&k]
+					.iter()
+					.map(|p| DeadProp {
+						ns: p.ns.clone(),
+						name: p.name.clone(),
+						value: p.value.clone(),
+					})
+					.collect()
+			};
+			props.insert(nk, vals);
+		}
+	}
+
+	fn lockdiscovery(&self, key: &str) -> String {
+		let mut dest = String::new();
+		let locks = self.locks.lock().unwrap();
+		let now = Instant::now();
+		for (k, ls) in locks.iter() {
+			for l in ls.iter() {
+				if l.expires <= now || !l.covers(k, key) {
+					continue;
+				}
+				self.write_activelock(&mut dest, k, l);
+			}
+		}
+		return dest;
+	}
+
+	fn write_activelock(&self, dest: &mut String, key: &str, l: &Lock) {
+		let _ = write!(
+			dest,
+			"<D:activelock><D:locktype><D:write/></D:locktype><D:lockscope>{}</D:lockscope><D:depth>{}</D:depth>",
+			if l.exclusive { "<D:exclusive/>" } else { "<D:shared/>" },
+			if l.infinite { "infinity" } else { "0" },
+		);
+		if !l.owner.is_empty() {
+			dest.push_str("<D:owner>");
+			xml::escape(dest, &l.owner);
+			dest.push_str("</D:owner>");
+		}
+		let _ = write!(
+			dest,
+			"<D:timeout>Second-{}</D:timeout><D:locktoken><D:href>{}</D:href></D:locktoken><D:lockroot><D:href>",
+			l.timeout, l.token
+		);
+		xml::escape(dest, &self.href(key, false));
+		dest.push_str("</D:href></D:lockroot></D:activelock>");
+	}
+
+	/// `live_prop` write the value of a live property, return `false` if the property is not available.
+	fn live_prop(&self, dest: &mut String, key: &str, meta: &Meta, name: &str) -> bool {
+		let mut value = String::new();
+		match name {
+			"creationdate" => match meta.modified {
+				Some(t) => {
+					value = httpdate::format_rfc3339(t);
+				}
+				None => {
+					return false;
+				}
+			},
+			"displayname" => {
+				xml::escape(&mut value, key.rsplit('/').next().unwrap_or(""));
+			}
+			"getcontentlength" => {
+				if meta.is_dir {
+					return false;
+				}
+				value = meta.size.to_string();
+			}
+			"getcontenttype" => {
+				if meta.is_dir {
+					return false;
+				}
+				value = mime::by_path(key).to_string();
+			}
+			"getetag" => {
+				if meta.is_dir {
+					return false;
+				}
+				xml::escape(&mut value, etag(meta, None).as_str());
+			}
+			"getlastmodified" => match meta.modified {
+				Some(t) => {
+					value = httpdate::format(t);
+				}
+				None => {
+					return false;
+				}
+			},
+			"resourcetype" => {
+				if meta.is_dir {
+					value.push_str("<D:collection/>");
+				}
+			}
+			"supportedlock" => {
+				value.push_str("<D:lockentry><D:lockscope><D:exclusive/></D:lockscope><D:locktype><D:write/></D:locktype></D:lockentry>");
+				value.push_str("<D:lockentry><D:lockscope><D:shared/></D:lockscope><D:locktype><D:write/></D:locktype></D:lockentry>");
+			}
+			"lockdiscovery" => {
+				value = self.lockdiscovery(key);
+			}
+			_ => {
+				return false;
+			}
+		}
+		let _ = write!(dest, "<D:{}>{}</D:{}>", name, value, name);
+		return true;
+	}
+
+	fn propfind_one(&self, dest: &mut String, key: &str, meta: &Meta, query: &Option<xml::Element>) {
+		let mut found = String::new();
+		let mut missing = String::new();
+		let props = self.props.lock().unwrap();
+		let dead = props.get(key);
+
+		let names_only = match query {
+			Some(q) => q.child(DAV_NS, "propname").is_some(),
+			None => false,
+		};
+		let requested = match query {
+			Some(q) => q.child(DAV_NS, "prop"),
+			None => None,
+		};
+
+		match requested {
+			Some(prop) => {
+				for e in prop.elements() {
+					if e.ns == DAV_NS && self.live_prop(&mut found, key, meta, &e.name) {
+						continue;
+					}
+					let value = dead.and_then(|v| v.iter().find(|p| p.ns == e.ns && p.name == e.name));
+					match value {
+						Some(p) => {
+							write_prop_open(&mut found, &p.ns, &p.name);
+							found.push('>');
+							xml::escape(&mut found, &p.value);
+							write_prop_close(&mut found, &p.ns, &p.name);
+						}
+						None => {
+							write_empty_prop(&mut missing, &e.ns, &e.name);
+						}
+					}
+				}
+			}
+			None => {
+				for name in LIVE_PROPS {
+					if names_only {
+						if self.live_prop(&mut String::new(), key, meta, name) {
+							write_empty_prop(&mut found, DAV_NS, name);
+						}
+						continue;
+					}
+					self.live_prop(&mut found, key, meta, name);
+				}
+				match dead {
+					Some(dead) => {
+						for p in dead {
+							if names_only {
+								write_empty_prop(&mut found, &p.ns, &p.name);
+								continue;
+							}
+							write_prop_open(&mut found, &p.ns, &p.name);
+							found.push('>');
+							xml::escape(&mut found, &p.value);
+							write_prop_close(&mut found, &p.ns, &p.name);
+						}
+					}
+					None => {}
+				}
+			}
+		}
+
+		dest.push_str("<D:response><D:href>");
+		xml::escape(dest, &self.href(key, meta.is_dir));
+		dest.push_str("</D:href>");
+		if !found.is_empty() || missing.is_empty() {
+			write_propstat(dest, &found, StatusCode::OK);
+		}
+		if !missing.is_empty() {
+			write_propstat(dest, &missing, StatusCode::NotFound);
+		}
+		dest.push_str("</D:response>");
+	}
+
+	fn multistatus(resp: &mut Response, body: &str) {
+		resp.status(StatusCode::MultiStatus);
+		resp.headers().content_type(XML_CONTENT_TYPE);
+		let _ = write!(
+			resp,
+			"<?xml version=\"1.0\" encoding=\"utf-8\"?><D:multistatus xmlns:D=\"DAV:\">{}</D:multistatus>",
+			body
+		);
+	}
+
+	/// `parse_body` parse the xml request body, return `Ok(None)` if the body is empty.
+	fn parse_body(req: &Request) -> Result<Option<xml::Element>, Error> {
+		let body = match req.body() {
+			Some(body) if !body.is_empty() => body,
+			_ => {
+				return Ok(None);
+			}
+		};
+		let src = std::str::from_utf8(body).map_err(|_| Error::newstatic(StatusCode::BadRequest, "body is not valid utf-8"))?;
+		if src.trim().is_empty() {
+			return Ok(None);
+		}
+		return xml::parse(src).map(Some).map_err(|_| Error::newstatic(StatusCode::BadRequest, "bad xml body"));
+	}
+
+	async fn propfind(&self, req: &Request<'_>, resp: &mut Response<'_>, key: &str, path: &Path) -> Result<(), Error> {
+		let deep = match header(req, hns::DEPTH) {
+			Some("0") => false,
+			Some("1") => true,
+			Some(_) => {
+				return Err(Error::newstatic(StatusCode::Forbidden, "infinite depth is not supported"));
+			}
+			None => true,
+		};
+		let query = Self::parse_body(req)?;
+		match query.as_ref() {
+			Some(q) if !q.is(DAV_NS, "propfind") => {
+				return Err(Error::newstatic(StatusCode::BadRequest, "expect a `propfind` body"));
+			}
+			_ => {}
+		}
+
+		let meta = Meta::from(tokio::fs::metadata(path).await.map_err(io_error)?);
+
+		let mut body = String::new();
+		self.propfind_one(&mut body, key, &meta, &query);
+
+		if deep && meta.is_dir {
+			match tokio::fs::read_dir(path).await {
+				Ok(mut entries) => {
+					while let Ok(Some(entry)) = entries.next_entry().await {
+						let name = match entry.file_name().into_string() {
+							Ok(v) => v,
+							Err(_) => {
+								continue;
+							}
+						};
+						let meta = match entry.metadata().await {
+							Ok(v) => Meta::from(v),
+							Err(_) => {
+								continue;
+							}
+						};
+						let ckey = if key.is_empty() { name } else { format!("{}/{}", key, name) };
+						self.propfind_one(&mut body, &ckey, &meta, &query);
+					}
+				}
+				Err(e) => {
+					return Err(io_error(e));
+				}
+			}
+		}
+		Self::multistatus(resp, &body);
+		return Ok(());
+	}
+
+	async fn proppatch(&self, req: &Request<'_>, resp: &mut Response<'_>, key: &str, path: &Path) -> Result<(), Error> {
+		tokio::fs::metadata(path).await.map_err(io_error)?;
+		if self.locked(key, false, req) {
+			return Err(locked_error());
+		}
+		let update = match Self::parse_body(req)? {
+			Some(v) if v.is(DAV_NS, "propertyupdate") => v,
+			_ => {
+				return Err(Error::newstatic(StatusCode::BadRequest, "expect a `propertyupdate` body"));
+			}
+		};
+
+		// (set?, ns, name, value) in document order
+		let mut ops: Vec<(bool, String, String, String)> = vec![];
+		for action in update.elements() {
+			let set = if action.is(DAV_NS, "set") {
+				true
+			} else if action.is(DAV_NS, "remove") {
+				false
+			} else {
+				continue;
+			};
+			match action.child(DAV_NS, "prop") {
+				Some(prop) => {
+					for e in prop.elements() {
+						ops.push((set, e.ns.clone(), e.name.clone(), e.text()));
+					}
+				}
+				None => {}
+			}
+		}
+
+		let protected = ops.iter().any(|(_, ns, name, _)| ns == DAV_NS && LIVE_PROPS.contains(&name.as_str()));
+		let mut ok = String::new();
+		let mut forbidden = String::new();
+		let mut failed = String::new();
+		{
+			let mut props = self.props.lock().unwrap();
+			let dead = props.entry(key.to_string()).or_default();
+			for (set, ns, name, value) in ops {
+				if protected {
+					if ns == DAV_NS && LIVE_PROPS.contains(&name.as_str()) {
+						write_empty_prop(&mut forbidden, &ns, &name);
+					} else {
+						write_empty_prop(&mut failed, &ns, &name);
+					}
+					continue;
+				}
+				dead.retain(|p| p.ns != ns || p.name != name);
+				if set {
+					dead.push(DeadProp {
+						ns: ns.clone(),
+						name: name.clone(),
+						value,
+					});
+				}
+				write_empty_prop(&mut ok, &ns, &name);
+			}
+		}
+
+		let mut body = String::from("<D:response><D:href>");
+		xml::escape(&mut body, &self.href(key, false));
+		body.push_str("</D:href>");
+		if !ok.is_empty() {
+			write_propstat(&mut body, &ok, StatusCode::OK);
+		}
+		if !forbidden.is_empty() {
+			write_propstat(&mut body, &forbidden, StatusCode::Forbidden);
+		}
+		if !failed.is_empty() {
+			write_propstat(&mut body, &failed, StatusCode::FailedDependency);
+		}
+		body.push_str("</D:response>");
+		Self::multistatus(resp, &body);
+		return Ok(());
+	}
+
+	async fn mkcol(&self, req: &Request<'_>, resp: &mut Response<'_>, key: &str, path: &Path) -> Result<(), Error> {
+		if req.body().map(|b| !b.is_empty()).unwrap_or(false) {
+			return Err(Error::newstatic(StatusCode::UnsupportedMediaType, "MKCOL body is not supported"));
+		}
+		if tokio::fs::metadata(path).await.is_ok() {
+			return Err(not_allowed("resource already exists"));
+		}
+		if self.locked(key, false, req) {
+			return Err(locked_error());
+		}
+		tokio::fs::create_dir(path).await.map_err(create_error)?;
+		resp.status(StatusCode::Created);
+		return Ok(());
+	}
+
+	async fn put(&self, req: &mut Request<'_>, resp: &mut Response<'_>, key: &str, path: &Path) -> Result<(), Error> {
+		let exists = match tokio::fs::metadata(path).await {
+			Ok(meta) => {
+				if meta.is_dir() {
+					return Err(not_allowed("resource is a collection"));
+				}
+				true
+			}
+			Err(_) => false,
+		};
+		if self.locked(key, false, req) {
+			return Err(locked_error());
+		}
+		// write to a temporary file and rename it into place, so a failed upload never truncates the old content.
+		let dir = match path.parent() {
+			Some(v) => v,
+			None => {
+				return Err(Error::newstatic(StatusCode::Forbidden, "can not write to the root"));
+			}
+		};
+		let tmp = dir.join(tmp_name());
+		let mut file = tokio::fs::OpenOptions::new().write(true).create_new(true).open(&tmp).await.map_err(create_error)?;
+		let result = async {
+			tokio::io::copy(&mut req.body_reader(), &mut file).await?;
+			file.sync_all().await?;
+			drop(file);
+			return tokio::fs::rename(&tmp, path).await;
+		}
+		.await;
+		if let Err(e) = result {
+			let _ = tokio::fs::remove_file(&tmp).await;
+			return Err(io_error(e));
+		}
+		sync_dir(dir).await;
+		resp.status(if exists { StatusCode::NoContent } else { StatusCode::Created });
+		return Ok(());
+	}
+
+	async fn delete(&self, req: &Request<'_>, resp: &mut Response<'_>, key: &str, path: &Path) -> Result<(), Error> {
+		if key.is_empty() {
+			return Err(Error::newstatic(StatusCode::Forbidden, "can not delete the root"));
+		}
+		if self.locked(key, true, req) {
+			return Err(locked_error());
+		}
+		remove(path).await.map_err(io_error)?;
+		self.drop_state(key);
+		resp.status(StatusCode::NoContent);
+		return Ok(());
+	}
+
+	async fn copy_or_move(
+		&self,
+		req: &Request<'_>,
+		resp: &mut Response<'_>,
+		key: &str,
+		path: &Path,
+		moving: bool,
+	) -> Result<(), Error> {
+		let (dkey, dpath) = match header(req, hns::DESTINATION).and_then(|v| self.resolve(v)) {
+			Some(v) => v,
+			None => {
+				return Err(Error::newstatic(StatusCode::BadRequest, "bad destination"));
+			}
+		};
+		if dkey == key || is_descendant(key, &dkey) || is_descendant(&dkey, key) || dkey.is_empty() {
+			return Err(Error::newstatic(StatusCode::Forbidden, "destination overlaps the source"));
+		}
+		tokio::fs::metadata(path).await.map_err(io_error)?;
+		if (moving && self.locked(key, true, req)) || self.locked(&dkey, true, req) {
+			return Err(locked_error());
+		}
+		match tokio::fs::metadata(self.root.join(parent_of(&dkey))).await {
+			Ok(meta) if meta.is_dir() => {}
+			_ => {
+				return Err(Error::newstatic(StatusCode::Conflict, "parent collection does not exist"));
+			}
+		}
+
+		let overwrite = !matches!(header(req, hns::OVERWRITE), Some("F") | Some("f"));
+		let exists = tokio::fs::metadata(&dpath).await.is_ok();
+		if exists {
+			if !overwrite {
+				return Err(Error::newstatic(StatusCode::PreconditionFailed, "destination exists"));
+			}
+			remove(&dpath).await.map_err(io_error)?;
+			self.drop_state(&dkey);
+		}
+
+		if moving {
+			tokio::fs::rename(path, &dpath).await.map_err(io_error)?;
+		} else {
+			copy_tree(path, &dpath, header(req, hns::DEPTH) != Some("0")).await.map_err(io_error)?;
+		}
+		self.copy_props(key, &dkey, moving);
+		if moving {
+			self.drop_state(key);
+		}
+		resp.status(if exists { StatusCode::NoContent } else { StatusCode::Created });
+		return Ok(());
+	}
+
+	fn lock_timeout(req: &Request) -> u64 {
+		let v = match header(req, hns::TIMEOUT) {
+			Some(v) => v,
+			None => {
+				return DEFAULT_LOCK_TIMEOUT;
+			}
+		};
+		for item in v.split(',') {
+			let item = item.trim();
+			if item.eq_ignore_ascii_case("infinite") {
+				return MAX_LOCK_TIMEOUT;
+			}
+			if let Some(secs) = item.strip_prefix("Second-") {
+				if let Ok(secs) = secs.parse::<u64>() {
+					return std::cmp::min(secs, MAX_LOCK_TIMEOUT);
+				}
+			}
+		}
+		return DEFAULT_LOCK_TIMEOUT;
+	}
+
+	fn lock_response(&self, resp: &mut Response, token: &str) {
+		let mut body = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?><D:prop xmlns:D=\"DAV:\"><D:lockdiscovery>");
+		{
+			let locks = self.locks.lock().unwrap();
+			for (k, ls) in locks.iter() {
+				match ls.iter().find(|l| l.token == token) {
+					Some(l) => {
+						self.write_activelock(&mut body, k, l);
+					}
+					None => {}
+				}
+			}
+		}
+		body.push_str("</D:lockdiscovery></D:prop>");
+		resp.headers().content_type(XML_CONTENT_TYPE).reset(hns::LOCK_TOKEN, format!("<{}>", token).as_str());
+		let _ = resp.write_str(&body);
+	}
+
+	async fn lock(&self, req: &Request<'_>, resp: &mut Response<'_>, key: &str, path: &Path) -> Result<(), Error> {
+		let info = Self::parse_body(req)?;
+		let timeout = Self::lock_timeout(req);
+
+		let info = match info {
+			Some(v) => v,
+			None => {
+				// refresh the lock that submitted by the `If` header
+				let submitted = header(req, hns::IF).unwrap_or("");
+				let mut token: Option<String> = None;
+				{
+					let mut locks = self.locks.lock().unwrap();
+					prune(&mut locks);
+					for (k, ls) in locks.iter_mut() {
+						for l in ls.iter_mut() {
+							if l.covers(k, key) && submitted.contains(format!("<{}>", l.token).as_str()) {
+								l.timeout = timeout;
+								l.expires = Instant::now() + Duration::from_secs(timeout);
+								token = Some(l.token.clone());
+								break;
+							}
+						}
+						if token.is_some() {
+							break;
+						}
+					}
+				}
+				return match token {
+					Some(token) => {
+						self.lock_response(resp, &token);
+						Ok(())
+					}
+					None => Err(Error::newstatic(StatusCode::PreconditionFailed, "no lock to refresh")),
+				};
+			}
+		};
+		if !info.is(DAV_NS, "lockinfo") {
+			return Err(Error::newstatic(StatusCode::BadRequest, "expect a `lockinfo` body"));
+		}
+		let exclusive = match info.child(DAV_NS, "lockscope") {
+			Some(scope) => scope.child(DAV_NS, "exclusive").is_some(),
+			None => true,
+		};
+		let owner = match info.child(DAV_NS, "owner") {
+			Some(owner) => match owner.child(DAV_NS, "href") {
+				Some(href) => href.text(),
+				None => owner.text(),
+			},
+			None => String::new(),
+		};
+		let infinite = header(req, hns::DEPTH) != Some("0");
+
+		let token = lock_token();
+		{
+			let mut locks = self.locks.lock().unwrap();
+			prune(&mut locks);
+			let conflict = locks.iter().any(|(k, ls)| {
+				return ls.iter().any(|l| {
+					let overlaps = l.covers(k, key) || (infinite && is_descendant(key, k));
+					return overlaps && (exclusive || l.exclusive);
+				});
+			});
+			if conflict {
+				return Err(locked_error());
+			}
+			let now = Instant::now();
+			locks.entry(key.to_string()).or_default().push(Lock {
+				token: token.clone(),
+				owner,
+				exclusive,
+				infinite,
+				timeout,
+				expires: now + Duration::from_secs(timeout),
+			});
+		}
+
+		// lock an unmapped url creates an empty resource
+		if tokio::fs::metadata(path).await.is_err() {
+			match tokio::fs::write(path, b"").await {
+				Ok(_) => {
+					resp.status(StatusCode::Created);
+				}
+				Err(e) => {
+					self.remove_lock(key, &token);
+					return Err(create_error(e));
+				}
+			}
+		}
+		self.lock_response(resp, &token);
+		return Ok(());
+	}
+
+	fn unlock(&self, req: &Request<'_>, resp: &mut Response<'_>, key: &str) -> Result<(), Error> {
+		let token = match header(req, hns::LOCK_TOKEN) {
+			Some(v) => v.trim_start_matches('<').trim_end_matches('>'),
+			None => {
+				return Err(Error::newstatic(StatusCode::BadRequest, "missing lock token"));
+			}
+		};
+		let found = {
+			let locks = self.locks.lock().unwrap();
+			locks
+				.iter()
+				.find(|(k, ls)| ls.iter().any(|l| l.token == token && l.covers(k, key)))
+				.map(|(k, _)| k.clone())
+		};
+		return match found {
+			Some(k) => {
+				self.remove_lock(&k, token);
+				resp.status(StatusCode::NoContent);
+				Ok(())
+			}
+			None => Err(Error::newstatic(StatusCode::Conflict, "lock token does not match")),
+		};
+	}
+
+	/// `remove_lock` remove the lock `token` on `key`, the other locks on it are kept.
+	fn remove_lock(&self, key: &str, token: &str) {
+		let mut locks = self.locks.lock().unwrap();
+		match locks.get_mut(key) {
+			Some(ls) => {
+				ls.retain(|l| l.token != token);
+				if ls.is_empty() {
+					locks.remove(key);
+				}
+			}
+			None => {}
+		}
+	}
+}
+
+#[async_trait]
+impl Handler for WebDav {
+	async fn handle<'a, 'c, 'h: 'a>(&'h self, req: &'a mut Request<'c>, resp: &'a mut Response<'c>) -> () {
+		let method = req.method().to_ascii_uppercase();
+		if method == methods::GET || method == methods::HEAD {
+			Readable::handle(&self.reader, req, resp).await;
+			return;
+		}
+		if method == methods::OPTIONS {
+			resp.headers().reset(hns::DAV, "1, 2").reset(hns::ALLOW, ALLOW).reset("ms-author-via", "DAV");
+			return;
+		}
+
+		let (key, path) = match self.resolve(req.path()) {
+			Some(v) => v,
+			None => {
+				Error::newstatic(StatusCode::Forbidden, "path is outside the root").into_response(resp);
+				return;
+			}
+		};
+		let result = match method.as_str() {
+			methods::PROPFIND => self.propfind(req, resp, &key, &path).await,
+			methods::PROPPATCH => self.proppatch(req, resp, &key, &path).await,
+			methods::MKCOL => self.mkcol(req, resp, &key, &path).await,
+			methods::PUT => self.put(req, resp, &key, &path).await,
+			methods::DELETE => self.delete(req, resp, &key, &path).await,
+			methods::COPY => self.copy_or_move(req, resp, &key, &path, false).await,
+			methods::MOVE => self.copy_or_move(req, resp, &key, &path, true).await,
+			methods::LOCK => self.lock(req, resp, &key, &path).await,
+			methods::UNLOCK => self.unlock(req, resp, &key),
+			_ => Err(not_allowed(StatusCode::MethodNotAllowed.msg())),
+		};
+		match result {
+			Ok(_) => {}
+			Err(e) => {
+				e.into_response(resp);
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use bytes::BytesMut;
+	use crate::h2tp::fs::webdav::WebDav;
+	use crate::h2tp::handler::Handler;
+	use crate::h2tp::{hns, Request, Response};
+
+	async fn call(dav: &WebDav, method: &str, path: &str, headers: &[(&str, &str)], body: &str) -> (String, Option<String>, String) {
+		let mut req = Request::new();
+		req.msg.startline.0.push_str(method);
+		req.msg.startline.1.push_str(path);
+		for (k, v) in headers {
+			req.msg.headers_builder().append(k, v);
+		}
+		req.msg.body = Some(BytesMut::from(body.as_bytes()));
+		let mut resp = Response::new();
+		dav.handle(&mut req, &mut resp).await;
+		let lock_token = resp.msg.headers.as_ref().and_then(|h| h.getone(hns::LOCK_TOKEN)).cloned();
+		let body = match (resp.msg.body.as_ref(), resp.error()) {
+			(Some(b), _) => String::from_utf8(b.to_vec()).unwrap(),
+			(None, Some(e)) => e.msg().to_string(),
+			(None, None) => String::new(),
+		};
+		let code = if resp.msg.startline.1.is_empty() { "200".to_string() } else { resp.msg.startline.1.clone() };
+		return (code, lock_token, body);
+	}
+
+	#[tokio::test]
+	async fn test_webdav() {
+		let root = std::env::temp_dir().join(format!("spk-webdav-{}", std::process::id()));
+		std::fs::create_dir_all(&root).unwrap();
+		let dav = WebDav::new(root.to_str().unwrap(), "/dav");
+
+		assert_eq!(call(&dav, "MKCOL", "/dav/a", &[], "").await.0, "201");
+		assert_eq!(call(&dav, "MKCOL", "/dav/x/y", &[], "").await.0, "409");
+		assert_eq!(call(&dav, "PUT", "/dav/a/f%20.txt", &[], "hello").await.0, "201");
+		assert_eq!(call(&dav, "PUT", "/dav/../f.txt", &[], "hello").await.0, "403");
+
+		let (code, _, body) = call(&dav, "PROPFIND", "/dav/a", &[(hns::DEPTH, "1")], "").await;
+		assert_eq!(code, "207");
+		assert!(body.contains("<D:href>/dav/a/f%20.txt</D:href>"));
+		assert!(body.contains("<D:getcontentlength>5</D:getcontentlength>"));
+		assert!(body.contains("<D:resourcetype><D:collection/></D:resourcetype>"));
+
+		let patch = r#"<?xml version="1.0"?><D:propertyupdate xmlns:D="DAV:" xmlns:Z="urn:z"><D:set><D:prop><Z:author>me</Z:author></D:prop></D:set></D:propertyupdate>"#;
+		assert_eq!(call(&dav, "PROPPATCH", "/dav/a/f%20.txt", &[], patch).await.0, "207");
+
+		let (code, token, _) = call(
+			&dav,
+			"LOCK",
+			"/dav/a/f%20.txt",
+			&[],
+			r#"<D:lockinfo xmlns:D="DAV:"><D:lockscope><D:exclusive/></D:lockscope><D:locktype><D:write/></D:locktype></D:lockinfo>"#,
+		)
+		.await;
+		assert_eq!(code, "200");
+		let token = token.unwrap();
+		assert_eq!(call(&dav, "PUT", "/dav/a/f%20.txt", &[], "x").await.0, "423");
+		let cond = format!("({})", token);
+		assert_eq!(call(&dav, "PUT", "/dav/a/f%20.txt", &[(hns::IF, &cond)], "world").await.0, "204");
+		assert_eq!(std::fs::read_to_string(root.join("a/f .txt")).unwrap(), "world");
+		assert_eq!(std::fs::read_dir(root.join("a")).unwrap().count(), 1);
+		assert_eq!(call(&dav, "UNLOCK", "/dav/a/f%20.txt", &[(hns::LOCK_TOKEN, &token)], "").await.0, "204");
+
+		let shared = r#"<D:lockinfo xmlns:D="DAV:"><D:lockscope><D:shared/></D:lockscope><D:locktype><D:write/></D:locktype></D:lockinfo>"#;
+		let (code, first, _) = call(&dav, "LOCK", "/dav/a/f%20.txt", &[(hns::DEPTH, "0")], shared).await;
+		assert_eq!(code, "200");
+		let (code, second, _) = call(&dav, "LOCK", "/dav/a/f%20.txt", &[(hns::DEPTH, "0")], shared).await;
+		assert_eq!(code, "200");
+		let (first, second) = (first.unwrap(), second.unwrap());
+		assert_ne!(first, second);
+		let (_, _, body) = call(&dav, "PROPFIND", "/dav/a/f%20.txt", &[(hns::DEPTH, "0")], "").await;
+		assert_eq!(body.matches("<D:activelock>").count(), 2);
+		assert_eq!(call(&dav, "UNLOCK", "/dav/a/f%20.txt", &[(hns::LOCK_TOKEN, &first)], "").await.0, "204");
+		assert_eq!(call(&dav, "PUT", "/dav/a/f%20.txt", &[], "x").await.0, "423");
+		let (_, _, body) = call(&dav, "PROPFIND", "/dav/a/f%20.txt", &[(hns::DEPTH, "0")], "").await;
+		assert_eq!(body.matches("<D:activelock>").count(), 1);
+		assert!(body.contains(second.trim_matches(|c| c == '<' || c == '>')));
+		assert_eq!(call(&dav, "UNLOCK", "/dav/a/f%20.txt", &[(hns::LOCK_TOKEN, &first)], "").await.0, "409");
+		assert_eq!(call(&dav, "UNLOCK", "/dav/a/f%20.txt", &[(hns::LOCK_TOKEN, &second)], "").await.0, "204");
+
+		let dest = "http://localhost/dav/b.txt";
+		assert_eq!(call(&dav, "MOVE", "/dav/a/f%20.txt", &[(hns::DESTINATION, dest)], "").await.0, "201");
+		assert_eq!(std::fs::read_to_string(root.join("b.txt")).unwrap(), "world");
+
+		let (_, _, body) = call(&dav, "PROPFIND", "/dav/b.txt", &[(hns::DEPTH, "0")], r#"<D:propfind xmlns:D="DAV:"><D:prop><author xmlns="urn:z"/><nope xmlns="urn:z"/></D:prop></D:propfind>"#).await;
+		assert!(body.contains("<author xmlns=\"urn:z\">me</author>"));
+		assert!(body.contains("<nope xmlns=\"urn:z\"/>"));
+
+		assert_eq!(call(&dav, "COPY", "/dav/a", &[(hns::DESTINATION, "/dav/c")], "").await.0, "201");
+		assert_eq!(call(&dav, "COPY", "/dav/b.txt", &[(hns::DESTINATION, "/dav/c"), (hns::OVERWRITE, "F")], "").await.0, "412");
+		assert_eq!(call(&dav, "DELETE", "/dav/c", &[], "").await.0, "204");
+		assert!(!root.join("c").exists());
+
+		assert_eq!(call(&dav, "MKCOL", "/dav/p", &[], "").await.0, "201");
+		assert_eq!(call(&dav, "MKCOL", "/dav/p/q", &[], "").await.0, "201");
+		assert_eq!(call(&dav, "MOVE", "/dav/p/q", &[(hns::DESTINATION, "/dav/p")], "").await.0, "403");
+		assert_eq!(call(&dav, "COPY", "/dav/p/q", &[(hns::DESTINATION, "/dav/p")], "").await.0, "403");
+		assert!(root.join("p/q").is_dir());
+		assert_eq!(call(&dav, "MOVE", "/dav/p/q", &[(hns::DESTINATION, "/dav/p")], "").await.2, "destination overlaps the source");
+		assert_eq!(call(&dav, "MKCOL", "/dav/p", &[], "").await.2, "resource already exists");
+		assert_eq!(call(&dav, "PROPFIND", "/dav/p", &[], "<a").await.2, "bad xml body");
+		assert_eq!(call(&dav, "DELETE", "/dav/nope", &[], "").await.2, "Not Found");
+
+		std::fs::remove_dir_all(&root).unwrap();
+	}
+}
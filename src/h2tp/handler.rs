@@ -31,3 +31,68 @@ impl Handler for FuncHandler {
 		(self.f)(req, resp).await
 	}
 }
+
+/// `ClosureHandler` is a handler of a closure, so the state can be captured. the future can not borrow
+/// from the closure, clone the captured values(`Arc`, channel senders, ...) before the `async move` block,
+/// or use `func!([db, tx], req, resp, { .. })` that does it.
+pub struct ClosureHandler<F> {
+	f: F,
+}
+
+impl<F> ClosureHandler<F>
+where
+	F: for<'a, 'c> Fn(&'a mut Request<'c>, &'a mut Response<'c>) -> HandlerFuture<'a> + Send + Sync,
+{
+	#[inline]
+	pub fn new(f: F) -> Self {
+		return Self { f };
+	}
+}
+
+#[async_trait]
+impl<F> Handler for ClosureHandler<F>
+where
+	F: for<'a, 'c> Fn(&'a mut Request<'c>, &'a mut Response<'c>) -> HandlerFuture<'a> + Send + Sync,
+{
+	#[inline]
+	async fn handle<'a, 'c, 'h: 'a>(&'h self, req: &'a mut Request<'c>, resp: &'a mut Response<'c>) -> () {
+		(self.f)(req, resp).await
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::sync::atomic::{AtomicUsize, Ordering};
+	use std::sync::Arc;
+	use crate::h2tp::handler::{ClosureHandler, Handler};
+	use crate::h2tp::{Request, Response};
+
+	#[tokio::test]
+	async fn test_closure_handler() {
+		let hits = Arc::new(AtomicUsize::new(0));
+		let counter = hits.clone();
+		let total = hits.clone();
+		let name = String::from("spk");
+		let handler = crate::func!([hits, name], req, resp, {
+			let n = hits.fetch_add(1, Ordering::SeqCst) + 1;
+			resp.headers().append("x-hits", format!("{} {} {}", name, req.path(), n).as_str());
+		});
+		let plain = ClosureHandler::new(move |_, _| {
+			let counter = counter.clone();
+			Box::pin(async move {
+				counter.fetch_add(10, Ordering::SeqCst);
+			})
+		});
+
+		for path in ["/a", "/b"] {
+			let mut req = Request::new();
+			req.msg.startline.1.push_str(path);
+			let mut resp = Response::new();
+			handler.handle(&mut req, &mut resp).await;
+			plain.handle(&mut req, &mut resp).await;
+			let v = resp.msg.headers.as_ref().unwrap().get("x-hits").unwrap()[0].clone();
+			assert!(v.starts_with(format!("spk {}", path).as_str()));
+		}
+		assert_eq!(total.load(Ordering::SeqCst), 22);
+	}
+}
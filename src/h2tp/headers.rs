@@ -18,9 +18,10 @@ pub mod hns {
 	pub_str_const!(LAST_MODIFIED, "last-modified");
 	pub_str_const!(IF_MODIFIED_SINCE, "if-modified-since");
 	pub_str_const!(IF_UNMODIFIED_SINCE, "if-unmodified-since");
-	pub_str_const!(E_TAG, "e-tag");
+	pub_str_const!(E_TAG, "etag");
 	pub_str_const!(IF_MATCH, "if-match");
 	pub_str_const!(IF_NONE_MATCH, "if-none-match");
+	pub_str_const!(ACCEPT, "accept");
 	pub_str_const!(ACCEPT_ENCODING, "accept-encoding");
 	pub_str_const!(COOKIE, "cookie");
 	pub_str_const!(SET_COOKIE, "set-cookie");
@@ -32,7 +33,7 @@ pub mod hns {
 	pub_str_const!(USER_AGENT, "user-agent");
 	pub_str_const!(ALLOW, "allow");
 	pub_str_const!(SERVER, "server");
-	pub_str_const!(ACCEPT_RANGE, "accept-range");
+	pub_str_const!(ACCEPT_RANGE, "accept-ranges");
 	pub_str_const!(RANGE, "range");
 	pub_str_const!(IF_RANGE, "if-range");
 	pub_str_const!(CONTENT_RANGE, "content-range");
@@ -44,6 +45,15 @@ pub mod hns {
 	pub_str_const!(DATE, "date");
 	pub_str_const!(RETRY_AFTER, "retry-after");
 	pub_str_const!(UPGRADE, "upgrade");
+	pub_str_const!(VARY, "vary");
+	pub_str_const!(CACHE_CONTROL, "cache-control");
+	pub_str_const!(DAV, "dav");
+	pub_str_const!(DEPTH, "depth");
+	pub_str_const!(DESTINATION, "destination");
+	pub_str_const!(OVERWRITE, "overwrite");
+	pub_str_const!(IF, "if");
+	pub_str_const!(LOCK_TOKEN, "lock-token");
+	pub_str_const!(TIMEOUT, "timeout");
 }
 
 pub mod mime {
@@ -53,20 +63,81 @@ pub mod mime {
 	pub_str_const!(CSS, "text/css");
 	pub_str_const!(HTML, "text/html");
 	pub_str_const!(JAVASCRIPT, "text/javascript");
+	pub_str_const!(MARKDOWN, "text/markdown");
+	pub_str_const!(CSV, "text/csv");
+	pub_str_const!(XML, "text/xml");
 
 	pub_str_const!(GIF, "image/gif");
 	pub_str_const!(JPEG, "image/jpeg");
 	pub_str_const!(PNG, "image/png");
 	pub_str_const!(SVG, "image/svg+xml");
 	pub_str_const!(WEBP, "image/webp");
+	pub_str_const!(ICO, "image/x-icon");
+	pub_str_const!(AVIF, "image/avif");
 
 	pub_str_const!(JSON, "application/json");
 	pub_str_const!(WWW_FORM_URLENCODED, "application/x-www-form-urlencoded");
 	pub_str_const!(MULTIPART_FORM, "multipart/form-data");
+	pub_str_const!(PDF, "application/pdf");
+	pub_str_const!(WASM, "application/wasm");
+	pub_str_const!(ZIP, "application/zip");
+	pub_str_const!(MANIFEST, "application/manifest+json");
 
 	pub_str_const!(WOFF, "font/woff");
+	pub_str_const!(WOFF2, "font/woff2");
+	pub_str_const!(TTF, "font/ttf");
+
+	pub_str_const!(MP3, "audio/mpeg");
+	pub_str_const!(MP4, "video/mp4");
+	pub_str_const!(WEBM, "video/webm");
+
+	/// `by_ext` return the mime type of a file extension(without the leading dot),
+	/// unknown extensions are treated as `STREAM`.
+	pub fn by_ext(ext: &str) -> &'static str {
+		return match ext.to_ascii_lowercase().as_str() {
+			"txt" | "text" | "log" => TEXT,
+			"css" => CSS,
+			"html" | "htm" => HTML,
+			"js" | "mjs" => JAVASCRIPT,
+			"md" | "markdown" => MARKDOWN,
+			"csv" => CSV,
+			"xml" => XML,
+			"gif" => GIF,
+			"jpg" | "jpeg" => JPEG,
+			"png" => PNG,
+			"svg" => SVG,
+			"webp" => WEBP,
+			"ico" => ICO,
+			"avif" => AVIF,
+			"json" | "map" => JSON,
+			"pdf" => PDF,
+			"wasm" => WASM,
+			"zip" => ZIP,
+			"webmanifest" => MANIFEST,
+			"woff" => WOFF,
+			"woff2" => WOFF2,
+			"ttf" => TTF,
+			"mp3" => MP3,
+			"mp4" => MP4,
+			"webm" => WEBM,
+			_ => STREAM,
+		};
+	}
+
+	/// `by_path` return the mime type by the extension of the last segment in `path`.
+	pub fn by_path(path: &str) -> &'static str {
+		let name = match path.rfind('/') {
+			Some(idx) => &path[idx + 1..],
+			None => path,
+		};
+		return match name.rfind('.') {
+			Some(idx) => by_ext(&name[idx + 1..]),
+			None => STREAM,
+		};
+	}
 }
 
+#[derive(Clone)]
 pub struct Headers {
 	pub(crate) m: MultiMap,
 }
@@ -121,6 +192,14 @@ impl Headers {
 		return Builder { headers: self };
 	}
 
+	pub fn get(&self, k: &str) -> Option<&Vec<String>> {
+		return self.m.get(k);
+	}
+
+	pub fn getone(&self, k: &str) -> Option<&String> {
+		return self.m.getone(k);
+	}
+
 	pub fn content_length(&self) -> Option<usize> {
 		match self.m.getone(hns::CONTENT_LENGTH) {
 			Some(v) => {
@@ -144,6 +223,102 @@ impl Headers {
 
 	getone!(host -> hns::HOST);
 
+	getone!(accept_encoding -> hns::ACCEPT_ENCODING);
+
+	getone!(if_none_match -> hns::IF_NONE_MATCH);
+
+	/// `accepts_encoding` check if the `accept-encoding` allows the content-coding `enc`.
+	/// codings with `q=0` are refused, and `*` matches any coding that not listed explicitly.
+	pub fn accepts_encoding(&self, enc: &str) -> bool {
+		let mut wildcard: Option<bool> = None;
+		match self.m.get(hns::ACCEPT_ENCODING) {
+			Some(vals) => {
+				for val in vals {
+					for item in val.split(',') {
+						let mut parts = item.split(';');
+						let name = parts.next().unwrap_or("").trim();
+						let mut accepted = true;
+						for param in parts {
+							let param = param.trim();
+							if param.starts_with("q=") || param.starts_with("Q=") {
+								accepted = match param[2..].trim().parse::<f32>() {
+									Ok(q) => q > 0.0,
+									Err(_) => false,
+								};
+							}
+						}
+						if name.eq_ignore_ascii_case(enc) {
+							return accepted;
+						}
+						if name == "*" {
+							wildcard = Some(accepted);
+						}
+					}
+				}
+			}
+			None => {}
+		}
+		return wildcard.unwrap_or(false);
+	}
+
+	/// `preferred` return the media type in `candidates` that the `accept` header prefers, the earlier one
+	/// wins if the qualities are equal. return the first candidate if there is no `accept` header.
+	pub fn preferred<'a>(&self, candidates: &[&'a str]) -> Option<&'a str> {
+		let vals = match self.m.get(hns::ACCEPT) {
+			Some(vals) => vals,
+			None => {
+				return candidates.first().copied();
+			}
+		};
+		let mut best: Option<(&'a str, f32)> = None;
+		for candidate in candidates {
+			let (ctype, _) = candidate.split_once('/').unwrap_or((candidate, ""));
+			// (specificity, q) of the most specific media range that matches
+			let mut matched: Option<(u8, f32)> = None;
+			for val in vals {
+				for item in val.split(',') {
+					let mut parts = item.split(';');
+					let range = parts.next().unwrap_or("").trim();
+					let mut q: f32 = 1.0;
+					for param in parts {
+						let param = param.trim();
+						if param.starts_with("q=") || param.starts_with("Q=") {
+							q = param[2..].trim().parse::<f32>().unwrap_or(0.0);
+						}
+					}
+					let specificity = if range.eq_ignore_ascii_case(candidate) {
+						2
+					} else if range == "*/*" {
+						0
+					} else {
+						match range.split_once('/') {
+							Some((t, "*")) if t.eq_ignore_ascii_case(ctype) => 1,
+							_ => {
+								continue;
+							}
+						}
+					};
+					match matched {
+						Some((s, _)) if s >= specificity => {}
+						_ => {
+							matched = Some((specificity, q));
+						}
+					}
+				}
+			}
+			match matched {
+				Some((_, q)) if q > 0.0 => match best {
+					Some((_, bq)) if bq >= q => {}
+					_ => {
+						best = Some((candidate, q));
+					}
+				},
+				_ => {}
+			}
+		}
+		return best.map(|(v, _)| v);
+	}
+
 	pub fn is_chunked(&self) -> bool {
 		return match self.transfer_encoding() {
 			Some(v) => v.contains("chunked"),
@@ -151,8 +326,45 @@ impl Headers {
 		};
 	}
 
-	pub fn range(&self) -> Option<(String, usize, usize)> {
-		return None;
+	getone!(if_range -> hns::IF_RANGE);
+
+	/// `range` return the unit and the bounds of the `range` header, the missing bound is `None`.
+	/// - `bytes=0-99` => `("bytes", Some(0), Some(99))`
+	/// - `bytes=100-` => `("bytes", Some(100), None)`
+	/// - `bytes=-100` => `("bytes", None, Some(100))`, the last 100 bytes
+	///
+	/// multiple ranges are not supported, `None` will be returned.
+	pub fn range(&self) -> Option<(String, Option<u64>, Option<u64>)> {
+		let v = self.m.getone(hns::RANGE)?;
+		let (unit, spec) = v.split_once('=')?;
+		if spec.contains(',') {
+			return None;
+		}
+		let (begin, end) = spec.trim().split_once('-')?;
+		let begin = begin.trim();
+		let end = end.trim();
+
+		macro_rules! bound {
+			($v:ident) => {
+				if $v.is_empty() {
+					None
+				} else {
+					match $v.parse::<u64>() {
+						Ok(num) => Some(num),
+						Err(_) => {
+							return None;
+						}
+					}
+				}
+			};
+		}
+
+		let begin = bound!(begin);
+		let end = bound!(end);
+		if begin.is_none() && end.is_none() {
+			return None;
+		}
+		return Some((unit.trim().to_string(), begin, end));
 	}
 }
 
@@ -1,16 +1,24 @@
-use crate::h2tp::cfg::MESSAGE_BUFFER_SIZE;
+use crate::h2tp::cfg::{MAX_BUFFERED_BODY_SIZE, MAX_DISCARDED_BODY_SIZE, MESSAGE_BUFFER_SIZE};
+use crate::h2tp::error::Error;
 use crate::h2tp::headers;
 use crate::h2tp::headers::Headers;
+use crate::h2tp::status_code::StatusCode;
 use bytes::BytesMut;
 use std::fmt;
 use std::fmt::Formatter;
 use std::fmt::Write;
 use std::io::ErrorKind;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
 use tokio::io::AsyncReadExt;
 use tokio::io::AsyncWriteExt;
+use tokio::io::{AsyncRead, ReadBuf};
+use tokio::net::TcpStream;
 
 use super::conn::ConnStatus;
+use super::response::RespBody;
 use super::types::{AsyncReader, AsyncWriter};
+use super::zerocopy;
 
 pub struct Message<'c> {
 	pub(crate) startline: (String, String, String),
@@ -22,6 +30,10 @@ pub struct Message<'c> {
 	buf: Option<BytesMut>,
 	bufsize: usize,
 	bufremains: usize,
+	/// the connection reader, attached by the server, so the large bodies can be streamed.
+	stream: Option<&'c mut (dyn AsyncReader + Sync + 'c)>,
+	/// the size of the body that still in the connection.
+	body_remains: usize,
 }
 
 #[derive(PartialEq)]
@@ -64,6 +76,11 @@ impl ParseError {
 		return self.ioe.is_none() && self.ue.is_none();
 	}
 
+	/// `is_bad_request` return true if the message is malformed, rather than the connection is broken.
+	pub fn is_bad_request(&self) -> bool {
+		return self.ue.is_some();
+	}
+
 	pub fn is_eof(&self) -> bool {
 		return match self.ioe.as_ref() {
 			Some(v) => v.kind() == ErrorKind::UnexpectedEof,
@@ -92,6 +109,16 @@ impl fmt::Debug for ParseError {
 	}
 }
 
+impl From<ParseError> for Error {
+	fn from(e: ParseError) -> Self {
+		return match (e.ioe, e.ue) {
+			(Some(ioe), _) => Error::from(ioe),
+			(None, Some(ue)) => Error::newstatic(StatusCode::BadRequest, ue),
+			(None, None) => Error::newstatic(StatusCode::BadRequest, BAD_REQUEST),
+		};
+	}
+}
+
 const BAD_REQUEST: &str = "bad request";
 
 impl<'c> Message<'c> {
@@ -104,9 +131,15 @@ impl<'c> Message<'c> {
 			conn: None,
 			bufsize: 0,
 			bufremains: 0,
+			stream: None,
+			body_remains: 0,
 		};
 	}
 
+	pub(crate) fn attach(&mut self, stream: &'c mut (dyn AsyncReader + Sync + 'c)) {
+		self.stream = Some(stream);
+	}
+
 	pub(crate) fn clear(&mut self) {
 		self.startline.0.clear();
 		self.startline.1.clear();
@@ -119,6 +152,7 @@ impl<'c> Message<'c> {
 		}
 		self.bufremains = 0;
 		self.bufsize = 0;
+		self.body_remains = 0;
 		match self.body.as_mut() {
 			Some(bodyref) => {
 				bodyref.clear();
@@ -177,8 +211,8 @@ impl<'c> Message<'c> {
 				bodyref.extend_from_slice(bytesslice);
 			} else {
 				self.bufremains -= remain;
-				remain = 0;
 				bodyref.extend_from_slice(&bytesslice[0..remain]);
+				remain = 0;
 			}
 
 			if remain == 0 {
@@ -295,7 +329,9 @@ impl<'c> Message<'c> {
 		return None;
 	}
 
-	pub(crate) async fn read_body(&mut self, stream: &mut dyn AsyncReader) -> Option<ParseError> {
+	/// `read_body` read the body into memory, if `defer` is true, the sized body larger than
+	/// `MAX_BUFFERED_BODY_SIZE` is left in the connection and can be read by the `BodyReader`.
+	pub(crate) async fn read_body(&mut self, stream: &mut dyn AsyncReader, defer: bool) -> Option<ParseError> {
 		let mut cl: Option<usize> = None;
 		match &self.headers {
 			Some(href) => {
@@ -305,6 +341,9 @@ impl<'c> Message<'c> {
 		}
 
 		match cl {
+			Some(cl) if defer && cl > MAX_BUFFERED_BODY_SIZE => {
+				self.body_remains = cl;
+			}
 			Some(cl) => {
 				if self.body.is_none() {
 					let buf = BytesMut::with_capacity(cl);
@@ -353,6 +392,10 @@ impl<'c> Message<'c> {
 	}
 
 	pub(crate) async fn from(&mut self, stream: &mut dyn AsyncReader) -> Option<ParseError> {
+		return self.parse(stream, false).await;
+	}
+
+	async fn parse(&mut self, stream: &mut dyn AsyncReader, defer: bool) -> Option<ParseError> {
 		self.ensurebuf();
 		let mut status: ParseStatus = ParseStatus::Empty;
 		let mut skip_newline = false;
@@ -452,7 +495,44 @@ impl<'c> Message<'c> {
 				break;
 			}
 		}
-		return self.read_body(stream).await;
+		return self.read_body(stream, defer).await;
+	}
+
+	/// `from_attached` read the message from the attached stream, see `read_body` for `defer`.
+	pub(crate) async fn from_attached(&mut self, defer: bool) -> Option<ParseError> {
+		let stream = match self.stream.take() {
+			Some(v) => v,
+			None => {
+				return Some(ParseError::ue("no attached stream"));
+			}
+		};
+		let result = self.parse(&mut *stream, defer).await;
+		self.stream = Some(stream);
+		return result;
+	}
+
+	/// `body_unread` return the size of the body that still in the connection.
+	pub(crate) fn body_unread(&self) -> usize {
+		return self.body_remains;
+	}
+
+	/// `discard_body` drop the body that not read by the handler, so the next message can be parsed.
+	/// the body larger than `MAX_DISCARDED_BODY_SIZE` is not drained, an error is returned and the connection should be closed.
+	pub(crate) async fn discard_body(&mut self) -> Option<ParseError> {
+		if self.body_remains == 0 {
+			return None;
+		}
+		if self.body_remains > MAX_DISCARDED_BODY_SIZE {
+			return Some(ParseError::ue("too large unread body"));
+		}
+		let mut reader = BodyReader {
+			msg: self,
+			pos: usize::MAX,
+		};
+		return match tokio::io::copy(&mut reader, &mut tokio::io::sink()).await {
+			Ok(_) => None,
+			Err(e) => Some(ParseError::ioe(e)),
+		};
 	}
 
 	pub(crate) fn headers_builder(&mut self) -> headers::Builder {
@@ -464,7 +544,20 @@ impl<'c> Message<'c> {
 }
 
 impl<'c> Message<'c> {
-	pub(crate) async fn to(&mut self, stream: &mut dyn AsyncWriter) {
+	/// `to` write the message to `stream`, the `extbody` will be sent after the headers
+	/// if `self.body` is empty.
+	/// `tcp` is the underlying stream of `stream` if it is a plaintext tcp connection,
+	/// the file bodies will be sent to it by zero-copy.
+	/// an error is returned if the file or static body is not sent completely, the connection should be closed.
+	/// `head` is `true` for the response of a `HEAD` request, the headers are written as the `GET` response,
+	/// but the body is not.
+	pub(crate) async fn to(
+		&mut self,
+		stream: &mut dyn AsyncWriter,
+		extbody: Option<&mut RespBody>,
+		tcp: Option<&TcpStream>,
+		head: bool,
+	) -> std::io::Result<()> {
 		self.ensurebuf();
 		let bufref = self.buf.as_mut().unwrap();
 		bufref.clear();
@@ -500,9 +593,44 @@ impl<'c> Message<'c> {
 			None => {}
 		}
 
+		// the `1xx`, `204` and `304` responses have no body, and no content-length.
+		let bodyless = match self.startline.1.as_str() {
+			"204" | "304" => true,
+			v => v.len() == 3 && v.starts_with('1'),
+		};
+
+		let mut extbody = extbody;
+		if content_length > 0 || bodyless {
+			extbody = None;
+		}
+		if bodyless {
+			content_length = 0;
+		}
+		match extbody.as_mut() {
+			Some(RespBody::File(file)) => match file.metadata().await {
+				Ok(meta) => {
+					content_length = meta.len() as usize;
+				}
+				Err(_) => {
+					extbody = None;
+				}
+			},
+			Some(RespBody::FilePart(_, size)) => {
+				content_length = *size as usize;
+			}
+			Some(RespBody::Static(data)) => {
+				content_length = data.len();
+			}
+			None => {}
+		}
+
 		match self.headers.as_mut() {
 			Some(headers) => {
-				headers.builder().content_length(content_length);
+				if bodyless {
+					headers.m.remove(headers::hns::CONTENT_LENGTH);
+				} else {
+					headers.builder().content_length(content_length);
+				}
 
 				headers.m.each(|k, v, _| {
 					writestr!(k);
@@ -512,18 +640,34 @@ impl<'c> Message<'c> {
 					return true;
 				});
 			}
+			None if bodyless => {}
 			None => {
-				if content_length > 0 {
-					writestr!("content-length: ");
-					writestr!(content_length.to_string().as_str());
-					writestr!("\r\n");
-				}
+				writestr!("content-length: ");
+				writestr!(content_length.to_string().as_str());
+				writestr!("\r\n");
 			}
 		}
 		writestr!("\r\n");
 
-		if content_length < 1 {
+		if content_length < 1 || head {
 			let _ = stream.write(bufref).await;
+		} else if extbody.is_some() {
+			stream.write_all(bufref).await?;
+			match extbody.unwrap() {
+				RespBody::File(file) => {
+					if !Self::sendfile(stream, tcp, file, content_length as u64).await? {
+						Self::copyfile(stream, file, content_length as u64).await?;
+					}
+				}
+				RespBody::FilePart(file, size) => {
+					if !Self::sendfile(stream, tcp, file, *size).await? {
+						Self::copyfile(stream, file, *size).await?;
+					}
+				}
+				RespBody::Static(data) => {
+					stream.write_all(data).await?;
+				}
+			}
 		} else {
 			let body = self.body.as_mut().unwrap();
 			if content_length < 4096 {
@@ -536,5 +680,190 @@ impl<'c> Message<'c> {
 		}
 
 		let _ = stream.flush().await;
+		return Ok(());
+	}
+
+	/// `sendfile` return `Ok(true)` if the file has been sent by zero-copy,
+	/// `Ok(false)` if nothing has been sent and the caller should copy it by itself.
+	/// an error means the body is incomplete, and the connection can not be reused.
+	async fn sendfile(
+		stream: &mut dyn AsyncWriter,
+		tcp: Option<&TcpStream>,
+		file: &mut tokio::fs::File,
+		count: u64,
+	) -> std::io::Result<bool> {
+		let tcp = match tcp {
+			Some(v) => v,
+			None => {
+				return Ok(false);
+			}
+		};
+		stream.flush().await?;
+		return zerocopy::sendfile(tcp, file, count).await;
+	}
+
+	/// `copyfile` copy `count` bytes of the file to `stream`,
+	/// an error is returned if the file is shorter than `count`.
+	async fn copyfile(stream: &mut dyn AsyncWriter, file: &mut tokio::fs::File, count: u64) -> std::io::Result<()> {
+		let n = tokio::io::copy(&mut file.take(count), stream).await?;
+		if n < count {
+			return Err(std::io::Error::new(ErrorKind::UnexpectedEof, "file is shorter than expected"));
+		}
+		return Ok(());
+	}
+}
+
+/// `BodyReader` read the request body, the buffered part first, then the part that still in the connection.
+pub struct BodyReader<'m, 'c> {
+	msg: &'m mut Message<'c>,
+	pos: usize,
+}
+
+impl<'m, 'c> BodyReader<'m, 'c> {
+	pub(crate) fn new(msg: &'m mut Message<'c>) -> Self {
+		return Self { msg, pos: 0 };
+	}
+
+	/// `remains` return the size of the body that not read yet.
+	pub fn remains(&self) -> usize {
+		let buffered = match self.msg.body.as_ref() {
+			Some(body) if self.pos < body.len() => body.len() - self.pos,
+			_ => 0,
+		};
+		return buffered + self.msg.body_remains;
+	}
+}
+
+impl<'m, 'c> AsyncRead for BodyReader<'m, 'c> {
+	fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, out: &mut ReadBuf<'_>) -> Poll<std::io::Result<()>> {
+		let this = &mut *self;
+		match this.msg.body.as_ref() {
+			Some(body) if this.pos < body.len() => {
+				let n = std::cmp::min(out.remaining(), body.len() - this.pos);
+				out.put_slice(&body[this.pos..this.pos + n]);
+				this.pos += n;
+				return Poll::Ready(Ok(()));
+			}
+			_ => {}
+		}
+
+		let msg = &mut *this.msg;
+		if msg.body_remains == 0 || out.remaining() == 0 {
+			return Poll::Ready(Ok(()));
+		}
+
+		if msg.bufremains > 0 {
+			let n = std::cmp::min(std::cmp::min(out.remaining(), msg.bufremains), msg.body_remains);
+			let begin = msg.bufsize - msg.bufremains;
+			out.put_slice(&msg.buf.as_ref().unwrap()[begin..begin + n]);
+			msg.bufremains -= n;
+			msg.body_remains -= n;
+			return Poll::Ready(Ok(()));
+		}
+
+		let stream = match msg.stream.as_mut() {
+			Some(v) => v,
+			None => {
+				return Poll::Ready(Err(std::io::Error::new(ErrorKind::UnexpectedEof, "no attached stream")));
+			}
+		};
+		let limit = std::cmp::min(out.remaining(), msg.body_remains);
+		let mut sub = out.take(limit);
+		ready!(Pin::new(&mut **stream).poll_read(cx, &mut sub))?;
+		let n = sub.filled().len();
+		if n == 0 {
+			return Poll::Ready(Err(std::io::Error::new(ErrorKind::UnexpectedEof, "incomplete body")));
+		}
+		unsafe {
+			out.assume_init(n);
+		}
+		out.advance(n);
+		msg.body_remains -= n;
+		return Poll::Ready(Ok(()));
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::h2tp::cfg::{MAX_BUFFERED_BODY_SIZE, MAX_DISCARDED_BODY_SIZE};
+	use crate::h2tp::message::{BodyReader, Message};
+	use crate::h2tp::response::RespBody;
+	use crate::h2tp::{hns, Response, StatusCode};
+	use tokio::io::AsyncReadExt;
+
+	#[tokio::test]
+	async fn test_defer_body() {
+		let size = MAX_BUFFERED_BODY_SIZE + 1;
+		let mut raw = format!("PUT / HTTP/1.1\r\ncontent-length: {size}\r\n\r\n").into_bytes();
+		raw.resize(raw.len() + size, b'a');
+
+		let mut stream: &[u8] = raw.as_slice();
+		let mut msg = Message::new();
+		msg.attach(&mut stream);
+		assert!(msg.from_attached(false).await.is_none());
+		assert_eq!(msg.body.as_ref().unwrap().len(), size);
+		assert_eq!(msg.body_unread(), 0);
+
+		let mut stream: &[u8] = raw.as_slice();
+		let mut msg = Message::new();
+		msg.attach(&mut stream);
+		assert!(msg.from_attached(true).await.is_none());
+		assert!(msg.body.as_ref().is_none_or(|b| b.is_empty()));
+		assert!(msg.body_unread() > MAX_DISCARDED_BODY_SIZE);
+		assert!(msg.discard_body().await.is_some());
+
+		// the partially read body is drained if the rest is small.
+		let mut stream: &[u8] = raw.as_slice();
+		let mut msg = Message::new();
+		msg.attach(&mut stream);
+		assert!(msg.from_attached(true).await.is_none());
+		let mut head = Vec::new();
+		let n = (&mut BodyReader::new(&mut msg)).take((size - 10) as u64).read_to_end(&mut head).await.unwrap();
+		assert_eq!(n, size - 10);
+		assert_eq!(msg.body_unread(), 10);
+		assert!(msg.discard_body().await.is_none());
+		assert_eq!(msg.body_unread(), 0);
+	}
+
+	#[tokio::test]
+	async fn test_bodyless_status() {
+		let mut resp = Response::new();
+		resp.status(StatusCode::NotModified);
+		resp.headers().reset(hns::E_TAG, "\"x\"").content_length(5);
+		let mut out: Vec<u8> = vec![];
+		assert!(resp.msg.to(&mut out, None, None, false).await.is_ok());
+		assert_eq!(String::from_utf8(out).unwrap(), "HTTP/1.1 304 Not Modified\r\netag: \"x\"\r\n\r\n");
+
+		let mut resp = Response::new();
+		resp.status(StatusCode::NoContent);
+		let mut out: Vec<u8> = vec![];
+		assert!(resp.msg.to(&mut out, None, None, false).await.is_ok());
+		assert_eq!(String::from_utf8(out).unwrap(), "HTTP/1.1 204 No Content\r\n\r\n");
+
+		let mut resp = Response::new();
+		let mut out: Vec<u8> = vec![];
+		assert!(resp.msg.to(&mut out, None, None, false).await.is_ok());
+		assert_eq!(String::from_utf8(out).unwrap(), "HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n");
+	}
+
+	#[tokio::test]
+	async fn test_truncated_file() {
+		let path = std::env::temp_dir().join(format!("spk-truncated-{}", std::process::id()));
+		std::fs::write(&path, "hello").unwrap();
+
+		let mut body = RespBody::FilePart(tokio::fs::File::open(&path).await.unwrap(), 10);
+		let mut resp = Response::new();
+		let mut out: Vec<u8> = vec![];
+		let err = resp.msg.to(&mut out, Some(&mut body), None, false).await.unwrap_err();
+		assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
+		assert!(String::from_utf8(out).unwrap().ends_with("content-length: 10\r\n\r\nhello"));
+
+		let mut body = RespBody::FilePart(tokio::fs::File::open(&path).await.unwrap(), 5);
+		let mut resp = Response::new();
+		let mut out: Vec<u8> = vec![];
+		assert!(resp.msg.to(&mut out, Some(&mut body), None, false).await.is_ok());
+		assert!(String::from_utf8(out).unwrap().ends_with("content-length: 5\r\n\r\nhello"));
+
+		std::fs::remove_file(&path).unwrap();
 	}
 }
@@ -6,3 +6,12 @@ pub const DELETE: &str = "DELETE";
 pub const CONNECT: &str = "CONNECT";
 pub const TRACE: &str = "TRACE";
 pub const PATCH: &str = "PATCH";
+pub const OPTIONS: &str = "OPTIONS";
+
+pub const PROPFIND: &str = "PROPFIND";
+pub const PROPPATCH: &str = "PROPPATCH";
+pub const MKCOL: &str = "MKCOL";
+pub const COPY: &str = "COPY";
+pub const MOVE: &str = "MOVE";
+pub const LOCK: &str = "LOCK";
+pub const UNLOCK: &str = "UNLOCK";
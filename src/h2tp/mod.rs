@@ -4,9 +4,12 @@ use tokio::sync::Mutex;
 mod cfg;
 mod conn;
 mod error;
+mod extensions;
+mod extract;
 mod handler;
 mod headers;
 mod message;
+mod multipart;
 mod methods;
 mod request;
 mod response;
@@ -16,7 +19,10 @@ mod types;
 mod url;
 mod utils;
 mod router;
+mod radix;
+mod vhost;
 mod fs;
+mod zerocopy;
 
 pub async fn shutdown(handler: &Arc<Mutex<server::ShutdownHandler>>, timout: u64) {
 	let mut guard = handler.lock().await;
@@ -31,27 +37,74 @@ pub fn server() -> server::Server {
 	return server::Server::new();
 }
 
-pub use handler::FuncHandler;
+pub use handler::{ClosureHandler, FuncHandler, Handler, HandlerFuture};
+pub use error::{DefaultErrorFormatter, Error, ErrorFormatter};
+pub use extensions::Extensions;
+pub use extract::{Extension, Extract, ExtractFn, Form, FromParams, FromRequest, Path, Query, State};
+#[cfg(feature = "json")]
+pub use extract::Json;
 pub use request::Request;
-pub use response::Response;
+pub use url::{ParseErr, Url};
+pub use utils::formed::{Charset, Encoder as FormEncoder, FormedError};
+pub use utils::multi_map::MultiMap;
+pub use response::{IntoResponse, Redirect, Response};
 pub use methods::*;
 pub use headers::hns;
+pub use headers::Headers;
+pub use headers::mime;
+pub use status_code::StatusCode;
+pub use router::{Middleware, MiddlewareControl, Next, Router, RouterFindError};
+pub use radix::{Group, RadixRouter, Route, RouteError, TrailingSlash, ANY_METHOD};
+pub use vhost::{HostRouter, SUBDOMAIN_PARAM};
+pub use fs::read::{Readable, SimpleOsReader};
+pub use fs::embed::{EmbeddedFile, EmbeddedReader};
+pub use fs::webdav::WebDav;
+pub use fs::upload::Uploader;
+pub use message::BodyReader;
+pub use multipart::{Multipart, Part};
+pub use procmacro::{delete, embed_dir, get, head, options, patch, post, put, route, Handler};
+#[doc(hidden)]
+pub use async_trait::async_trait;
 
+/// `func!` make a handler of the block, `func!([a, b], req, resp, { .. })` move the variables into the
+/// handler and clone them for each request.
 #[macro_export]
 macro_rules! func {
+	([$($cap:ident),* $(,)?], $req:pat, $resp:pat, $content:expr) => {
+		$crate::h2tp::ClosureHandler::new(move |$req, $resp| {
+			$(let $cap = $cap.clone();)*
+			std::boxed::Box::pin(async move { $content })
+		})
+	};
 	($content:expr) => {
-		crate::h2tp::FuncHandler::new(|_, _| std::boxed::Box::pin(async move { $content }))
+		$crate::h2tp::FuncHandler::new(|_, _| std::boxed::Box::pin(async move { $content }))
 	};
 	(_, _, $content:expr) => {
-		crate::h2tp::FuncHandler::new(|_, _| std::boxed::Box::pin(async move { $content }))
+		$crate::h2tp::FuncHandler::new(|_, _| std::boxed::Box::pin(async move { $content }))
 	};
 	($req:ident, _, $content:expr) => {
-		crate::h2tp::FuncHandler::new(|$req, _| std::boxed::Box::pin(async move { $content }))
+		$crate::h2tp::FuncHandler::new(|$req, _| std::boxed::Box::pin(async move { $content }))
 	};
 	(_, $resp:ident, $content:expr) => {
-		crate::h2tp::FuncHandler::new(|_, $resp| std::boxed::Box::pin(async move { $content }))
+		$crate::h2tp::FuncHandler::new(|_, $resp| std::boxed::Box::pin(async move { $content }))
 	};
 	($req:ident, $resp:ident, $content:expr) => {
-		crate::h2tp::FuncHandler::new(|$req, $resp| std::boxed::Box::pin(async move { $content }))
+		$crate::h2tp::FuncHandler::new(|$req, $resp| std::boxed::Box::pin(async move { $content }))
 	};
 }
+
+/// `routes![a, b]` build a `RadixRouter` from the handlers that declared by the route attributes(`#[get(..)]` ...),
+/// it panics if the routes conflict.
+#[macro_export]
+macro_rules! routes {
+	($($route:expr),* $(,)?) => {{
+		let mut router = $crate::h2tp::RadixRouter::new();
+		$(
+			match router.route($route) {
+				Ok(_) => {}
+				Err(e) => panic!("routes!: {}", e),
+			}
+		)*
+		router
+	}};
+}
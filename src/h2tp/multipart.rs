@@ -0,0 +1,535 @@
+use std::future::poll_fn;
+use std::io::{Error, ErrorKind};
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use tokio::io::{AsyncRead, ReadBuf};
+
+use crate::h2tp::headers::Headers;
+use crate::h2tp::utils::formed::Charset;
+use crate::h2tp::utils::uricoding;
+
+use super::types::AsyncReader;
+
+const READ_SIZE: usize = 8192;
+const MAX_PART_HEADERS_SIZE: usize = 8192;
+const MAX_PARTS: usize = 1000;
+const MAX_FIELD_SIZE: u64 = 1024 * 1024;
+
+/// `Part` is the headers of a multipart/form-data part, the content is read by `Multipart::read`
+/// or `Multipart::reader`.
+pub struct Part {
+	pub name: String,
+	/// the `filename*`(RFC 5987) wins over the `filename`.
+	pub filename: Option<String>,
+	pub content_type: Option<String>,
+	/// all the headers of the part, the names are lowercase.
+	pub headers: Headers,
+}
+
+#[derive(PartialEq)]
+enum State {
+	Preamble,
+	Content,
+	Delimiter,
+	Done,
+}
+
+/// `Multipart` is a streaming multipart/form-data(RFC 7578) parser, the parts are read one by one
+/// and the contents are never buffered entirely. the reader can be a buffered body(`&[u8]`) or
+/// a `BodyReader`, see `Request::multipart`.
+///
+/// the `FileTooLarge` error is returned if there are too many parts or a part is too large.
+pub struct Multipart<'r> {
+	reader: Box<dyn AsyncReader + 'r>,
+	/// `\r\n--boundary`
+	delimiter: Vec<u8>,
+	buf: Vec<u8>,
+	pos: usize,
+	state: State,
+
+	parts: usize,
+	/// the size of the content that read of the current part, and the limit of it.
+	part_size: u64,
+	part_limit: u64,
+	max_parts: usize,
+	max_headers_size: usize,
+	max_field_size: u64,
+	max_file_size: u64,
+}
+
+/// `boundary` return the boundary parameter of a multipart content-type.
+pub fn boundary(content_type: &str) -> Option<String> {
+	let (mime, params) = content_type.split_once(';')?;
+	if !mime.trim().to_ascii_lowercase().starts_with("multipart/") {
+		return None;
+	}
+	let boundary = param(params, "boundary")?;
+	if boundary.is_empty() || boundary.len() > 70 {
+		return None;
+	}
+	return Some(boundary);
+}
+
+/// `param` return the value of the parameter `key` in `k1=v1; k2="v2"`, the quoted values are unescaped.
+pub(crate) fn param(params: &str, key: &str) -> Option<String> {
+	let mut rest = params;
+	loop {
+		rest = rest.trim_start_matches(|c: char| c == ';' || c.is_whitespace());
+		if rest.is_empty() {
+			return None;
+		}
+		let (k, after) = match rest.split_once('=') {
+			Some(v) => v,
+			None => {
+				return None;
+			}
+		};
+		let after = after.trim_start();
+		let value: String;
+		if let Some(quoted) = after.strip_prefix('"') {
+			let mut v = String::new();
+			let mut escaped = false;
+			let mut end = None;
+			for (idx, c) in quoted.char_indices() {
+				if escaped {
+					v.push(c);
+					escaped = false;
+				} else if c == '\\' {
+					escaped = true;
+				} else if c == '"' {
+					end = Some(idx);
+					break;
+				} else {
+					v.push(c);
+				}
+			}
+			rest = &quoted[end? + 1..];
+			value = v;
+		} else {
+			let end = after.find(';').unwrap_or(after.len());
+			value = after[..end].trim().to_string();
+			rest = &after[end..];
+		}
+		if k.trim().eq_ignore_ascii_case(key) {
+			return Some(value);
+		}
+	}
+}
+
+/// `ext_value` decode the RFC 5987 `charset'language'value`, the charset can be `utf-8` or `iso-8859-1`.
+fn ext_value(v: &str) -> Option<String> {
+	let mut items = v.splitn(3, '\'');
+	let charset = Charset::from_label(items.next()?)?;
+	let _language = items.next()?;
+	let mut buf = vec![];
+	if !uricoding::decode_uri_component(&mut buf, items.next()?) {
+		return None;
+	}
+	return charset.decode(&buf);
+}
+
+fn bad(msg: &'static str) -> Error {
+	return Error::new(ErrorKind::InvalidData, msg);
+}
+
+fn too_large(msg: &'static str) -> Error {
+	return Error::new(ErrorKind::FileTooLarge, msg);
+}
+
+impl<'r> Multipart<'r> {
+	pub fn new<R: AsyncReader + 'r>(reader: R, boundary: &str) -> Self {
+		let mut delimiter = Vec::with_capacity(boundary.len() + 4);
+		delimiter.extend_from_slice(b"\r\n--");
+		delimiter.extend_from_slice(boundary.as_bytes());
+		return Self {
+			reader: Box::new(reader),
+			delimiter,
+			// so the first boundary, which has no leading CRLF, matches the delimiter too.
+			buf: b"\r\n".to_vec(),
+			pos: 0,
+			state: State::Preamble,
+			parts: 0,
+			part_size: 0,
+			part_limit: u64::MAX,
+			max_parts: MAX_PARTS,
+			max_headers_size: MAX_PART_HEADERS_SIZE,
+			max_field_size: MAX_FIELD_SIZE,
+			max_file_size: u64::MAX,
+		};
+	}
+
+	/// `set_max_parts` set the max count of the parts, the default is 1000.
+	pub fn set_max_parts(&mut self, v: usize) -> &mut Self {
+		self.max_parts = v;
+		return self;
+	}
+
+	/// `set_max_headers_size` set the max size of the headers of a part, the default is 8KiB.
+	pub fn set_max_headers_size(&mut self, v: usize) -> &mut Self {
+		self.max_headers_size = v;
+		return self;
+	}
+
+	/// `set_max_field_size` set the max size of a part without a filename, the default is 1MiB.
+	pub fn set_max_field_size(&mut self, v: u64) -> &mut Self {
+		self.max_field_size = v;
+		return self;
+	}
+
+	/// `set_max_file_size` set the max size of a part with a filename, the default is unlimited.
+	pub fn set_max_file_size(&mut self, v: u64) -> &mut Self {
+		self.max_file_size = v;
+		return self;
+	}
+
+	/// `fill` read more bytes into the buffer, return false if the reader is at eof.
+	async fn fill(&mut self) -> Result<bool, Error> {
+		return poll_fn(|cx| self.poll_fill(cx)).await;
+	}
+
+	fn poll_fill(&mut self, cx: &mut Context<'_>) -> Poll<Result<bool, Error>> {
+		if self.pos > 0 {
+			self.buf.drain(..self.pos);
+			self.pos = 0;
+		}
+		let size = self.buf.len();
+		self.buf.resize(size + READ_SIZE, 0);
+		let mut rb = ReadBuf::new(&mut self.buf[size..]);
+		let result = Pin::new(&mut self.reader).poll_read(cx, &mut rb);
+		let n = rb.filled().len();
+		self.buf.truncate(size + n);
+		ready!(result)?;
+		return Poll::Ready(Ok(n > 0));
+	}
+
+	fn find(&self, needle: &[u8]) -> Option<usize> {
+		return self.buf[self.pos..].windows(needle.len()).position(|w| w == needle);
+	}
+
+	/// `next_part` skip the rest of the current part and return the headers of the next one, `None` at the end.
+	pub async fn next_part(&mut self) -> Result<Option<Part>, Error> {
+		let mut sink = [0u8; 1024];
+		match self.state {
+			State::Done => {
+				return Ok(None);
+			}
+			State::Preamble | State::Content => while poll_fn(|cx| self.poll_read_inner(cx, &mut sink)).await? > 0 {},
+			State::Delimiter => {}
+		}
+
+		// after the delimiter: `--` for the end, or the optional whitespaces and CRLF.
+		loop {
+			let rest = &self.buf[self.pos..];
+			if rest.starts_with(b"--") {
+				self.state = State::Done;
+				return Ok(None);
+			}
+			match rest.windows(2).position(|w| w == b"\r\n") {
+				Some(idx) => {
+					if rest[..idx].iter().any(|c| *c != b' ' && *c != b'\t') {
+						return Err(bad("bad multipart delimiter"));
+					}
+					self.pos += idx + 2;
+					break;
+				}
+				None => {
+					if rest.len() > 2 && rest.iter().any(|c| *c != b' ' && *c != b'\t' && *c != b'\r') {
+						return Err(bad("bad multipart delimiter"));
+					}
+					if !self.fill().await? {
+						return Err(Error::new(ErrorKind::UnexpectedEof, "incomplete multipart body"));
+					}
+				}
+			}
+		}
+
+		if self.parts >= self.max_parts {
+			return Err(too_large("too many multipart parts"));
+		}
+		self.parts += 1;
+
+		let headers = loop {
+			if self.buf.len() - self.pos < 2 {
+				if !self.fill().await? {
+					return Err(Error::new(ErrorKind::UnexpectedEof, "incomplete multipart body"));
+				}
+				continue;
+			}
+			if self.buf[self.pos..].starts_with(b"\r\n") {
+				// a part without headers
+				self.pos += 2;
+				break String::new();
+			}
+			match self.find(b"\r\n\r\n") {
+				Some(idx) => {
+					if idx > self.max_headers_size {
+						return Err(bad("multipart headers too large"));
+					}
+					let headers = String::from_utf8_lossy(&self.buf[self.pos..self.pos + idx]).to_string();
+					self.pos += idx + 4;
+					break headers;
+				}
+				None => {
+					if self.buf.len() - self.pos > self.max_headers_size {
+						return Err(bad("multipart headers too large"));
+					}
+					if !self.fill().await? {
+						return Err(Error::new(ErrorKind::UnexpectedEof, "incomplete multipart body"));
+					}
+				}
+			}
+		};
+
+		let mut part = Part {
+			name: String::new(),
+			filename: None,
+			content_type: None,
+			headers: Headers::new(),
+		};
+		let mut disposition = false;
+		for line in headers.split("\r\n") {
+			let (k, v) = match line.split_once(':') {
+				Some(kv) => kv,
+				None => {
+					continue;
+				}
+			};
+			let k = k.trim();
+			part.headers.builder().append(k.to_ascii_lowercase().as_str(), v.trim());
+			if k.eq_ignore_ascii_case("content-disposition") {
+				let (kind, params) = v.split_once(';').unwrap_or((v, ""));
+				if !kind.trim().eq_ignore_ascii_case("form-data") {
+					return Err(bad("bad content-disposition"));
+				}
+				part.name = param(params, "name").unwrap_or_default();
+				part.filename = match param(params, "filename*").and_then(|v| ext_value(&v)) {
+					Some(v) => Some(v),
+					None => param(params, "filename"),
+				};
+				disposition = true;
+			} else if k.eq_ignore_ascii_case("content-type") {
+				part.content_type = Some(v.trim().to_string());
+			}
+		}
+		if !disposition {
+			return Err(bad("missing content-disposition"));
+		}
+		self.state = State::Content;
+		self.part_size = 0;
+		self.part_limit = if part.filename.is_some() { self.max_file_size } else { self.max_field_size };
+		return Ok(Some(part));
+	}
+
+	/// `read` read the content of the current part, return 0 at the end of the part.
+	/// the content beyond the limit of the part is never copied to `out`.
+	pub async fn read(&mut self, out: &mut [u8]) -> Result<usize, Error> {
+		return poll_fn(|cx| self.poll_read_part(cx, out)).await;
+	}
+
+	/// `reader` return the content of the current part as an `AsyncRead`, so it can be used with
+	/// `tokio::io::copy` and the like.
+	pub fn reader(&mut self) -> PartReader<'_, 'r> {
+		return PartReader { mp: self };
+	}
+
+	fn poll_read_part(&mut self, cx: &mut Context<'_>, out: &mut [u8]) -> Poll<Result<usize, Error>> {
+		if self.state != State::Content || out.is_empty() {
+			return Poll::Ready(Ok(0));
+		}
+		let allowed = self.part_limit - self.part_size;
+		if allowed == 0 {
+			// the part must end here, read a byte into a scratch buffer to know it.
+			let mut probe = [0u8; 1];
+			if ready!(self.poll_read_inner(cx, &mut probe))? > 0 {
+				return Poll::Ready(Err(too_large("multipart part too large")));
+			}
+			return Poll::Ready(Ok(0));
+		}
+		let size = std::cmp::min(out.len() as u64, allowed) as usize;
+		let n = ready!(self.poll_read_inner(cx, &mut out[..size]))?;
+		self.part_size += n as u64;
+		return Poll::Ready(Ok(n));
+	}
+
+	/// `bytes` read the rest content of the current part into memory, at most `max_field_size` bytes
+	/// even if the part is a file.
+	pub async fn bytes(&mut self) -> Result<Vec<u8>, Error> {
+		self.part_limit = std::cmp::min(self.part_limit, self.part_size.saturating_add(self.max_field_size));
+		let mut content = vec![];
+		let mut buf = [0u8; READ_SIZE];
+		loop {
+			let n = self.read(&mut buf).await?;
+			if n == 0 {
+				return Ok(content);
+			}
+			content.extend_from_slice(&buf[..n]);
+		}
+	}
+
+	fn poll_read_inner(&mut self, cx: &mut Context<'_>, out: &mut [u8]) -> Poll<Result<usize, Error>> {
+		if out.is_empty() {
+			return Poll::Ready(Ok(0));
+		}
+		loop {
+			match self.find(&self.delimiter) {
+				Some(0) => {
+					self.pos += self.delimiter.len();
+					self.state = State::Delimiter;
+					return Poll::Ready(Ok(0));
+				}
+				Some(idx) => {
+					let n = std::cmp::min(idx, out.len());
+					out[..n].copy_from_slice(&self.buf[self.pos..self.pos + n]);
+					self.pos += n;
+					return Poll::Ready(Ok(n));
+				}
+				None => {
+					// the tail may be the beginning of the delimiter, keep it.
+					let available = self.buf.len() - self.pos;
+					let safe = available.saturating_sub(self.delimiter.len() - 1);
+					if safe > 0 {
+						let n = std::cmp::min(safe, out.len());
+						out[..n].copy_from_slice(&self.buf[self.pos..self.pos + n]);
+						self.pos += n;
+						return Poll::Ready(Ok(n));
+					}
+					if !ready!(self.poll_fill(cx))? {
+						return Poll::Ready(Err(Error::new(ErrorKind::UnexpectedEof, "incomplete multipart body")));
+					}
+				}
+			}
+		}
+	}
+}
+
+/// `PartReader` is the `AsyncRead` of the current part content, see `Multipart::reader`.
+pub struct PartReader<'a, 'r> {
+	mp: &'a mut Multipart<'r>,
+}
+
+impl<'a, 'r> AsyncRead for PartReader<'a, 'r> {
+	fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<std::io::Result<()>> {
+		let this = self.get_mut();
+		let n = ready!(this.mp.poll_read_part(cx, buf.initialize_unfilled()))?;
+		buf.advance(n);
+		return Poll::Ready(Ok(()));
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::h2tp::multipart::{boundary, Multipart};
+	use crate::h2tp::Request;
+	use bytes::BytesMut;
+	use std::io::ErrorKind;
+
+	#[tokio::test]
+	async fn test_multipart() {
+		assert_eq!(boundary("multipart/form-data; boundary=\"a b\"").unwrap(), "a b");
+		assert_eq!(boundary("multipart/form-data; charset=utf-8; boundary=xyz").unwrap(), "xyz");
+		assert!(boundary("text/plain; boundary=xyz").is_none());
+
+		let body = "preamble\r\n--xyz\r\ncontent-disposition: form-data; name=\"title\"\r\n\r\nhello\r\n--xyz  \r\n\
+			Content-Disposition: form-data; name=\"file\"; filename=\"a \\\"b\\\".txt\"\r\ncontent-type: text/plain\r\n\r\n\
+			line1\r\n--xy\r\nline2\r\n--xyz--\r\nepilogue";
+		let mut mp = Multipart::new(body.as_bytes(), "xyz");
+
+		let part = mp.next_part().await.unwrap().unwrap();
+		assert_eq!(part.name, "title");
+		assert!(part.filename.is_none());
+
+		let part = mp.next_part().await.unwrap().unwrap();
+		assert_eq!(part.name, "file");
+		assert_eq!(part.filename.unwrap(), "a \"b\".txt");
+		assert_eq!(part.content_type.unwrap(), "text/plain");
+		let mut content = vec![];
+		let mut buf = [0u8; 3];
+		loop {
+			let n = mp.read(&mut buf).await.unwrap();
+			if n == 0 {
+				break;
+			}
+			content.extend_from_slice(&buf[..n]);
+		}
+		assert_eq!(content, b"line1\r\n--xy\r\nline2");
+		assert!(mp.next_part().await.unwrap().is_none());
+
+		let mut mp = Multipart::new(body.as_bytes(), "xyz");
+		mp.next_part().await.unwrap().unwrap();
+		mp.next_part().await.unwrap().unwrap();
+		let mut content = vec![];
+		assert_eq!(tokio::io::copy(&mut mp.reader(), &mut content).await.unwrap(), 18);
+		assert_eq!(content, b"line1\r\n--xy\r\nline2");
+		assert!(mp.next_part().await.unwrap().is_none());
+
+		let reader = "--xyz\r\ncontent-disposition: form-data; name=\"a\"\r\n\r\nabc".as_bytes();
+		let mut mp = Multipart::new(reader, "xyz");
+		mp.next_part().await.unwrap().unwrap();
+		assert!(mp.next_part().await.is_err());
+	}
+
+	#[tokio::test]
+	async fn test_multipart_limits() {
+		let body = "--xyz\r\ncontent-disposition: form-data; name=\"f\"; filename=\"a.txt\"; filename*=UTF-8''%E6%88%91.txt\r\n\
+			X-Extra: 1\r\n\r\n0123456789\r\n--xyz\r\ncontent-disposition: form-data; name=\"t\"\r\n\r\nabcdef\r\n--xyz--";
+
+		let mut req = Request::new();
+		req.msg.headers_builder().append("content-type", "multipart/form-data; boundary=xyz");
+		req.msg.body = Some(BytesMut::from(body));
+		let mut mp = req.multipart().unwrap();
+		let part = mp.next_part().await.unwrap().unwrap();
+		assert_eq!(part.filename.unwrap(), "我.txt");
+		assert_eq!(part.headers.getone("x-extra").unwrap(), "1");
+		assert_eq!(mp.bytes().await.unwrap(), b"0123456789");
+		let part = mp.next_part().await.unwrap().unwrap();
+		assert_eq!(part.name, "t");
+		assert_eq!(mp.bytes().await.unwrap(), b"abcdef");
+		assert!(mp.next_part().await.unwrap().is_none());
+
+		let mut mp = Multipart::new(body.as_bytes(), "xyz");
+		mp.set_max_file_size(4);
+		mp.next_part().await.unwrap().unwrap();
+		let mut buf = [0u8; 64];
+		assert_eq!(mp.read(&mut buf).await.unwrap(), 4);
+		assert_eq!(&buf[..4], b"0123");
+		assert_eq!(mp.read(&mut buf).await.unwrap_err().kind(), ErrorKind::FileTooLarge);
+		assert_eq!(buf[4], 0);
+
+		let mut mp = Multipart::new(body.as_bytes(), "xyz");
+		mp.set_max_file_size(4);
+		mp.next_part().await.unwrap().unwrap();
+		assert_eq!(mp.bytes().await.unwrap_err().kind(), ErrorKind::FileTooLarge);
+
+		let mut mp = Multipart::new(body.as_bytes(), "xyz");
+		mp.set_max_file_size(4);
+		mp.next_part().await.unwrap().unwrap();
+		let mut content = vec![];
+		assert_eq!(tokio::io::copy(&mut mp.reader(), &mut content).await.unwrap_err().kind(), ErrorKind::FileTooLarge);
+		assert_eq!(content, b"0123");
+
+		// `bytes` keeps the files in memory, so they are limited by the `max_field_size` too.
+		let mut mp = Multipart::new(body.as_bytes(), "xyz");
+		mp.set_max_field_size(8);
+		mp.next_part().await.unwrap().unwrap();
+		assert_eq!(mp.bytes().await.unwrap_err().kind(), ErrorKind::FileTooLarge);
+
+		let mut mp = Multipart::new(body.as_bytes(), "xyz");
+		mp.set_max_field_size(5).set_max_parts(2);
+		mp.next_part().await.unwrap().unwrap();
+		mp.next_part().await.unwrap().unwrap();
+		assert_eq!(mp.bytes().await.unwrap_err().kind(), ErrorKind::FileTooLarge);
+
+		let mut mp = Multipart::new(body.as_bytes(), "xyz");
+		mp.set_max_parts(1);
+		mp.next_part().await.unwrap().unwrap();
+		assert_eq!(mp.next_part().await.err().unwrap().kind(), ErrorKind::FileTooLarge);
+
+		let mut mp = Multipart::new(body.as_bytes(), "xyz");
+		mp.set_max_headers_size(16);
+		assert_eq!(mp.next_part().await.err().unwrap().kind(), ErrorKind::InvalidData);
+
+		let mut req = Request::new();
+		req.msg.headers_builder().append("content-type", "text/plain");
+		assert_eq!(req.multipart().err().unwrap().statuscode().code(), 415);
+	}
+}
@@ -0,0 +1,1048 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+use async_trait::async_trait;
+use crate::h2tp::handler::Handler;
+use crate::h2tp::router::{impl_handler_for_router, run_with, Middleware, Router, RouterFindError};
+use crate::h2tp::status_code::StatusCode;
+use crate::h2tp::url::Url;
+use crate::h2tp::utils::uricoding;
+use crate::h2tp::{methods, Request, Response};
+
+/// `ANY_METHOD` registers a handler for all the methods, the handlers of the exact methods win over it.
+pub const ANY_METHOD: &str = "*";
+
+/// the name of the catch-all parameter that used by `mount`, it is removed before calling the mounted handler.
+const MOUNT_PARAM: &str = "__mount";
+
+/// `Route` is a handler that knows its method and pattern, it is implemented by the route attributes.
+pub trait Route: Handler {
+	fn method(&self) -> &'static str;
+	fn pattern(&self) -> &'static str;
+}
+
+/// `TrailingSlash` is the policy of the paths that only differ from a route in the trailing slash.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TrailingSlash {
+	/// `/a/` and `/a` are different paths, the missing one is not found.
+	Strict,
+	/// redirect to the registered one when the requested one is not found.
+	Redirect,
+}
+
+pub enum RouteError {
+	/// the pattern is already registered for the method, or a parameter has a different name at the same position.
+	Conflict(String),
+	BadPattern(String),
+	/// `url_for` is called with a name that not registered.
+	UnknownName(String),
+	/// `url_for` is called without a value of the parameter.
+	MissingParam(String),
+}
+
+impl fmt::Debug for RouteError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			RouteError::Conflict(v) => write!(f, "RouteConflict({})", v),
+			RouteError::BadPattern(v) => write!(f, "BadRoutePattern({})", v),
+			RouteError::UnknownName(v) => write!(f, "UnknownRouteName({})", v),
+			RouteError::MissingParam(v) => write!(f, "MissingRouteParam({})", v),
+		}
+	}
+}
+
+impl fmt::Display for RouteError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		fmt::Debug::fmt(self, f)
+	}
+}
+
+enum Token<'p> {
+	Static(String),
+	Param(&'p str),
+	CatchAll(&'p str),
+}
+
+/// `tokenize` split the pattern to the static parts and the parameters,
+/// the parameters must be whole segments, like `/users/:id` and `/static/*path`.
+fn tokenize(pattern: &str) -> Result<Vec<Token<'_>>, RouteError> {
+	if !pattern.starts_with('/') {
+		return Err(RouteError::BadPattern(format!("`{}` does not start with `/`", pattern)));
+	}
+	let mut tokens = vec![];
+	let mut static_part = String::new();
+	let segs: Vec<&str> = pattern[1..].split('/').collect();
+	for (idx, seg) in segs.iter().enumerate() {
+		static_part.push('/');
+		let (is_param, name) = match seg.strip_prefix(':') {
+			Some(name) => (true, name),
+			None => match seg.strip_prefix('*') {
+				Some(name) => (false, name),
+				None => {
+					static_part.push_str(seg);
+					continue;
+				}
+			},
+		};
+		if name.is_empty() || name.contains(|c| c == ':' || c == '*') {
+			return Err(RouteError::BadPattern(format!("`{}` has a bad parameter name", pattern)));
+		}
+		tokens.push(Token::Static(std::mem::take(&mut static_part)));
+		if is_param {
+			tokens.push(Token::Param(name));
+		} else {
+			if idx != segs.len() - 1 {
+				return Err(RouteError::BadPattern(format!("`{}` has a catch-all parameter that is not at the end", pattern)));
+			}
+			tokens.push(Token::CatchAll(name));
+		}
+	}
+	if !static_part.is_empty() {
+		tokens.push(Token::Static(static_part));
+	}
+	return Ok(tokens);
+}
+
+#[derive(Default)]
+struct Node {
+	prefix: String,
+	/// the first bytes of the children's prefixes are distinct.
+	statics: Vec<Node>,
+	param: Option<(String, Box<Node>)>,
+	catchall: Option<(String, Box<Node>)>,
+	handlers: Vec<(String, Box<dyn Handler>)>,
+}
+
+fn common_prefix(a: &str, b: &str) -> usize {
+	let mut n = 0;
+	for (x, y) in a.bytes().zip(b.bytes()) {
+		if x != y {
+			break;
+		}
+		n += 1;
+	}
+	// keep the split on a char boundary
+	while !a.is_char_boundary(n) {
+		n -= 1;
+	}
+	return n;
+}
+
+impl Node {
+	fn insert_static(&mut self, s: &str) -> &mut Node {
+		let common = common_prefix(&self.prefix, s);
+		if common < self.prefix.len() {
+			let child = Node {
+				prefix: self.prefix[common..].to_string(),
+				statics: std::mem::take(&mut self.statics),
+				param: self.param.take(),
+				catchall: self.catchall.take(),
+				handlers: std::mem::take(&mut self.handlers),
+			};
+			self.prefix.truncate(common);
+			self.statics.push(child);
+		}
+
+		let rest = &s[common..];
+		if rest.is_empty() {
+			return self;
+		}
+		let first = rest.as_bytes()[0];
+		let idx = match self.statics.iter().position(|c| c.prefix.as_bytes()[0] == first) {
+			Some(idx) => idx,
+			None => {
+				self.statics.push(Node {
+					prefix: rest.to_string(),
+					..Default::default()
+				});
+				return self.statics.last_mut().unwrap();
+			}
+		};
+		return self.statics[idx].insert_static(rest);
+	}
+
+	/// `lookup` find the node that has a handler for `method`, the static children are tried first,
+	/// then the parameter, then the catch-all. `fallback` is the first matched node, used for `405`.
+	fn lookup<'n>(
+		&'n self,
+		path: &str,
+		method: &str,
+		params: &mut Vec<(String, String)>,
+		fallback: &mut Option<&'n Node>,
+	) -> Option<&'n dyn Handler> {
+		if path.is_empty() && !self.handlers.is_empty() {
+			match self.handler(method) {
+				Some(h) => {
+					return Some(h);
+				}
+				None => {
+					if fallback.is_none() {
+						*fallback = Some(self);
+					}
+				}
+			}
+		}
+
+		if !path.is_empty() {
+			let first = path.as_bytes()[0];
+			for child in self.statics.iter() {
+				if child.prefix.as_bytes()[0] == first && path.starts_with(child.prefix.as_str()) {
+					match child.lookup(&path[child.prefix.len()..], method, params, fallback) {
+						Some(h) => {
+							return Some(h);
+						}
+						None => {}
+					}
+					break;
+				}
+			}
+
+			match self.param.as_ref() {
+				Some((name, child)) if first != b'/' => {
+					let end = path.find('/').unwrap_or(path.len());
+					params.push((name.clone(), path[..end].to_string()));
+					match child.lookup(&path[end..], method, params, fallback) {
+						Some(h) => {
+							return Some(h);
+						}
+						None => {
+							params.pop();
+						}
+					}
+				}
+				_ => {}
+			}
+		}
+
+		match self.catchall.as_ref() {
+			Some((name, child)) => match child.handler(method) {
+				Some(h) => {
+					params.push((name.clone(), path.to_string()));
+					return Some(h);
+				}
+				None => {
+					if fallback.is_none() {
+						*fallback = Some(child);
+					}
+				}
+			},
+			None => {}
+		}
+		return None;
+	}
+
+	fn handler(&self, method: &str) -> Option<&dyn Handler> {
+		let mut get: Option<&dyn Handler> = None;
+		for (m, h) in self.handlers.iter() {
+			if m == method {
+				return Some(h.as_ref());
+			}
+			if m == methods::GET {
+				get = Some(h.as_ref());
+			}
+		}
+		if method == methods::HEAD && get.is_some() {
+			return get;
+		}
+		for (m, h) in self.handlers.iter() {
+			if m == ANY_METHOD {
+				return Some(h.as_ref());
+			}
+		}
+		return None;
+	}
+
+	fn allowed(&self) -> String {
+		let mut v: Vec<&str> = self.handlers.iter().map(|(m, _)| m.as_str()).collect();
+		if v.contains(&methods::GET) && !v.contains(&methods::HEAD) {
+			v.push(methods::HEAD);
+		}
+		return v.join(", ");
+	}
+}
+
+/// `RadixRouter` is a radix tree router keyed by method and path. the patterns are made of the static
+/// parts, the named parameters(`/users/:id`) and the catch-all parameter at the end(`/static/*path`).
+/// when more than one pattern matches, the static part wins over the parameter, and the parameter
+/// wins over the catch-all. `HEAD` is handled by the `GET` handler if it is not registered.
+pub struct RadixRouter {
+	root: Node,
+	/// the patterns of the named routes.
+	names: HashMap<String, String>,
+	before: Vec<Box<dyn Middleware>>,
+	after: Vec<Box<dyn Middleware>>,
+	clean_path: bool,
+	trailing_slash: TrailingSlash,
+	redirect_code: StatusCode,
+}
+
+impl RadixRouter {
+	pub fn new() -> Self {
+		return Self {
+			root: Node::default(),
+			names: HashMap::new(),
+			before: vec![],
+			after: vec![],
+			clean_path: false,
+			trailing_slash: TrailingSlash::Strict,
+			redirect_code: StatusCode::PermanentRedirect,
+		};
+	}
+
+	/// `register` add a handler for `method` and `pattern`, the conflicts are reported instead of overriding.
+	pub fn register(&mut self, method: &str, pattern: &str, handler: Box<dyn Handler>) -> Result<&mut Self, RouteError> {
+		let method = method.to_ascii_uppercase();
+		let mut node = &mut self.root;
+		for token in tokenize(pattern)? {
+			match token {
+				Token::Static(s) => {
+					node = node.insert_static(&s);
+				}
+				Token::Param(name) | Token::CatchAll(name) => {
+					let slot = match token {
+						Token::Param(_) => &mut node.param,
+						_ => &mut node.catchall,
+					};
+					match slot {
+						Some((exists, _)) if exists != name => {
+							return Err(RouteError::Conflict(format!(
+								"`{}` names the parameter `{}`, but it is `{}` in another pattern",
+								pattern, name, exists
+							)));
+						}
+						Some(_) => {}
+						None => {
+							*slot = Some((name.to_string(), Box::new(Node::default())));
+						}
+					}
+					node = slot.as_mut().unwrap().1.as_mut();
+				}
+			}
+		}
+		if node.handlers.iter().any(|(m, _)| *m == method) {
+			return Err(RouteError::Conflict(format!("`{} {}` is already registered", method, pattern)));
+		}
+		node.handlers.push((method, handler));
+		return Ok(self);
+	}
+
+	/// `route` register a handler that declared by the route attributes(`#[get("/users/:id")]` ...).
+	pub fn route<R: Route + 'static>(&mut self, route: R) -> Result<&mut Self, RouteError> {
+		let (method, pattern) = (route.method(), route.pattern());
+		return self.register(method, pattern, Box::new(route));
+	}
+
+	/// `register_named` register the route like `register`, and name it for `url_for`, a name can be
+	/// shared by the methods of the same pattern.
+	pub fn register_named(&mut self, name: &str, method: &str, pattern: &str, handler: Box<dyn Handler>) -> Result<&mut Self, RouteError> {
+		match self.names.get(name) {
+			Some(exists) if exists != pattern => {
+				return Err(RouteError::Conflict(format!("the name `{}` is already used by `{}`", name, exists)));
+			}
+			_ => {}
+		}
+		self.register(method, pattern, handler)?;
+		self.names.insert(name.to_string(), pattern.to_string());
+		return Ok(self);
+	}
+
+	/// `url_for` build the path of the named route, the parameter values and the query are percent-encoded,
+	/// like `url_for("user.show", &[("id", "7")], &[("tab", "posts")])` returns `/users/7?tab=posts`.
+	/// the `/` in the value of a catch-all parameter is kept as the separator.
+	pub fn url_for(&self, name: &str, params: &[(&str, &str)], query: &[(&str, &str)]) -> Result<String, RouteError> {
+		let pattern = match self.names.get(name) {
+			Some(v) => v,
+			None => {
+				return Err(RouteError::UnknownName(name.to_string()));
+			}
+		};
+		let value = |k: &str| -> Result<&str, RouteError> {
+			return match params.iter().find(|(pk, _)| *pk == k) {
+				Some((_, v)) => Ok(v),
+				None => Err(RouteError::MissingParam(k.to_string())),
+			};
+		};
+
+		let mut path: Vec<u8> = vec![];
+		for token in tokenize(pattern)? {
+			match token {
+				Token::Static(s) => {
+					path.extend_from_slice(s.as_bytes());
+				}
+				Token::Param(k) => {
+					let v = value(k)?;
+					if v.is_empty() {
+						return Err(RouteError::MissingParam(k.to_string()));
+					}
+					uricoding::encode_uri_component(&mut path, v);
+				}
+				Token::CatchAll(k) => {
+					for (idx, seg) in value(k)?.split('/').enumerate() {
+						if idx > 0 {
+							path.push(b'/');
+						}
+						uricoding::encode_uri_component(&mut path, seg);
+					}
+				}
+			}
+		}
+
+		let mut url = Url::new();
+		let mut builder = url.builder();
+		builder.path(std::str::from_utf8(&path).unwrap_or("/"));
+		let qm = builder.query();
+		for (k, v) in query {
+			qm.append(k, v);
+		}
+		let mut dest = String::new();
+		let _ = url.to(&mut dest);
+		return Ok(dest);
+	}
+
+	/// `mount` serve all the requests under `prefix` by `handler`, usually another `Router`.
+	/// the prefix is stripped from the request path, so `/api/users?x=1` is `/users?x=1` for the handler
+	/// that mounted at `/api`. the prefix can contain the named parameters, like `/tenants/:tid`.
+	pub fn mount(&mut self, prefix: &str, handler: Box<dyn Handler>) -> Result<&mut Self, RouteError> {
+		let prefix = prefix.trim_end_matches('/');
+		let mounted: Arc<dyn Handler> = Arc::new(Mounted {
+			depth: prefix.split('/').filter(|s| !s.is_empty()).count(),
+			inner: handler,
+		});
+		if !prefix.is_empty() {
+			self.register(ANY_METHOD, prefix, Box::new(Shared(mounted.clone())))?;
+		}
+		self.register(ANY_METHOD, format!("{}/*{}", prefix, MOUNT_PARAM).as_str(), Box::new(Shared(mounted)))?;
+		return Ok(self);
+	}
+
+	/// `group` return a `Group` that registers the routes under `prefix`, and the routes are wrapped by `middleware`.
+	pub fn group(&mut self, prefix: &str, middleware: Vec<Box<dyn Middleware>>) -> Group<'_> {
+		return Group {
+			router: self,
+			prefix: prefix.trim_end_matches('/').to_string(),
+			layers: vec![Arc::new(middleware)],
+		};
+	}
+
+	/// `set_clean_path` redirect the paths that have dot segments or duplicate slashes to the cleaned ones,
+	/// if the cleaned path matches a route.
+	pub fn set_clean_path(&mut self, v: bool) -> &mut Self {
+		self.clean_path = v;
+		return self;
+	}
+
+	pub fn set_trailing_slash(&mut self, v: TrailingSlash) -> &mut Self {
+		self.trailing_slash = v;
+		return self;
+	}
+
+	/// `set_redirect_code` set the status of the canonical path redirects, `301` or `308`(the default).
+	pub fn set_redirect_code(&mut self, code: StatusCode) -> &mut Self {
+		self.redirect_code = code;
+		return self;
+	}
+
+	pub fn add_before(&mut self, middleware: Box<dyn Middleware>) -> &mut Self {
+		self.before.push(middleware);
+		return self;
+	}
+
+	pub fn add_after(&mut self, middleware: Box<dyn Middleware>) -> &mut Self {
+		self.after.push(middleware);
+		return self;
+	}
+
+	/// `lookup` return the handler and the raw values of the parameters.
+	pub fn lookup(&self, method: &str, path: &str) -> Result<(&dyn Handler, Vec<(String, String)>), RouterFindError> {
+		let path = match path.find(|c| c == '?' || c == '#') {
+			Some(idx) => &path[..idx],
+			None => path,
+		};
+		let mut params = vec![];
+		let mut fallback = None;
+		return match self.root.lookup(path, method, &mut params, &mut fallback) {
+			Some(h) => Ok((h, params)),
+			None => match fallback {
+				Some(node) => Err(RouterFindError::MethodNotAllow(node.allowed())),
+				None => Err(RouterFindError::NotFound),
+			},
+		};
+	}
+
+	/// `canonical` return the canonical form of `path` that matches a route, `None` if the path is already canonical
+	/// or the canonical one does not match too.
+	fn canonical(&self, method: &str, path: &str) -> Option<String> {
+		let mut path = path.to_string();
+		if self.clean_path {
+			let cleaned = clean(&path);
+			if cleaned != path && self.lookup(method, &cleaned).is_ok() {
+				return Some(cleaned);
+			}
+			// the trailing slash is only toggled for the paths that not found.
+			if !matches!(self.lookup(method, &path), Err(RouterFindError::NotFound)) {
+				return None;
+			}
+			path = cleaned;
+		}
+		if self.trailing_slash == TrailingSlash::Redirect {
+			if path.len() > 1 && path.ends_with('/') {
+				path.pop();
+			} else if !path.ends_with('/') {
+				path.push('/');
+			} else {
+				return None;
+			}
+			if self.lookup(method, &path).is_ok() {
+				return Some(path);
+			}
+		}
+		return None;
+	}
+}
+
+/// `clean` return the path that the duplicate slashes are collapsed and the dot segments are resolved,
+/// `..` never goes above the root, and the trailing slash is kept.
+fn clean(path: &str) -> String {
+	let mut segments: Vec<&str> = vec![];
+	let mut trailing = false;
+	for seg in path.split('/') {
+		match seg {
+			"" => {}
+			"." => {
+				trailing = true;
+			}
+			".." => {
+				segments.pop();
+				trailing = true;
+			}
+			_ => {
+				segments.push(seg);
+				trailing = false;
+			}
+		}
+	}
+	if path.ends_with('/') {
+		trailing = true;
+	}
+	let mut v = String::with_capacity(path.len() + 1);
+	for seg in segments.iter() {
+		v.push('/');
+		v.push_str(seg);
+	}
+	if trailing || v.is_empty() {
+		v.push('/');
+	}
+	return v;
+}
+
+#[async_trait]
+impl Router for RadixRouter {
+	fn middleware<'a, 'c>(&self, _req: &'a Request<'c>) -> (&Vec<Box<dyn Middleware>>, &Vec<Box<dyn Middleware>>) {
+		return (&self.before, &self.after);
+	}
+
+	fn find<'a, 'c>(&self, req: &'a mut Request<'c>) -> Result<&dyn Handler, RouterFindError> {
+		if self.clean_path || self.trailing_slash != TrailingSlash::Strict {
+			let raw = req.path();
+			let (path, query) = raw.split_at(raw.find(|c| c == '?' || c == '#').unwrap_or(raw.len()));
+			let query = query.split('#').next().unwrap_or("");
+			if self.clean_path || matches!(self.lookup(req.method(), path), Err(RouterFindError::NotFound)) {
+				match self.canonical(req.method(), path) {
+					Some(canonical) => {
+						return Err(RouterFindError::RedirectTo(format!("{}{}{}", req.base, canonical, query)));
+					}
+					None => {}
+				}
+			}
+		}
+
+		let (handler, mut params) = self.lookup(req.method(), req.path())?;
+		// the remainder of a mount is decoded by the inner router.
+		params.retain(|(k, _)| k != MOUNT_PARAM);
+		match req.set_params(params) {
+			Ok(_) => {}
+			Err(name) => {
+				return Err(RouterFindError::BadParam(name));
+			}
+		}
+		return Ok(handler);
+	}
+
+	fn redirect_code(&self) -> StatusCode {
+		return self.redirect_code;
+	}
+}
+
+impl_handler_for_router!(RadixRouter);
+
+/// `Shared` is a handler that registered more than once.
+struct Shared(Arc<dyn Handler>);
+
+#[async_trait]
+impl Handler for Shared {
+	async fn handle<'a, 'c, 'h: 'a>(&'h self, req: &'a mut Request<'c>, resp: &'a mut Response<'c>) -> () {
+		self.0.handle(req, resp).await
+	}
+}
+
+/// `Mounted` strip the first `depth` segments of the request path and call the inner handler.
+struct Mounted {
+	depth: usize,
+	inner: Box<dyn Handler>,
+}
+
+/// `strip_segments` remove the first `depth` segments from the raw path, the query is kept.
+/// return the stripped path and the removed prefix.
+fn strip_segments(raw: &str, depth: usize) -> (String, &str) {
+	let end = raw.find(|c| c == '?' || c == '#').unwrap_or(raw.len());
+	let (path, query) = raw.split_at(end);
+	let mut pos = 0;
+	for _ in 0..depth {
+		pos = match path[pos + 1..].find('/') {
+			Some(idx) => pos + 1 + idx,
+			None => path.len(),
+		};
+		if pos >= path.len() {
+			break;
+		}
+	}
+	let mut stripped = String::with_capacity(raw.len() - pos + 1);
+	if pos >= path.len() {
+		stripped.push('/');
+	} else {
+		stripped.push_str(&path[pos..]);
+	}
+	stripped.push_str(query);
+	return (stripped, &path[..pos]);
+}
+
+#[async_trait]
+impl Handler for Mounted {
+	async fn handle<'a, 'c, 'h: 'a>(&'h self, req: &'a mut Request<'c>, resp: &'a mut Response<'c>) -> () {
+		let raw = std::mem::take(&mut req.msg.startline.1);
+		let (stripped, prefix) = strip_segments(&raw, self.depth);
+		let baselen = req.base.len();
+		req.base.push_str(prefix);
+		req.msg.startline.1 = stripped;
+		self.inner.handle(req, resp).await;
+		req.msg.startline.1 = raw;
+		req.base.truncate(baselen);
+	}
+}
+
+/// `Wrapped` run the middleware around the handler.
+struct Wrapped {
+	middleware: Arc<Vec<Box<dyn Middleware>>>,
+	handler: Box<dyn Handler>,
+}
+
+#[async_trait]
+impl Handler for Wrapped {
+	async fn handle<'a, 'c, 'h: 'a>(&'h self, req: &'a mut Request<'c>, resp: &'a mut Response<'c>) -> () {
+		run_with(self.middleware.as_slice(), self.handler.as_ref(), req, resp).await;
+	}
+}
+
+/// `Group` registers the routes with a shared prefix and shared middleware, the middleware run after the
+/// middleware of the router, and the outer group's run before the inner group's.
+pub struct Group<'r> {
+	router: &'r mut RadixRouter,
+	prefix: String,
+	layers: Vec<Arc<Vec<Box<dyn Middleware>>>>,
+}
+
+impl<'r> Group<'r> {
+	fn wrap(&self, handler: Box<dyn Handler>) -> Box<dyn Handler> {
+		let mut handler = handler;
+		for layer in self.layers.iter().rev() {
+			if layer.is_empty() {
+				continue;
+			}
+			handler = Box::new(Wrapped {
+				middleware: layer.clone(),
+				handler,
+			});
+		}
+		return handler;
+	}
+
+	/// `register` add a handler for `method` and the prefix of the group + `pattern`.
+	pub fn register(&mut self, method: &str, pattern: &str, handler: Box<dyn Handler>) -> Result<&mut Self, RouteError> {
+		let handler = self.wrap(handler);
+		self.router.register(method, format!("{}{}", self.prefix, pattern).as_str(), handler)?;
+		return Ok(self);
+	}
+
+	/// `route` register a handler that declared by the route attributes, under the prefix of the group.
+	pub fn route<R: Route + 'static>(&mut self, route: R) -> Result<&mut Self, RouteError> {
+		let (method, pattern) = (route.method(), route.pattern());
+		return self.register(method, pattern, Box::new(route));
+	}
+
+	/// `register_named` see `RadixRouter::register_named`.
+	pub fn register_named(&mut self, name: &str, method: &str, pattern: &str, handler: Box<dyn Handler>) -> Result<&mut Self, RouteError> {
+		let handler = self.wrap(handler);
+		self.router.register_named(name, method, format!("{}{}", self.prefix, pattern).as_str(), handler)?;
+		return Ok(self);
+	}
+
+	/// `mount` mount `handler` at the prefix of the group + `prefix`, see `RadixRouter::mount`.
+	pub fn mount(&mut self, prefix: &str, handler: Box<dyn Handler>) -> Result<&mut Self, RouteError> {
+		let handler = self.wrap(handler);
+		self.router.mount(format!("{}{}", self.prefix, prefix).as_str(), handler)?;
+		return Ok(self);
+	}
+
+	/// `group` return a nested group.
+	pub fn group(&mut self, prefix: &str, middleware: Vec<Box<dyn Middleware>>) -> Group<'_> {
+		let mut layers = self.layers.clone();
+		layers.push(Arc::new(middleware));
+		return Group {
+			router: self.router,
+			prefix: format!("{}{}", self.prefix, prefix.trim_end_matches('/')),
+			layers,
+		};
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use async_trait::async_trait;
+	use crate::h2tp::handler::Handler;
+	use crate::h2tp::radix::{clean, RadixRouter, Route, RouteError, TrailingSlash, ANY_METHOD};
+	use crate::h2tp::router::{Middleware, MiddlewareControl, Next, RouterFindError};
+	use crate::h2tp::status_code::StatusCode;
+	use crate::h2tp::{FuncHandler, Request, Response};
+
+	/// `Trace` append its name to the `x-trace` header before and after the rest of the chain.
+	struct Trace(&'static str, MiddlewareControl);
+
+	#[async_trait]
+	impl Middleware for Trace {
+		async fn handle<'a, 'c, 'n>(&self, req: &'a mut Request<'c>, resp: &'a mut Response<'c>, next: Next<'n>) -> MiddlewareControl {
+			resp.headers().append("x-trace", self.0);
+			match self.1 {
+				MiddlewareControl::Continue => {
+					next.run(req, resp).await;
+					resp.headers().append("x-trace", format!("/{}", self.0).as_str());
+					return MiddlewareControl::Continue;
+				}
+				MiddlewareControl::Break => MiddlewareControl::Break,
+				MiddlewareControl::Return => MiddlewareControl::Return,
+			}
+		}
+	}
+
+	fn noop() -> Box<FuncHandler> {
+		return Box::new(FuncHandler::new(|_, _| Box::pin(async move {})));
+	}
+
+	fn find(router: &RadixRouter, method: &str, path: &str) -> Result<Vec<(String, String)>, String> {
+		return match router.lookup(method, path) {
+			Ok((_, params)) => Ok(params),
+			Err(RouterFindError::MethodNotAllow(v)) => Err(v),
+			Err(RouterFindError::NotFound) => Err("404".to_string()),
+			Err(_) => unreachable!(),
+		};
+	}
+
+	fn p(v: &[(&str, &str)]) -> Result<Vec<(String, String)>, String> {
+		return Ok(v.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect());
+	}
+
+	#[tokio::test]
+	async fn test_radix() {
+		let mut router = RadixRouter::new();
+		router
+			.register("GET", "/", noop())
+			.unwrap()
+			.register("GET", "/users", noop())
+			.unwrap()
+			.register("GET", "/users/new", noop())
+			.unwrap()
+			.register("GET", "/users/:id", noop())
+			.unwrap()
+			.register("delete", "/users/:id", noop())
+			.unwrap()
+			.register("GET", "/users/:id/posts/:pid", noop())
+			.unwrap()
+			.register("GET", "/static/*path", noop())
+			.unwrap()
+			.register("GET", "/static/index.html", noop())
+			.unwrap()
+			.register("POST", "/u", noop())
+			.unwrap();
+
+		assert_eq!(find(&router, "GET", "/"), p(&[]));
+		assert_eq!(find(&router, "GET", "/users?x=1"), p(&[]));
+		assert_eq!(find(&router, "GET", "/users/new"), p(&[]));
+		assert_eq!(find(&router, "GET", "/users/newer"), p(&[("id", "newer")]));
+		assert_eq!(find(&router, "HEAD", "/users/12"), p(&[("id", "12")]));
+		assert_eq!(find(&router, "DELETE", "/users/new"), p(&[("id", "new")]));
+		assert_eq!(find(&router, "GET", "/users/7/posts/9"), p(&[("id", "7"), ("pid", "9")]));
+		assert_eq!(find(&router, "GET", "/static/index.html"), p(&[]));
+		assert_eq!(find(&router, "GET", "/static/js/a.js"), p(&[("path", "js/a.js")]));
+		assert_eq!(find(&router, "GET", "/static/"), p(&[("path", "")]));
+		assert_eq!(find(&router, "GET", "/u"), Err("POST".to_string()));
+		assert_eq!(find(&router, "PUT", "/users/1"), Err("GET, DELETE, HEAD".to_string()));
+		assert_eq!(find(&router, "GET", "/users/"), Err("404".to_string()));
+		assert_eq!(find(&router, "GET", "/nope"), Err("404".to_string()));
+
+		assert!(matches!(router.register("GET", "/users/:name", noop()), Err(RouteError::Conflict(_))));
+		assert!(matches!(router.register("GET", "/users/new", noop()), Err(RouteError::Conflict(_))));
+		assert!(matches!(router.register("GET", "/static/*file", noop()), Err(RouteError::Conflict(_))));
+		assert!(matches!(router.register("GET", "/a/*p/b", noop()), Err(RouteError::BadPattern(_))));
+		assert!(matches!(router.register("GET", "/a/:", noop()), Err(RouteError::BadPattern(_))));
+		assert!(matches!(router.register("GET", "a", noop()), Err(RouteError::BadPattern(_))));
+
+		let mut req = crate::h2tp::Request::new();
+		req.msg.startline.0.push_str("PUT");
+		req.msg.startline.1.push_str("/u");
+		let mut resp = Response::new();
+		crate::h2tp::handler::Handler::handle(&router, &mut req, &mut resp).await;
+		assert_eq!(resp.msg.startline.1, "405");
+		assert_eq!(resp.msg.headers.as_ref().unwrap().getone("allow").unwrap(), "POST");
+
+		let mut req = crate::h2tp::Request::new();
+		req.msg.startline.0.push_str("GET");
+		req.msg.startline.1.push_str("/users/a%20b/posts/12");
+		crate::h2tp::router::Router::find(&router, &mut req).ok().unwrap();
+		assert_eq!(req.param("id"), Some("a b"));
+		assert_eq!(req.param_as::<u32>("pid").unwrap(), 12);
+		assert_eq!(req.param_as::<u32>("id").unwrap_err().statuscode(), StatusCode::BadRequest);
+		assert!(req.param("x").is_none());
+
+		req.msg.startline.1.clear();
+		req.msg.startline.1.push_str("/users/%zz");
+		assert!(matches!(crate::h2tp::router::Router::find(&router, &mut req), Err(RouterFindError::BadParam(name)) if name == "id"));
+
+		req.msg.startline.1.clear();
+		req.msg.startline.1.push_str("/users/%FF");
+		assert!(matches!(crate::h2tp::router::Router::find(&router, &mut req), Err(RouterFindError::BadParam(_))));
+		let mut resp = Response::new();
+		Handler::handle(&router, &mut req, &mut resp).await;
+		assert_eq!(resp.msg.startline.1, "400");
+
+		// the decoded values before the bad one are not left behind.
+		let mut req = crate::h2tp::Request::new();
+		req.msg.startline.0.push_str("GET");
+		req.msg.startline.1.push_str("/users/a/posts/%zz");
+		assert!(matches!(crate::h2tp::router::Router::find(&router, &mut req), Err(RouterFindError::BadParam(name)) if name == "pid"));
+		assert!(req.params().is_empty());
+	}
+
+	async fn trace(router: &RadixRouter, path: &str) -> (String, Vec<String>) {
+		let mut req = Request::new();
+		req.msg.startline.0.push_str("GET");
+		req.msg.startline.1.push_str(path);
+		let mut resp = Response::new();
+		Handler::handle(router, &mut req, &mut resp).await;
+		let trace = resp.msg.headers.as_ref().and_then(|h| h.get("x-trace")).cloned().unwrap_or_default();
+		return (resp.msg.startline.1.clone(), trace);
+	}
+
+	#[tokio::test]
+	async fn test_middleware() {
+		let mut router = RadixRouter::new();
+		router
+			.register(
+				"GET",
+				"/",
+				Box::new(FuncHandler::new(|_, resp| {
+					Box::pin(async move {
+						resp.headers().append("x-trace", "handler");
+					})
+				})),
+			)
+			.unwrap();
+		router
+			.add_before(Box::new(Trace("a", MiddlewareControl::Continue)))
+			.add_before(Box::new(Trace("b", MiddlewareControl::Break)))
+			.add_before(Box::new(Trace("skipped", MiddlewareControl::Continue)))
+			.add_after(Box::new(Trace("c", MiddlewareControl::Continue)));
+
+		let (_, v) = trace(&router, "/").await;
+		assert_eq!(v, vec!["a", "b", "c", "handler", "/c", "/a"]);
+
+		let (code, v) = trace(&router, "/404").await;
+		assert_eq!(code, "404");
+		assert_eq!(v, vec!["a", "b", "/a"]);
+
+		router.add_after(Box::new(Trace("stop", MiddlewareControl::Return)));
+		let (_, v) = trace(&router, "/").await;
+		assert_eq!(v, vec!["a", "b", "c", "stop", "/c", "/a"]);
+	}
+
+	fn echo() -> Box<FuncHandler> {
+		return Box::new(FuncHandler::new(|req, resp| {
+			Box::pin(async move {
+				let mut v = req.path().to_string();
+				for (k, p) in req.params() {
+					v.push_str(format!(" {}={}", k, p).as_str());
+				}
+				resp.headers().append("x-echo", &v);
+			})
+		}));
+	}
+
+	async fn call(router: &RadixRouter, method: &str, path: &str) -> (String, Vec<String>) {
+		let mut req = Request::new();
+		req.msg.startline.0.push_str(method);
+		req.msg.startline.1.push_str(path);
+		let mut resp = Response::new();
+		Handler::handle(router, &mut req, &mut resp).await;
+		assert_eq!(req.path(), path);
+		let mut v = resp.msg.headers.as_ref().and_then(|h| h.get("x-trace")).cloned().unwrap_or_default();
+		v.extend(resp.msg.headers.as_ref().and_then(|h| h.get("x-echo")).cloned().unwrap_or_default());
+		return (resp.msg.startline.1.clone(), v);
+	}
+
+	#[tokio::test]
+	async fn test_mount_and_group() {
+		let mut inner = RadixRouter::new();
+		inner.register("GET", "/", echo()).unwrap().register("GET", "/users/:id", echo()).unwrap();
+		inner.add_before(Box::new(Trace("inner", MiddlewareControl::Continue)));
+
+		let mut router = RadixRouter::new();
+		router.mount("/api/:ver/", Box::new(inner)).unwrap();
+		{
+			let mut admin = router.group("/admin", vec![Box::new(Trace("admin", MiddlewareControl::Continue))]);
+			admin.register("GET", "/stats", echo()).unwrap();
+			let mut users = admin.group("/users/", vec![Box::new(Trace("users", MiddlewareControl::Continue))]);
+			users.register("POST", "/:id", echo()).unwrap();
+		}
+		router.register(ANY_METHOD, "/any", echo()).unwrap().register("GET", "/any", noop()).unwrap();
+
+		assert_eq!(call(&router, "GET", "/api/v1/users/7?x=1").await.1, vec!["inner", "/inner", "/users/7?x=1 ver=v1 id=7"]);
+		assert_eq!(call(&router, "GET", "/api/v2").await.1, vec!["inner", "/inner", "/ ver=v2"]);
+		assert_eq!(call(&router, "GET", "/api/v2/").await.1, vec!["inner", "/inner", "/ ver=v2"]);
+		assert_eq!(call(&router, "GET", "/api/v2/nope").await.0, "404");
+		assert_eq!(call(&router, "GET", "/api/v2/%zz").await.0, "404");
+		for (path, name) in [("/api/v2/users/%zz", "id"), ("/api/%zz/users/1", "ver")] {
+			let mut req = Request::new();
+			req.msg.startline.0.push_str("GET");
+			req.msg.startline.1.push_str(path);
+			let mut resp = Response::new();
+			Handler::handle(&router, &mut req, &mut resp).await;
+			assert_eq!(resp.msg.startline.1, "400");
+			let body = String::from_utf8(resp.msg.body.as_deref().unwrap_or_default().to_vec()).unwrap();
+			assert_eq!(body, format!("bad path parameter `{}`", name));
+		}
+		assert_eq!(call(&router, "GET", "/admin/stats").await.1, vec!["admin", "/admin", "/admin/stats"]);
+		assert_eq!(
+			call(&router, "POST", "/admin/users/9").await.1,
+			vec!["admin", "users", "/users", "/admin", "/admin/users/9 id=9"]
+		);
+		assert_eq!(call(&router, "GET", "/admin/users/9").await.0, "405");
+		assert_eq!(call(&router, "PATCH", "/any").await.1, vec!["/any"]);
+		assert!(call(&router, "GET", "/any").await.1.is_empty());
+
+		assert!(matches!(router.mount("/api/:version", noop()), Err(RouteError::Conflict(_))));
+	}
+
+	#[test]
+	fn test_url_for() {
+		let mut router = RadixRouter::new();
+		router
+			.register_named("user.show", "GET", "/users/:id", noop())
+			.unwrap()
+			.register_named("user.show", "DELETE", "/users/:id", noop())
+			.unwrap()
+			.register_named("static", "GET", "/static/*path", noop())
+			.unwrap()
+			.register_named("home", "GET", "/", noop())
+			.unwrap();
+		router.group("/admin", vec![]).register_named("admin.user", "GET", "/users/:id/:tab", noop()).unwrap();
+
+		assert_eq!(router.url_for("home", &[], &[]).unwrap(), "/");
+		assert_eq!(router.url_for("user.show", &[("id", "a b/c")], &[]).unwrap(), "/users/a%20b%2Fc");
+		assert_eq!(
+			router.url_for("user.show", &[("id", "7")], &[("tab", "posts"), ("q", "a&b")]).unwrap(),
+			"/users/7?tab=posts&q=a%26b"
+		);
+		assert_eq!(router.url_for("static", &[("path", "js/a b.js")], &[]).unwrap(), "/static/js/a%20b.js");
+		assert_eq!(router.url_for("admin.user", &[("tab", "x"), ("id", "1")], &[]).unwrap(), "/admin/users/1/x");
+
+		assert!(matches!(router.url_for("admin.user", &[("id", "1")], &[]), Err(RouteError::MissingParam(_))));
+		assert!(matches!(router.url_for("user.show", &[("id", "")], &[]), Err(RouteError::MissingParam(_))));
+		assert!(matches!(router.url_for("nope", &[], &[]), Err(RouteError::UnknownName(_))));
+		assert!(matches!(router.register_named("home", "GET", "/home", noop()), Err(RouteError::Conflict(_))));
+
+		let path = router.url_for("user.show", &[("id", "a b")], &[]).unwrap();
+		let (_, params) = router.lookup("GET", &path).ok().unwrap();
+		assert_eq!(params, vec![("id".to_string(), "a%20b".to_string())]);
+	}
+
+	#[tokio::test]
+	async fn test_canonical_path() {
+		assert_eq!(clean("/a//b/./c/../d"), "/a/b/d");
+		assert_eq!(clean("/a/b/.."), "/a/");
+		assert_eq!(clean("/../../a/"), "/a/");
+		assert_eq!(clean("//"), "/");
+
+		let location = |router: &RadixRouter, path: &'static str| {
+			let mut req = Request::new();
+			req.msg.startline.0.push_str("GET");
+			req.msg.startline.1.push_str(path);
+			return match crate::h2tp::router::Router::find(router, &mut req) {
+				Err(RouterFindError::RedirectTo(v)) => Some(v),
+				_ => None,
+			};
+		};
+
+		let mut inner = RadixRouter::new();
+		inner.register("GET", "/users/", echo()).unwrap().register("GET", "/users/:id", echo()).unwrap();
+		inner.set_clean_path(true).set_trailing_slash(TrailingSlash::Redirect);
+
+		let mut router = RadixRouter::new();
+		router.register("GET", "/docs", echo()).unwrap();
+		router.set_clean_path(true).set_trailing_slash(TrailingSlash::Redirect).set_redirect_code(StatusCode::MovedPermanently);
+		assert_eq!(location(&router, "/docs/?a=1#x").unwrap(), "/docs?a=1");
+		assert_eq!(location(&router, "//x/../docs").unwrap(), "/docs");
+		assert!(location(&router, "/docs").is_none());
+		assert!(location(&router, "/nope/").is_none());
+		router.mount("/api", Box::new(inner)).unwrap();
+
+		assert_eq!(call(&router, "GET", "/docs/").await.0, "301");
+		assert_eq!(call(&router, "GET", "/docs").await.1, vec!["/docs"]);
+		assert_eq!(call(&router, "GET", "/api/users/7").await, ("".to_string(), vec!["/users/7 id=7".to_string()]));
+
+		let mut req = Request::new();
+		req.msg.startline.0.push_str("GET");
+		req.msg.startline.1.push_str("/api/users?page=2");
+		let mut resp = Response::new();
+		Handler::handle(&router, &mut req, &mut resp).await;
+		assert_eq!(resp.msg.startline.1, "308");
+		assert_eq!(resp.msg.headers.as_ref().unwrap().get("location").unwrap()[0], "/api/users/?page=2");
+
+		let mut strict = RadixRouter::new();
+		strict.register("GET", "/docs", echo()).unwrap();
+		assert_eq!(call(&strict, "GET", "/docs/").await.0, "404");
+		assert_eq!(call(&strict, "GET", "/./docs").await.0, "404");
+	}
+
+	#[crate::h2tp::get("/users/:id")]
+	async fn show_user(req: &mut Request<'_>, resp: &mut Response<'_>) {
+		let v = format!("user {}", req.param("id").unwrap_or(""));
+		resp.headers().append("x-echo", &v);
+	}
+
+	#[crate::h2tp::route("*", "/files/*path")]
+	pub(crate) async fn files(req: &mut Request<'_>, resp: &mut Response<'_>) {
+		let v = format!("{} {}", req.method(), req.param("path").unwrap_or(""));
+		resp.headers().append("x-echo", &v);
+	}
+
+	#[tokio::test]
+	async fn test_route_attributes() {
+		assert_eq!((show_user.method(), show_user.pattern()), ("GET", "/users/:id"));
+
+		let mut router = crate::routes![show_user, files];
+		router.group("/v1", vec![]).route(show_user).unwrap();
+		assert_eq!(call(&router, "GET", "/users/7").await.1, vec!["user 7"]);
+		assert_eq!(call(&router, "GET", "/v1/users/8").await.1, vec!["user 8"]);
+		assert_eq!(call(&router, "DELETE", "/files/a/b").await.1, vec!["DELETE a/b"]);
+		assert_eq!(call(&router, "POST", "/users/7").await.0, "405");
+		assert!(matches!(router.route(show_user), Err(RouteError::Conflict(_))));
+	}
+}
@@ -1,12 +1,35 @@
-use crate::h2tp::headers::Headers;
-use crate::h2tp::message::{Message, ParseError};
+#[cfg(feature = "json")]
+use crate::h2tp::cfg::MAX_BUFFERED_BODY_SIZE;
+use crate::h2tp::cfg::MAX_FORM_BODY_SIZE;
+use crate::h2tp::error::Error;
+use crate::h2tp::extensions::Extensions;
+use crate::h2tp::headers::{mime, Headers};
+use crate::h2tp::message::{BodyReader, Message, ParseError};
+use crate::h2tp::multipart::{self, Multipart};
+use crate::h2tp::status_code::StatusCode;
+use crate::h2tp::url::Url;
+use crate::h2tp::utils::formed::{self, Charset, FormedError};
+use crate::h2tp::utils::multi_map::MultiMap;
+use crate::h2tp::utils::uricoding;
 use bytes::BytesMut;
+use serde::de::DeserializeOwned;
 use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+use tokio::io::AsyncReadExt;
 
 use super::types::AsyncReader;
 
 pub struct Request<'c> {
 	pub(crate) msg: Message<'c>,
+	/// the decoded path parameters, filled by the router.
+	pub(crate) params: Vec<(String, String)>,
+	/// the raw path prefix that stripped by the mounting routers.
+	pub(crate) base: String,
+	/// the per-request values, cleared between the requests.
+	pub(crate) extensions: Extensions,
+	/// the state of the server, shared by all the requests.
+	pub(crate) state: Option<Arc<Extensions>>,
 }
 
 impl<'c> fmt::Debug for Request<'c> {
@@ -33,15 +56,30 @@ impl<'c> fmt::Debug for Request<'c> {
 	}
 }
 
+/// `formed_status` is `422` if the pairs do not match the type, or `400` for the others.
+fn formed_status(e: &FormedError) -> StatusCode {
+	if e.is_data() {
+		return StatusCode::UnprocessableEntity;
+	}
+	return StatusCode::BadRequest;
+}
+
 impl<'c> Request<'c> {
 	pub fn new() -> Self {
 		return Self {
 			msg: Message::new(),
+			params: vec![],
+			base: String::new(),
+			extensions: Extensions::new(),
+			state: None,
 		};
 	}
 
 	pub fn clear(&mut self) {
 		self.msg.clear();
+		self.params.clear();
+		self.base.clear();
+		self.extensions.clear();
 	}
 
 	pub async fn from(&mut self, stream: &mut dyn AsyncReader) -> Option<ParseError> {
@@ -56,15 +94,228 @@ impl<'c> Request<'c> {
 		return self.msg.startline.1.as_str();
 	}
 
+	/// `url` parse the request target, the host and the port of an origin-form target(`/path?query`) are
+	/// taken from the `Host` header. the path is kept escaped. return a `400` error if the target or the
+	/// `Host` header is malformed.
+	pub fn url(&self) -> Result<Url<'_>, Error> {
+		let mut url = Url::new();
+		match url.from(self.path()) {
+			Some(e) => {
+				return Err(Error::new(StatusCode::BadRequest, format!("bad request target: {}", e).as_str()));
+			}
+			None => {}
+		}
+		if url.host().is_empty() {
+			match self.headers().and_then(|headers| headers.host()) {
+				Some(host) => match url.authority(host.as_str()) {
+					Some(e) => {
+						return Err(Error::new(StatusCode::BadRequest, format!("bad host: {}", e).as_str()));
+					}
+					None => {}
+				},
+				None => {}
+			}
+		}
+		return Ok(url);
+	}
+
+	/// `query` deserialize the query string to `T`, return a `422` error if the values do not match `T`,
+	/// like an invalid value or a missing field, or a `400` error for the others.
+	pub fn query<T: DeserializeOwned>(&self) -> Result<T, Error> {
+		return match self.url()?.query_as::<T>() {
+			Ok(v) => Ok(v),
+			Err(e) => Err(Error::new(formed_status(&e), format!("bad query: {}", e).as_str())),
+		};
+	}
+
 	pub fn version(&self) -> &str {
 		return self.msg.startline.2.as_str();
 	}
 
+	/// `state` return the value of type `T` that stored by `Server::state`.
+	pub fn state<T: Send + Sync + 'static>(&self) -> Option<&T> {
+		return match self.state.as_ref() {
+			Some(state) => state.get::<T>(),
+			None => None,
+		};
+	}
+
+	pub fn extensions(&self) -> &Extensions {
+		return &self.extensions;
+	}
+
+	pub fn extensions_mut(&mut self) -> &mut Extensions {
+		return &mut self.extensions;
+	}
+
+	/// `sni` return the server name that the client sent in the TLS handshake.
+	pub fn sni(&self) -> Option<&str> {
+		return match self.msg.conn {
+			Some(conn) => conn.sni.as_deref(),
+			None => None,
+		};
+	}
+
 	pub fn headers(&self) -> Option<&Headers> {
 		return self.msg.headers.as_ref();
 	}
 
+	/// `body` return the buffered body, it is `None` for the large sized body when `Server::defer_body` is enabled,
+	/// use `body_reader` for it.
 	pub fn body(&self) -> Option<&BytesMut> {
 		return self.msg.body.as_ref();
 	}
+
+	/// `body_reader` return a reader of the whole body, buffered or not.
+	pub fn body_reader(&mut self) -> BodyReader<'_, 'c> {
+		return BodyReader::new(&mut self.msg);
+	}
+
+	/// `read_body` read the whole body, return a `413` error if it is larger than `limit`.
+	pub(crate) async fn read_body(&mut self, limit: usize) -> Result<Vec<u8>, Error> {
+		let mut reader = self.body_reader();
+		let mut body = Vec::with_capacity(std::cmp::min(reader.remains(), limit));
+		let n = match (&mut reader).take(limit as u64 + 1).read_to_end(&mut body).await {
+			Ok(n) => n,
+			Err(_) => {
+				return Err(Error::newstatic(StatusCode::BadRequest, "incomplete body"));
+			}
+		};
+		if n > limit {
+			return Err(Error::newstatic(StatusCode::PayloadTooLarge, "body is too large"));
+		}
+		return Ok(body);
+	}
+
+	/// `form` parse the `application/x-www-form-urlencoded` body, the `charset` parameter can be `utf-8` or
+	/// `iso-8859-1`. return a `415` error for the other content types or charsets, and a `413` error if the body
+	/// is larger than `MAX_FORM_BODY_SIZE`.
+	pub async fn form(&mut self) -> Result<MultiMap, Error> {
+		let mut map = MultiMap::new();
+		for (k, v) in self.form_pairs().await? {
+			map.append(&k, &v);
+		}
+		return Ok(map);
+	}
+
+	/// `form_as` deserialize the form body to `T`, return a `422` error if the values do not match `T`,
+	/// see `form` for the others.
+	pub async fn form_as<T: DeserializeOwned>(&mut self) -> Result<T, Error> {
+		return match formed::from_pairs::<T>(&self.form_pairs().await?) {
+			Ok(v) => Ok(v),
+			Err(e) => Err(Error::new(formed_status(&e), format!("bad form: {}", e).as_str())),
+		};
+	}
+
+	/// `media_type` return the lowercase mime type and the parameters of the content type.
+	fn media_type(&self) -> (String, String) {
+		return match self.headers().and_then(|headers| headers.content_type()) {
+			Some(v) => match v.split_once(';') {
+				Some((mime_type, params)) => (mime_type.trim().to_ascii_lowercase(), params.to_string()),
+				None => (v.trim().to_ascii_lowercase(), String::new()),
+			},
+			None => (String::new(), String::new()),
+		};
+	}
+
+	/// `json` deserialize the `application/json`(or `*/*+json`) body. return a `415` error for the other content
+	/// types, a `413` error if the body is larger than `MAX_BUFFERED_BODY_SIZE`, a `422` error if the values
+	/// do not match `T`, and a `400` error with the position if the body is not valid.
+	#[cfg(feature = "json")]
+	pub async fn json<T: DeserializeOwned>(&mut self) -> Result<T, Error> {
+		let (mime_type, _) = self.media_type();
+		if mime_type != mime::JSON && !mime_type.ends_with("+json") {
+			return Err(Error::new(StatusCode::UnsupportedMediaType, format!("expect `{}` body", mime::JSON).as_str()));
+		}
+		let body = self.read_body(MAX_BUFFERED_BODY_SIZE).await?;
+		return match serde_json::from_slice::<T>(&body) {
+			Ok(v) => Ok(v),
+			Err(e) => {
+				let status = match e.classify() {
+					serde_json::error::Category::Data => StatusCode::UnprocessableEntity,
+					_ => StatusCode::BadRequest,
+				};
+				Err(Error::new(status, format!("bad json: {}", e).as_str()).with_source(e))
+			}
+		};
+	}
+
+	async fn form_pairs(&mut self) -> Result<Vec<(String, String)>, Error> {
+		let (mime_type, params) = self.media_type();
+		if mime_type != mime::WWW_FORM_URLENCODED {
+			return Err(Error::new(
+				StatusCode::UnsupportedMediaType,
+				format!("expect `{}` body", mime::WWW_FORM_URLENCODED).as_str(),
+			));
+		}
+		let charset = match multipart::param(&params, "charset") {
+			Some(label) => match Charset::from_label(&label) {
+				Some(charset) => charset,
+				None => {
+					return Err(Error::new(
+						StatusCode::UnsupportedMediaType,
+						format!("unsupported charset `{}`", label).as_str(),
+					));
+				}
+			},
+			None => Charset::Utf8,
+		};
+		let body = self.read_body(MAX_FORM_BODY_SIZE).await?;
+		return Ok(formed::parse_bytes(&body, charset));
+	}
+
+	/// `multipart` return the streaming parser of the `multipart/form-data` body, buffered or not.
+	/// return a `415` error if the content type is not multipart or the boundary is missing.
+	pub fn multipart(&mut self) -> Result<Multipart<'_>, Error> {
+		let boundary = match self.headers().and_then(|headers| headers.content_type()).and_then(|ct| multipart::boundary(ct)) {
+			Some(v) => v,
+			None => {
+				return Err(Error::newstatic(StatusCode::UnsupportedMediaType, "expect `multipart/form-data` body"));
+			}
+		};
+		return Ok(Multipart::new(self.body_reader(), &boundary));
+	}
+
+	/// `param` return the decoded value of the path parameter `name`, like `id` in `/users/:id`.
+	pub fn param(&self, name: &str) -> Option<&str> {
+		return self.params.iter().find(|(k, _)| k == name).map(|(_, v)| v.as_str());
+	}
+
+	pub fn params(&self) -> &Vec<(String, String)> {
+		return &self.params;
+	}
+
+	/// `param_as` parse the path parameter `name`, return a `400` error if it is missing or can not be parsed.
+	pub fn param_as<T: FromStr>(&self, name: &str) -> Result<T, Error> {
+		return match self.param(name) {
+			Some(v) => match v.parse::<T>() {
+				Ok(v) => Ok(v),
+				Err(_) => Err(Error::new(StatusCode::BadRequest, format!("bad path parameter `{}`", name).as_str())),
+			},
+			None => Err(Error::new(StatusCode::BadRequest, format!("missing path parameter `{}`", name).as_str())),
+		};
+	}
+
+	/// `set_params` decode and append the raw path parameters, the parameters of the outer routers are kept.
+	/// return the name of the first value that is not a valid escaped utf-8 string, and nothing is appended.
+	pub(crate) fn set_params(&mut self, raw: Vec<(String, String)>) -> Result<(), String> {
+		let len = self.params.len();
+		for (k, v) in raw {
+			let mut buf: Vec<u8> = Vec::with_capacity(v.len());
+			if !uricoding::decode_uri_component(&mut buf, &v) {
+				self.params.truncate(len);
+				return Err(k);
+			}
+			match String::from_utf8(buf) {
+				Ok(v) => {
+					self.params.push((k, v));
+				}
+				Err(_) => {
+					self.params.truncate(len);
+					return Err(k);
+				}
+			}
+		}
+		return Ok(());
+	}
 }
@@ -1,15 +1,29 @@
-use bytes::BytesMut;
+use bytes::{Bytes, BytesMut};
 use tokio::fs::File;
 
+use crate::h2tp::error::Error;
+use crate::h2tp::extract::Form;
+#[cfg(feature = "json")]
+use crate::h2tp::extract::Json;
+use crate::h2tp::headers::{self, hns, mime};
 use crate::h2tp::message::Message;
+use crate::h2tp::status_code::StatusCode;
+use crate::h2tp::utils::formed;
+use serde::Serialize;
 
 pub enum RespBody {
 	File(File),
+	/// a part of the file, the file has been seeked to the beginning of the part,
+	/// the `u64` is the length of the part.
+	FilePart(File, u64),
+	Static(&'static [u8]),
 }
 
 pub struct Response<'c> {
 	pub(crate) msg: Message<'c>,
 	pub(crate) body: Option<RespBody>,
+	/// the error that returned by the handler, its body is written by the `ErrorFormatter` of the server.
+	pub(crate) error: Option<Error>,
 }
 
 impl<'c> Response<'c> {
@@ -17,12 +31,26 @@ impl<'c> Response<'c> {
 		return Response {
 			msg: Message::new(),
 			body: None,
+			error: None,
 		};
 	}
 
 	pub fn clear(&mut self) {
 		self.msg.clear();
 		self.body = None;
+		self.error = None;
+	}
+
+	pub fn status(&mut self, code: StatusCode) -> &mut Self {
+		self.msg.startline.1.clear();
+		self.msg.startline.1.push_str(code.code().to_string().as_str());
+		self.msg.startline.2.clear();
+		self.msg.startline.2.push_str(code.msg());
+		return self;
+	}
+
+	pub fn headers(&mut self) -> headers::Builder<'_> {
+		return self.msg.headers_builder();
 	}
 
 	pub fn resetbody(&mut self) {
@@ -45,7 +73,30 @@ impl<'c> Response<'c> {
 		return self.msg.body.as_mut().unwrap();
 	}
 
-	pub fn ioe(&mut self, _err: std::io::Error) {}
+	/// `ioe` respond the io error, see `Error::from` for the status.
+	pub fn ioe(&mut self, err: std::io::Error) {
+		Error::from(err).into_response(self);
+	}
+
+	/// `json` write `v` as the `application/json` body, the body that already written is discarded.
+	/// it responds a `500` error if `v` can not be serialized.
+	#[cfg(feature = "json")]
+	pub fn json<T: Serialize + ?Sized>(&mut self, v: &T) -> &mut Self {
+		match serde_json::to_vec(v) {
+			Ok(body) => {
+				write_body(self, mime::JSON, &body);
+			}
+			Err(e) => {
+				Error::newstatic(StatusCode::InternalServerError, "bad json").with_source(e).into_response(self);
+			}
+		}
+		return self;
+	}
+
+	/// `error` return the error that the handler responded.
+	pub fn error(&self) -> Option<&Error> {
+		return self.error.as_ref();
+	}
 }
 
 impl<'c> std::io::Write for Response<'c> {
@@ -66,3 +117,244 @@ impl<'c> std::fmt::Write for Response<'c> {
 		buf.write_str(s)
 	}
 }
+
+/// `IntoResponse` is the return value of the extractor handlers, it writes itself into the response.
+///
+/// - `()`: nothing;
+/// - `String`, `&'static str`: a `text/plain` body;
+/// - `Vec<u8>`, `Bytes`, `&'static [u8]`: an `application/octet-stream` body;
+/// - `tokio::fs::File`: the file, the content type is `application/octet-stream` if not set;
+/// - `StatusCode`, `(StatusCode, T)`: the status, and `T`;
+/// - `Redirect`: the status and the `location` header;
+/// - `Form<T>`: an `application/x-www-form-urlencoded` body;
+/// - `Json<T>`: an `application/json` body, with the `json` feature;
+/// - `Error`, `Result<T, E>`.
+pub trait IntoResponse {
+	fn into_response(self, resp: &mut Response<'_>);
+}
+
+impl IntoResponse for () {
+	fn into_response(self, _resp: &mut Response<'_>) {}
+}
+
+fn write_body(resp: &mut Response<'_>, content_type: &str, body: &[u8]) {
+	resp.resetbody();
+	resp.headers().content_type(content_type);
+	let _ = std::io::Write::write(resp, body);
+}
+
+impl IntoResponse for String {
+	fn into_response(self, resp: &mut Response<'_>) {
+		write_body(resp, "text/plain; charset=utf-8", self.as_bytes());
+	}
+}
+
+impl IntoResponse for &'static str {
+	fn into_response(self, resp: &mut Response<'_>) {
+		write_body(resp, "text/plain; charset=utf-8", self.as_bytes());
+	}
+}
+
+impl IntoResponse for Vec<u8> {
+	fn into_response(self, resp: &mut Response<'_>) {
+		write_body(resp, mime::STREAM, &self);
+	}
+}
+
+impl IntoResponse for Bytes {
+	fn into_response(self, resp: &mut Response<'_>) {
+		write_body(resp, mime::STREAM, &self);
+	}
+}
+
+/// the static bytes are written without copying.
+impl IntoResponse for &'static [u8] {
+	fn into_response(self, resp: &mut Response<'_>) {
+		resp.resetbody();
+		resp.headers().content_type(mime::STREAM);
+		resp.body = Some(RespBody::Static(self));
+	}
+}
+
+impl IntoResponse for File {
+	fn into_response(self, resp: &mut Response<'_>) {
+		resp.resetbody();
+		let typed = match resp.msg.headers.as_ref() {
+			Some(headers) => headers.getone(hns::CONTENT_TYPE).is_some(),
+			None => false,
+		};
+		if !typed {
+			resp.headers().content_type(mime::STREAM);
+		}
+		resp.body = Some(RespBody::File(self));
+	}
+}
+
+impl IntoResponse for StatusCode {
+	fn into_response(self, resp: &mut Response<'_>) {
+		resp.status(self);
+	}
+}
+
+/// the status wins over the status that written by `T`, and the status of the `Error` that `T` responded.
+impl<T: IntoResponse> IntoResponse for (StatusCode, T) {
+	fn into_response(self, resp: &mut Response<'_>) {
+		self.1.into_response(resp);
+		resp.status(self.0);
+		if let Some(mut err) = resp.error.take() {
+			err.set_statuscode(self.0);
+			write_body(resp, "text/plain; charset=utf-8", err.msg().as_bytes());
+			resp.error = Some(err);
+		}
+	}
+}
+
+/// `Redirect` is a redirect response without body.
+pub struct Redirect {
+	code: StatusCode,
+	location: String,
+}
+
+impl Redirect {
+	/// `found` is a `302`, the method may be changed to `GET` by the clients.
+	pub fn found(location: &str) -> Self {
+		return Self { code: StatusCode::Found, location: location.to_string() };
+	}
+
+	/// `see_other` is a `303`, the client should `GET` the location, like after a form submission.
+	pub fn see_other(location: &str) -> Self {
+		return Self { code: StatusCode::SeeOther, location: location.to_string() };
+	}
+
+	/// `temporary` is a `307`, the method and the body are kept.
+	pub fn temporary(location: &str) -> Self {
+		return Self { code: StatusCode::TemporaryRedirect, location: location.to_string() };
+	}
+
+	/// `permanent` is a `308`, the method and the body are kept.
+	pub fn permanent(location: &str) -> Self {
+		return Self { code: StatusCode::PermanentRedirect, location: location.to_string() };
+	}
+}
+
+impl IntoResponse for Redirect {
+	fn into_response(self, resp: &mut Response<'_>) {
+		resp.resetbody();
+		resp.status(self.code);
+		resp.headers().reset(hns::LOCATION, &self.location);
+	}
+}
+
+impl<T: Serialize> IntoResponse for Form<T> {
+	fn into_response(self, resp: &mut Response<'_>) {
+		match formed::to_string(&self.0) {
+			Ok(body) => {
+				write_body(resp, mime::WWW_FORM_URLENCODED, body.as_bytes());
+			}
+			Err(e) => {
+				Error::newstatic(StatusCode::InternalServerError, "bad form").with_source(e).into_response(resp);
+			}
+		}
+	}
+}
+
+#[cfg(feature = "json")]
+impl<T: Serialize> IntoResponse for Json<T> {
+	fn into_response(self, resp: &mut Response<'_>) {
+		resp.json(&self.0);
+	}
+}
+
+/// the status, the headers and the message(as plain text) of the error are written, the body that already written
+/// is discarded. the server replaces the body by its `ErrorFormatter`.
+impl IntoResponse for Error {
+	fn into_response(self, resp: &mut Response<'_>) {
+		resp.status(self.statuscode());
+		for (k, v) in self.headers() {
+			resp.headers().append(k, v);
+		}
+		write_body(resp, "text/plain; charset=utf-8", self.msg().as_bytes());
+		resp.error = Some(self);
+	}
+}
+
+impl<T: IntoResponse, E: IntoResponse> IntoResponse for Result<T, E> {
+	fn into_response(self, resp: &mut Response<'_>) {
+		match self {
+			Ok(v) => v.into_response(resp),
+			Err(e) => e.into_response(resp),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use bytes::Bytes;
+	use std::collections::BTreeMap;
+	use crate::h2tp::response::{IntoResponse, Redirect, RespBody};
+	use crate::h2tp::{Error, Form, Response, StatusCode};
+
+	fn render<T: IntoResponse>(v: T) -> (String, Option<String>, Vec<u8>) {
+		let mut resp = Response::new();
+		let _ = std::io::Write::write(&mut resp, b"discarded");
+		v.into_response(&mut resp);
+		let ct = resp.msg.headers.as_ref().and_then(|h| h.getone("content-type")).cloned();
+		let body = resp.msg.body.as_deref().unwrap_or_default().to_vec();
+		return (resp.msg.startline.1.clone(), ct, body);
+	}
+
+	#[test]
+	fn test_into_response() {
+		assert_eq!(render("hi"), ("".to_string(), Some("text/plain; charset=utf-8".to_string()), b"hi".to_vec()));
+		assert_eq!(render((StatusCode::Created, String::from("ok"))).0, "201");
+		assert_eq!(render(Bytes::from_static(b"\x00")).1.unwrap(), "application/octet-stream");
+		assert_eq!(render(StatusCode::Accepted).0, "202");
+
+		let r: Result<&str, Error> = Err(Error::newstatic(StatusCode::Forbidden, "nope"));
+		assert_eq!(render(r), ("403".to_string(), Some("text/plain; charset=utf-8".to_string()), b"nope".to_vec()));
+		assert_eq!(render((StatusCode::NotFound, Error::newstatic(StatusCode::Forbidden, "x"))).0, "404");
+
+		let mut resp = Response::new();
+		(StatusCode::NotFound, Error::newstatic(StatusCode::Forbidden, StatusCode::Forbidden.msg())).into_response(&mut resp);
+		assert_eq!(resp.msg.startline.1, "404");
+		let err = resp.error().unwrap();
+		assert_eq!(err.statuscode().code(), 404);
+		assert_eq!(err.to_string(), "404 Not Found");
+		assert_eq!(resp.msg.body.as_deref().unwrap(), b"Not Found");
+
+		let mut resp = Response::new();
+		let r: Result<(), Error> = Err(Error::newstatic(StatusCode::Forbidden, "x"));
+		(StatusCode::NotFound, r).into_response(&mut resp);
+		assert_eq!(resp.error().unwrap().to_string(), "404 x");
+
+		let mut resp = Response::new();
+		Redirect::see_other("/done?a=1").into_response(&mut resp);
+		assert_eq!(resp.msg.startline.1, "303");
+		assert_eq!(resp.msg.headers.as_ref().unwrap().getone("location").unwrap(), "/done?a=1");
+		assert!(resp.msg.body.as_ref().map(|b| b.is_empty()).unwrap_or(true));
+
+		let mut resp = Response::new();
+		let data: &'static [u8] = b"static";
+		data.into_response(&mut resp);
+		assert!(matches!(resp.body, Some(RespBody::Static(b"static"))));
+
+		let form: BTreeMap<&str, &str> = [("q", "a b"), ("tag", "x&y")].into_iter().collect();
+		assert_eq!(
+			render(Form(form)),
+			("".to_string(), Some("application/x-www-form-urlencoded".to_string()), b"q=a+b&tag=x%26y".to_vec())
+		);
+		assert_eq!(render(Form(1)).0, "500");
+	}
+
+	#[cfg(feature = "json")]
+	#[test]
+	fn test_json() {
+		use crate::h2tp::Json;
+
+		let v: BTreeMap<&str, Vec<u8>> = [("a", vec![1, 2])].into_iter().collect();
+		assert_eq!(render(Json(v)), ("".to_string(), Some("application/json".to_string()), br#"{"a":[1,2]}"#.to_vec()));
+
+		let v: BTreeMap<Vec<u8>, u8> = [(vec![1], 1)].into_iter().collect();
+		assert_eq!(render(Json(v)).0, "500");
+	}
+}
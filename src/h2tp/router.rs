@@ -1,8 +1,11 @@
 use std::time::Duration;
 use async_trait::async_trait;
 
-use crate::h2tp::{Request, Response};
-use crate::h2tp::handler::{Handler};
+use crate::h2tp::error::Error;
+use crate::h2tp::response::IntoResponse;
+use crate::h2tp::{hns, Request, Response};
+use crate::h2tp::handler::{Handler, HandlerFuture};
+use crate::h2tp::status_code::StatusCode;
 
 pub enum MiddlewareControl {
 	Continue,
@@ -11,14 +14,127 @@ pub enum MiddlewareControl {
 }
 
 /// `Middleware` will return by the `Router`, then be called one by one in `Router.handle`.
+/// each middleware wraps the rest of the chain: it can call `next.run(req, resp)` to run the
+/// rest of the middleware and the handler, then observe or modify the response.
 #[async_trait]
 pub trait Middleware: Send + Sync {
-	/// `handle` take `req` and `resp`, return a `MiddlewareControl`.
+	/// `handle` take `req`, `resp` and the rest of the chain, return a `MiddlewareControl`.
+	/// if `next.run` has been called, the returned value is ignored, otherwise:
 	/// - if return `::Continue`, the loop of middleware group call will continue;
 	/// - if return `::Break`, the loop of middleware group call will break;
 	/// - if return `::Return`, the loop of middleware group call will break and
 	/// the `Router.handle` will return, and the `handler` which returned by `Router.find` will not execute;
-	async fn handle<'a, 'c>(&self, req: &'a mut Request<'c>, resp: &'a mut Response<'c>) -> MiddlewareControl;
+	async fn handle<'a, 'c, 'n>(&self, req: &'a mut Request<'c>, resp: &'a mut Response<'c>, next: Next<'n>) -> MiddlewareControl;
+}
+
+#[derive(Clone, Copy)]
+enum Endpoint<'n> {
+	/// call `Router.find`, then run the second group of the middleware and the handler.
+	Find(&'n dyn Router, &'n [Box<dyn Middleware>]),
+	Handler(&'n dyn Handler),
+}
+
+/// `Chain` is a group of middleware and what to do after them.
+#[derive(Clone, Copy)]
+struct Chain<'n> {
+	middleware: &'n [Box<dyn Middleware>],
+	endpoint: Endpoint<'n>,
+}
+
+impl<'n> Chain<'n> {
+	fn run<'a, 'c>(self, req: &'a mut Request<'c>, resp: &'a mut Response<'c>) -> HandlerFuture<'a>
+	where
+		'n: 'a,
+	{
+		return Box::pin(async move {
+			let (first, rest) = match self.middleware.split_first() {
+				Some(v) => v,
+				None => {
+					match self.endpoint {
+						Endpoint::Handler(handler) => {
+							handler.handle(req, resp).await;
+						}
+						Endpoint::Find(router, after) => match router.find(req) {
+							Err(e) => {
+								router.onerror(e, req, resp).await;
+							}
+							Ok(handler) => {
+								let chain = Chain {
+									middleware: after,
+									endpoint: Endpoint::Handler(handler),
+								};
+								chain.run(req, resp).await;
+							}
+						},
+					}
+					return;
+				}
+			};
+
+			let rest = Chain {
+				middleware: rest,
+				endpoint: self.endpoint,
+			};
+			let mut called = false;
+			let control = first
+				.handle(
+					req,
+					resp,
+					Next {
+						chain: rest,
+						called: &mut called,
+					},
+				)
+				.await;
+			if called {
+				return;
+			}
+			match control {
+				MiddlewareControl::Continue => {
+					rest.run(req, resp).await;
+				}
+				MiddlewareControl::Break => {
+					let chain = Chain {
+						middleware: &[],
+						endpoint: self.endpoint,
+					};
+					chain.run(req, resp).await;
+				}
+				MiddlewareControl::Return => {}
+			}
+		});
+	}
+}
+
+/// `run_with` run `middleware` around `handler`.
+pub(crate) fn run_with<'a, 'c, 'n: 'a>(
+	middleware: &'n [Box<dyn Middleware>],
+	handler: &'n dyn Handler,
+	req: &'a mut Request<'c>,
+	resp: &'a mut Response<'c>,
+) -> HandlerFuture<'a> {
+	let chain = Chain {
+		middleware,
+		endpoint: Endpoint::Handler(handler),
+	};
+	return chain.run(req, resp);
+}
+
+/// `Next` is the rest of the middleware chain, include the handler.
+pub struct Next<'n> {
+	chain: Chain<'n>,
+	called: &'n mut bool,
+}
+
+impl<'n> Next<'n> {
+	/// `run` run the rest of the chain.
+	pub fn run<'a, 'c>(self, req: &'a mut Request<'c>, resp: &'a mut Response<'c>) -> HandlerFuture<'a>
+	where
+		'n: 'a,
+	{
+		*self.called = true;
+		return self.chain.run(req, resp);
+	}
 }
 
 pub enum RouterFindError {
@@ -26,6 +142,8 @@ pub enum RouterFindError {
 	MethodNotAllow(String),
 	RedirectTo(String),
 	RetryAfter(Duration),
+	/// the value of the path parameter is not a valid escaped utf-8 string.
+	BadParam(String),
 	Undefined,
 }
 
@@ -34,54 +152,177 @@ pub trait Router: Handler {
 	/// `middleware` return two groups of middleware, the first group to execute before `find` and
 	/// the second group will execute after a successful `find` call.
 	fn middleware<'a, 'c>(&self, req: &'a Request<'c>) -> (&Vec<Box<dyn Middleware>>, &Vec<Box<dyn Middleware>>);
-	/// `find` return a `& dyn Handler` or `RouterFindError`
-	fn find<'a, 'c>(&self, req: &'a Request<'c>) -> Result<&dyn Handler, RouterFindError>;
-	/// `onerror` handle the error that returned by `find`
-	async fn onerror<'a, 'c>(&self, err: RouterFindError, req: &'a mut Request<'c>, resp: &'a mut Response<'c>);
+	/// `find` return a `& dyn Handler` or `RouterFindError`, the path parameters can be stored to `req`.
+	fn find<'a, 'c>(&self, req: &'a mut Request<'c>) -> Result<&dyn Handler, RouterFindError>;
+	/// `redirect_code` return the status of the `RedirectTo` responses that written by the default `onerror`.
+	fn redirect_code(&self) -> StatusCode {
+		return StatusCode::PermanentRedirect;
+	}
 
-	async fn handle<'a, 'c, 'h: 'a>(&'h self, req: &'a mut Request<'c>, resp: &'a mut Response<'c>) {
+	/// `onerror` handle the error that returned by `find`, the default implementation responds an `Error`,
+	/// so its body is written by the `ErrorFormatter` of the server like the errors of the handlers:
+	/// - `NotFound`: `404`;
+	/// - `MethodNotAllow(allowed)`: `405` with the `allow` header;
+	/// - `RedirectTo(url)`: `redirect_code()`(`308` by default) with the `location` header and no body;
+	/// - `RetryAfter(d)`: `503` with the `retry-after` header in seconds;
+	/// - `BadParam(name)`: `400`;
+	/// - `Undefined`: `500`;
+	async fn onerror<'a, 'c>(&self, err: RouterFindError, _req: &'a mut Request<'c>, resp: &'a mut Response<'c>) {
+		let err = match err {
+			RouterFindError::NotFound => Error::newstatic(StatusCode::NotFound, StatusCode::NotFound.msg()),
+			RouterFindError::MethodNotAllow(allowed) => {
+				Error::newstatic(StatusCode::MethodNotAllowed, StatusCode::MethodNotAllowed.msg()).with_header(hns::ALLOW, &allowed)
+			}
+			RouterFindError::RedirectTo(url) => {
+				resp.status(self.redirect_code());
+				resp.headers().reset(hns::LOCATION, &url);
+				return;
+			}
+			RouterFindError::RetryAfter(d) => {
+				let secs = d.as_secs() + if d.subsec_nanos() > 0 { 1 } else { 0 };
+				Error::newstatic(StatusCode::ServiceUnavailable, StatusCode::ServiceUnavailable.msg())
+					.with_header(hns::RETRY_AFTER, secs.to_string().as_str())
+			}
+			RouterFindError::BadParam(name) => {
+				Error::new(StatusCode::BadRequest, format!("bad path parameter `{}`", name).as_str())
+			}
+			RouterFindError::Undefined => {
+				Error::newstatic(StatusCode::InternalServerError, StatusCode::InternalServerError.msg())
+			}
+		};
+		err.into_response(resp);
+	}
+
+	async fn handle<'a, 'c, 'h: 'a>(&'h self, req: &'a mut Request<'c>, resp: &'a mut Response<'c>)
+	where
+		Self: Sized,
+	{
 		let (before, after) = self.middleware(req);
+		let chain = Chain {
+			middleware: before,
+			endpoint: Endpoint::Find(self, after),
+		};
+		chain.run(req, resp).await;
+	}
+}
 
-		macro_rules! run_middleware {
-		    ($name:ident) => {
-				for middleware in $name {
-					match middleware.handle(req, resp).await {
-						MiddlewareControl::Continue => {
-							continue;
-						}
-						MiddlewareControl::Break => {
-							break;
-						}
-						MiddlewareControl::Return => {
-							return;
-						}
-					}
-				}
-			};
+/// `impl_handler_for_router` implement `Handler` for a `Router` by calling `Router::handle`.
+macro_rules! impl_handler_for_router {
+    ($name:ident) => {
+		#[async_trait::async_trait]
+		impl crate::h2tp::handler::Handler for $name {
+			#[inline]
+			async fn handle<'a, 'c, 'h: 'a>(
+				&'h self,
+				req: &'a mut crate::h2tp::Request<'c>,
+				resp: &'a mut crate::h2tp::Response<'c>,
+			) -> () {
+				crate::h2tp::router::Router::handle(self, req, resp).await
+			}
 		}
+	};
+}
+
+pub(crate) use impl_handler_for_router;
+
+#[cfg(test)]
+mod tests {
+	use std::time::Duration;
+	use async_trait::async_trait;
+	use crate::h2tp::error::{DefaultErrorFormatter, ErrorFormatter};
+	use crate::h2tp::handler::Handler;
+	use crate::h2tp::router::{Middleware, Router, RouterFindError};
+	use crate::h2tp::{hns, Request, Response};
+
+	struct ErrRouter {
+		mws: Vec<Box<dyn Middleware>>,
+	}
+
+	#[async_trait]
+	impl Router for ErrRouter {
+		fn middleware<'a, 'c>(&self, _req: &'a Request<'c>) -> (&Vec<Box<dyn Middleware>>, &Vec<Box<dyn Middleware>>) {
+			return (&self.mws, &self.mws);
+		}
+
+		fn find<'a, 'c>(&self, req: &'a mut Request<'c>) -> Result<&dyn Handler, RouterFindError> {
+			return Err(match req.path() {
+				"/405" => RouterFindError::MethodNotAllow("GET, HEAD".to_string()),
+				"/308" => RouterFindError::RedirectTo("/x/".to_string()),
+				"/503" => RouterFindError::RetryAfter(Duration::from_millis(1500)),
+				"/500" => RouterFindError::Undefined,
+				"/400" => RouterFindError::BadParam("id".to_string()),
+				_ => RouterFindError::NotFound,
+			});
+		}
+	}
 
-		run_middleware!(before);
+	impl_handler_for_router!(ErrRouter);
 
-		match self.find(req) {
-			Err(e) => {
-				self.onerror(e, req, resp).await;
+	async fn call(router: &ErrRouter, path: &str, accept: Option<&str>) -> Response<'static> {
+		let mut req = Request::new();
+		req.msg.startline.0.push_str("GET");
+		req.msg.startline.1.push_str(path);
+		match accept {
+			Some(v) => {
+				req.msg.headers_builder().append(hns::ACCEPT, v);
 			}
-			Ok(handler) => {
-				run_middleware!(after);
-				handler.handle(req, resp).await;
+			None => {}
+		}
+		let mut resp = Response::new();
+		Handler::handle(router, &mut req, &mut resp).await;
+		match resp.error.take() {
+			Some(err) => {
+				DefaultErrorFormatter.format(&err, &req, &mut resp);
 			}
+			None => {}
 		}
+		return resp;
 	}
-}
 
-// macro_rules! impl_handler_for_router {
-//     ($name:ident) => {
-// 		#[async_trait]
-// 		impl Handler for $name {
-// 			#[inline]
-// 			async fn handle<'a, 'c, 'h: 'a>(&'h self, req: &'a mut Request<'c>, resp: &'a mut Response<'c>) -> () {
-// 				Router::handle(self, req, resp).await
-// 			}
-// 		}
-// 	};
-// }
+	fn header<'a>(resp: &'a Response, k: &str) -> &'a str {
+		return resp.msg.headers.as_ref().unwrap().getone(k).unwrap().as_str();
+	}
+
+	fn body(resp: &Response) -> String {
+		return String::from_utf8(resp.msg.body.as_ref().map(|b| b.to_vec()).unwrap_or_default()).unwrap();
+	}
+
+	#[tokio::test]
+	async fn test_onerror() {
+		let router = ErrRouter { mws: vec![] };
+
+		let resp = call(&router, "/", None).await;
+		assert_eq!(resp.msg.startline.1, "404");
+		assert_eq!(body(&resp), "Not Found");
+
+		let resp = call(&router, "/", Some("text/html,application/xhtml+xml,*/*;q=0.8")).await;
+		assert!(body(&resp).contains("<h1>404 Not Found</h1>"));
+
+		let resp = call(&router, "/", Some("application/json;q=0.9, text/html;q=0.5")).await;
+		assert_eq!(header(&resp, hns::CONTENT_TYPE), "application/json");
+		assert_eq!(body(&resp), r#"{"code":404,"message":"Not Found"}"#);
+
+		let resp = call(&router, "/405", Some("text/html, application/json")).await;
+		assert_eq!(resp.msg.startline.1, "405");
+		assert_eq!(header(&resp, hns::ALLOW), "GET, HEAD");
+		assert!(body(&resp).starts_with("<!DOCTYPE html>"));
+
+		let resp = call(&router, "/308", None).await;
+		assert_eq!(resp.msg.startline.1, "308");
+		assert_eq!(header(&resp, hns::LOCATION), "/x/");
+		assert_eq!(body(&resp), "");
+
+		let resp = call(&router, "/503", Some("application/*")).await;
+		assert_eq!(resp.msg.startline.1, "503");
+		assert_eq!(header(&resp, hns::RETRY_AFTER), "2");
+		assert!(body(&resp).contains("503"));
+
+		let resp = call(&router, "/400", None).await;
+		assert_eq!(resp.msg.startline.1, "400");
+		assert_eq!(body(&resp), "bad path parameter `id`");
+
+		let resp = call(&router, "/500", Some("text/*;q=0, */*")).await;
+		assert_eq!(resp.msg.startline.1, "500");
+		assert_eq!(header(&resp, hns::CONTENT_TYPE), "application/json");
+	}
+}
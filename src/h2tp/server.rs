@@ -1,5 +1,7 @@
 use crate::h2tp::cfg::ATOMIC_ORDERING;
-use crate::h2tp::conn::Conn;
+use crate::h2tp::conn::{Conn, ConnGuard};
+use crate::h2tp::error::{DefaultErrorFormatter, ErrorFormatter};
+use crate::h2tp::extensions::Extensions;
 use crate::h2tp::handler::Handler;
 use crate::h2tp::FuncHandler;
 use core::fmt;
@@ -63,6 +65,10 @@ pub struct Server {
 	shutdown_signal_receiver: UnboundedReceiver<()>,
 	shutdown_done_sender: UnboundedSender<()>,
 	shutdownhandler: Arc<Mutex<ShutdownHandler>>,
+	zerocopy: bool,
+	defer_body: bool,
+	state: Extensions,
+	formatter: Arc<dyn ErrorFormatter>,
 }
 
 pub struct ShutdownHandler {
@@ -107,6 +113,10 @@ impl Server {
 				signal_sender: stx,
 				done_receiver: drx,
 			})),
+			zerocopy: true,
+			defer_body: false,
+			state: Extensions::new(),
+			formatter: Arc::new(DefaultErrorFormatter),
 		};
 	}
 
@@ -117,6 +127,30 @@ impl Server {
 		});
 	}
 
+	/// `zerocopy` enable or disable sending files by `sendfile(2)` on the plaintext connections, enabled by default.
+	/// it is only available on linux, the tls connections always copy the files through the userspace buffers.
+	pub fn zerocopy(&mut self, v: bool) {
+		self.zerocopy = v;
+	}
+
+	/// `defer_body` enable or disable leaving the sized request bodies larger than `MAX_BUFFERED_BODY_SIZE` in the connection,
+	/// disabled by default. when enabled, `Request::body` is `None` for such bodies, and the handlers read them by
+	/// `Request::body_reader`, like `Uploader` and `WebDav` do.
+	pub fn defer_body(&mut self, v: bool) {
+		self.defer_body = v;
+	}
+
+	/// `state` store a value that shared by all the requests, it can be got by `Request::state`.
+	/// the values of the same type are replaced, and the state is frozen when `listen` is called.
+	pub fn state<T: Send + Sync + 'static>(&mut self, v: T) {
+		self.state.insert(v);
+	}
+
+	/// `error_formatter` set the formatter of the error responses, `DefaultErrorFormatter` by default.
+	pub fn error_formatter(&mut self, formatter: Box<dyn ErrorFormatter>) {
+		self.formatter = Arc::from(formatter);
+	}
+
 	pub fn shutdownhandler(&self) -> Arc<Mutex<ShutdownHandler>> {
 		return self.shutdownhandler.clone();
 	}
@@ -140,6 +174,7 @@ impl Server {
 
 		println!("Listening @ {}...", addr);
 
+		let state = Arc::new(std::mem::take(&mut self.state));
 		let alive_conn_count = Arc::new(AtomicU64::new(0));
 		let closing = Arc::new(AtomicBool::new(false));
 		let lref = self.listener.as_ref().unwrap();
@@ -162,6 +197,10 @@ impl Server {
 							let accc = Arc::clone(&alive_conn_count);
 							let cc = Arc::clone(&closing);
 							let hc = Arc::clone(&handler);
+							let zerocopy = self.zerocopy;
+							let defer_body = self.defer_body;
+							let state = Arc::clone(&state);
+							let formatter = Arc::clone(&self.formatter);
 
 							match tls_acceptor.as_ref() {
 								Some(tls)=>{
@@ -169,10 +208,12 @@ impl Server {
 									tokio::spawn(async move {
 										match acceptor.accept(stream).await {
 											Ok(tls_stream) => {
-												accc.fetch_add(1, ATOMIC_ORDERING);
+												let _guard = ConnGuard::new(accc);
 												let mut conn = Conn::newservtls(addr, tls_stream, cc);
+												conn.defer_body = defer_body;
+												conn.state = Some(state);
+												conn.formatter = formatter;
 												conn.as_server(hc).await;
-												accc.fetch_sub(1, ATOMIC_ORDERING);
 											}
 											Err(_) => {}
 										}
@@ -180,10 +221,13 @@ impl Server {
 								}
 								None=>{
 									tokio::spawn(async move {
-										accc.fetch_add(1, ATOMIC_ORDERING);
+										let _guard = ConnGuard::new(accc);
 										let mut conn = Conn::new(addr, stream, cc);
+										conn.zerocopy = zerocopy;
+										conn.defer_body = defer_body;
+										conn.state = Some(state);
+										conn.formatter = formatter;
 										conn.as_server(hc).await;
-										accc.fetch_sub(1, ATOMIC_ORDERING);
 									});
 								}
 							}
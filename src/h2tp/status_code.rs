@@ -69,6 +69,10 @@ pub enum StatusCode {
 }
 
 impl StatusCode {
+	pub fn code(&self) -> u16 {
+		return *self as u16;
+	}
+
 	pub fn msg(&self) -> &'static str {
 		match self {
 			StatusCode::Continue => "Continue",
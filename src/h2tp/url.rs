@@ -1,6 +1,9 @@
+use crate::h2tp::utils::formed::{self, FormedError};
 use crate::h2tp::utils::multi_map::MultiMap;
 use core::fmt;
+use serde::de::DeserializeOwned;
 use std::fmt::{Display, Error, Formatter, Write};
+use std::sync::OnceLock;
 
 use super::utils::uricoding;
 
@@ -195,6 +198,7 @@ pub struct Url<'a> {
 	fragment: &'a str,
 
 	setter: Option<Setter>,
+	query: OnceLock<MultiMap>,
 }
 
 pub struct ParseErr {
@@ -237,7 +241,6 @@ impl<'a> fmt::Debug for Url<'a> {
 	}
 }
 
-const PATH_MISSING: &str = "Path Missing";
 const IPV6_ENDING_CHAR_MISSING: &str = "Ipv6 Ending-Char Missing";
 
 macro_rules! getter {
@@ -269,6 +272,7 @@ impl<'a> Url<'a> {
 			rawquery: "",
 			fragment: "",
 			setter: None,
+			query: OnceLock::new(),
 		};
 	}
 
@@ -281,12 +285,13 @@ impl<'a> Url<'a> {
 	}
 
 	/// #### url:
-	/// `[scheme://][[username:][password]@][host:port]/[path][?rawquery][#fragment]`
+	/// `[scheme://][[username:][password]@][host:port][/path][?rawquery][#fragment]`, the path is `/` if it is missing.
 	/// #### host:
 	/// - ipv4 or hostname: direct
 	/// - ipv6: [2001:db8:1f70::999:de8:7648:6e8]
 	pub fn from(&mut self, v: &'a str) -> Option<ParseErr> {
 		let mut v = &v[0..];
+		self.query = OnceLock::new();
 		match v.find("://") {
 			Some(idx) => {
 				self.scheme = &v[..idx];
@@ -295,11 +300,60 @@ impl<'a> Url<'a> {
 			None => {}
 		}
 
-		match v.find('@') {
+		// the asterisk-form of `OPTIONS *`
+		if v == "*" {
+			self.path = v;
+			return None;
+		}
+
+		let idx = v.find(|c| c == '/' || c == '?' || c == '#').unwrap_or(v.len());
+		match self.authority(&v[..idx]) {
+			Some(e) => {
+				return Some(e);
+			}
+			None => {}
+		}
+		v = &v[idx..];
+
+		match v.find('?') {
+			Some(idx) => {
+				self.path = &v[..idx];
+				v = &v[idx + 1..];
+
+				match v.find('#') {
+					Some(idx) => {
+						self.rawquery = &v[..idx];
+						self.fragment = &v[idx + 1..];
+					}
+					None => {
+						self.rawquery = v;
+					}
+				}
+			}
+			None => match v.find('#') {
+				Some(idx) => {
+					self.path = &v[..idx];
+					self.fragment = &v[idx + 1..];
+				}
+				None => {
+					self.path = v;
+				}
+			},
+		}
+		if self.path.is_empty() {
+			self.path = "/";
+		}
+		return None;
+	}
+
+	/// `authority` parse the `[[username:][password]@][host][:port]`.
+	pub(crate) fn authority(&mut self, v: &'a str) -> Option<ParseErr> {
+		let mut v = v;
+		match v.rfind('@') {
 			Some(idx) => {
 				let userinfo = &v[..idx];
 				v = &v[idx + 1..];
-				match userinfo.find(b':' as char) {
+				match userinfo.find(':') {
 					Some(idx) => {
 						self.username = &userinfo[..idx];
 						self.password = &userinfo[idx + 1..];
@@ -312,69 +366,34 @@ impl<'a> Url<'a> {
 			None => {}
 		}
 
-		let mut hosted = false;
-
-		if v.chars().nth(0) == Some('[') {
+		if v.starts_with('[') {
 			match v.find(']') {
 				Some(idx) => {
 					self.host = &v[..idx + 1];
 					v = &v[idx + 1..];
-					hosted = true;
 				}
 				None => {
 					return Some(ParseErr::new(IPV6_ENDING_CHAR_MISSING));
 				}
 			}
-		} else {
-			match v.rfind(':') {
-				Some(idx) => {
-					self.host = &v[..idx];
-					v = &v[idx + 1..];
-					hosted = true;
+			match v.strip_prefix(':') {
+				Some(port) => {
+					self.port = port;
 				}
 				None => {}
 			}
+			return None;
 		}
 
-		match v.find('/') {
+		match v.rfind(':') {
 			Some(idx) => {
-				if !hosted {
-					self.host = &v[..idx];
-				} else {
-					self.port = &v[..idx];
-				}
-				v = &v[idx..];
+				self.host = &v[..idx];
+				self.port = &v[idx + 1..];
 			}
 			None => {
-				return Some(ParseErr::new(PATH_MISSING));
+				self.host = v;
 			}
 		}
-
-		match v.find('?') {
-			Some(idx) => {
-				self.path = &v[..idx];
-				v = &v[idx + 1..];
-
-				match v.find('#') {
-					Some(idx) => {
-						self.rawquery = &v[..idx];
-						self.fragment = &v[idx + 1..];
-					}
-					None => {
-						self.rawquery = v;
-					}
-				}
-			}
-			None => match v.find('#') {
-				Some(idx) => {
-					self.path = &v[..idx];
-					self.fragment = &v[idx + 1..];
-				}
-				None => {
-					self.path = v;
-				}
-			},
-		}
 		return None;
 	}
 
@@ -385,8 +404,40 @@ impl<'a> Url<'a> {
 		return Builder::new(self.setter.as_mut().unwrap());
 	}
 
+	/// `to` write the url to `dist`, the values that set by the `builder` win over the parsed ones.
 	pub fn to<W: Write>(&self, dist: &mut W) -> Result<(), Error> {
-		dist.write_char('-' as char)
+		let scheme = self.scheme();
+		if !scheme.is_empty() {
+			write!(dist, "{}://", scheme)?;
+		}
+		let username = self.username();
+		if !username.is_empty() {
+			dist.write_str(username)?;
+			let password = self.password();
+			if !password.is_empty() {
+				write!(dist, ":{}", password)?;
+			}
+			dist.write_char('@')?;
+		}
+		dist.write_str(self.host())?;
+		let port = self.port();
+		if port != 0 {
+			write!(dist, ":{}", port)?;
+		}
+		dist.write_str(self.path())?;
+
+		let mut buf: Vec<u8> = vec![];
+		let rawquery = match self.setter.as_ref() {
+			Some(setter) if setter.query.is_some() => setter.rawquery(&mut buf),
+			_ => self.rawquery,
+		};
+		if !rawquery.is_empty() {
+			write!(dist, "?{}", rawquery)?;
+		}
+		if !self.fragment.is_empty() {
+			write!(dist, "#{}", self.fragment)?;
+		}
+		return Ok(());
 	}
 
 	getter!(scheme, 0);
@@ -397,10 +448,10 @@ impl<'a> Url<'a> {
 	pub fn port(&self) -> u16 {
 		let v: &str;
 		match self.setter.as_ref() {
-			Some(setter) => {
+			Some(setter) if !setter.parts[4].is_empty() => {
 				v = setter.parts[4].as_str();
 			}
-			None => {
+			_ => {
 				v = self.port;
 			}
 		}
@@ -435,7 +486,30 @@ impl<'a> Url<'a> {
 		}
 	}
 
-	pub fn query(&self) {}
+	/// `query` return the query that set by the `builder`, or the parsed `rawquery`, it is parsed once
+	/// when first called.
+	pub fn query(&self) -> &MultiMap {
+		match self.setter.as_ref() {
+			Some(setter) => match setter.query.as_ref() {
+				Some(query) => {
+					return query;
+				}
+				None => {}
+			},
+			None => {}
+		}
+		return self.query.get_or_init(|| formed::to_multimap(self.rawquery));
+	}
+
+	/// `query_as` deserialize the query to `T`, a repeated key can be deserialized to a `Vec`.
+	pub fn query_as<T: DeserializeOwned>(&self) -> Result<T, FormedError> {
+		let mut pairs = vec![];
+		self.query().each(|k, v, _| {
+			pairs.push((k.to_string(), v.to_string()));
+			return true;
+		});
+		return formed::from_pairs(&pairs);
+	}
 }
 
 #[cfg(test)]
@@ -489,4 +563,69 @@ mod tests {
 		println!("{}", url.rawquery(&mut opt_dest));
 		println!("{:?}", opt_dest);
 	}
+
+	#[test]
+	fn test_to() {
+		let mut url = Url::parse("https://u:p@example.com:8080/a%20b?x=1#top").unwrap();
+		let mut v = String::new();
+		url.to(&mut v).unwrap();
+		assert_eq!(v, "https://u:p@example.com:8080/a%20b?x=1#top");
+
+		let mut builder = url.builder();
+		builder.path("/c").query().append("q", "a b&c");
+		let mut v = String::new();
+		url.to(&mut v).unwrap();
+		assert_eq!(v, "https://u:p@example.com:8080/c?q=a%20b%26c#top");
+	}
+
+	#[test]
+	fn test_query_map() {
+		let url = Url::parse("/a@b/c?x=1:2&y=a+b&x=%E6%88%91").unwrap();
+		assert_eq!(url.host(), "");
+		assert_eq!(url.path(), "/a@b/c");
+		assert_eq!(url.query().get("x").unwrap(), &vec!["1:2".to_string(), "我".to_string()]);
+		assert_eq!(url.query().getone("y").unwrap(), "a b");
+
+		// the pairs with a malformed escape are dropped.
+		let url = Url::parse("/?a=%z1&b=%zz&c=%4&%zzd=1&e=%41").unwrap();
+		assert!(["a", "b", "c", "%d", "d"].iter().all(|k| url.query().get(k).is_none()));
+		assert_eq!(url.query().getone("e").unwrap(), "A");
+
+		let url = Url::parse("http://u@[::1]:8080/").unwrap();
+		assert_eq!((url.username(), url.host(), url.port()), ("u", "[::1]", 8080));
+
+		let mut url = Url::parse("/?a=1").unwrap();
+		url.builder().query().append("b", "2");
+		assert!(url.query().get("a").is_none());
+		assert_eq!(url.query().getone("b").unwrap(), "2");
+	}
+
+	#[test]
+	fn test_missing_path() {
+		let url = Url::parse("*").unwrap();
+		assert_eq!((url.host(), url.path()), ("", "*"));
+
+		let url = Url::parse("http://example.com").unwrap();
+		assert_eq!((url.host(), url.path()), ("example.com", "/"));
+
+		let url = Url::parse("http://example.com:8080?x=1#top").unwrap();
+		assert_eq!((url.host(), url.port(), url.path()), ("example.com", 8080, "/"));
+		assert_eq!((url.rawquery, url.fragment), ("x=1", "top"));
+
+		let url = Url::parse("http://example.com#top").unwrap();
+		assert_eq!((url.path(), url.fragment), ("/", "top"));
+
+		assert!(Url::parse("http://[::1/a").is_err());
+
+		let mut req = crate::h2tp::Request::new();
+		req.msg.startline.1.push_str("http://[::1?x=1");
+		assert_eq!(req.url().err().unwrap().statuscode().code(), 400);
+		assert_eq!(req.query::<std::collections::HashMap<String, String>>().err().unwrap().statuscode().code(), 400);
+
+		let mut req = crate::h2tp::Request::new();
+		req.msg.startline.1.push_str("/a?x=1");
+		req.msg.headers_builder().append("host", "example.com:8080");
+		let url = req.url().unwrap();
+		assert_eq!((url.host(), url.port(), url.path()), ("example.com", 8080, "/a"));
+	}
 }
@@ -0,0 +1,963 @@
+use crate::h2tp::utils::multi_map::MultiMap;
+use crate::h2tp::utils::uricoding;
+use serde::de::{self, DeserializeOwned, DeserializeSeed, IntoDeserializer, MapAccess, SeqAccess, Visitor};
+use serde::ser::{self, Impossible, Serialize};
+use serde::forward_to_deserialize_any;
+use std::collections::HashMap;
+use std::fmt;
+
+/// `Charset` is the charset of the form values, the `%XX` escaped bytes are decoded by it.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Charset {
+	Utf8,
+	/// `iso-8859-1`, every byte is a char.
+	Latin1,
+}
+
+impl Charset {
+	/// `from_label` return the charset of the `charset` parameter, `None` if it is not supported.
+	pub fn from_label(label: &str) -> Option<Self> {
+		return match label.trim().to_ascii_lowercase().as_str() {
+			"utf-8" | "utf8" | "us-ascii" | "ascii" => Some(Charset::Utf8),
+			"iso-8859-1" | "iso8859-1" | "latin1" | "l1" => Some(Charset::Latin1),
+			_ => None,
+		};
+	}
+
+	pub(crate) fn decode(&self, bytes: &[u8]) -> Option<String> {
+		return match self {
+			Charset::Utf8 => match std::str::from_utf8(bytes) {
+				Ok(v) => Some(v.to_string()),
+				Err(_) => None,
+			},
+			Charset::Latin1 => Some(bytes.iter().map(|b| *b as char).collect()),
+		};
+	}
+}
+
+/// `parse` split the `application/x-www-form-urlencoded` string(the query string or the form body) to
+/// the decoded pairs, the pairs that have a malformed `%` escape or are not valid utf-8 are skipped.
+pub fn parse(src: &str) -> Vec<(String, String)> {
+	return parse_bytes(src.as_bytes(), Charset::Utf8);
+}
+
+/// `parse_bytes` is the `parse` of the raw form body, the values are decoded by `charset`.
+pub fn parse_bytes(src: &[u8], charset: Charset) -> Vec<(String, String)> {
+	let mut pairs = vec![];
+	let mut buf: Vec<u8> = vec![];
+	for item in src.split(|b| *b == b'&') {
+		if item.is_empty() {
+			continue;
+		}
+		let (k, v) = match item.iter().position(|b| *b == b'=') {
+			Some(idx) => (&item[..idx], &item[idx + 1..]),
+			None => (item, &item[item.len()..]),
+		};
+		let key = match decode(&mut buf, k, charset) {
+			Some(key) => key,
+			None => continue,
+		};
+		match decode(&mut buf, v, charset) {
+			Some(val) => {
+				pairs.push((key, val));
+			}
+			None => {}
+		}
+	}
+	return pairs;
+}
+
+fn decode(buf: &mut Vec<u8>, v: &[u8], charset: Charset) -> Option<String> {
+	buf.clear();
+	if !uricoding::decode_formed_bytes(buf, v) {
+		return None;
+	}
+	return charset.decode(buf);
+}
+
+/// `to_multimap` parse the form string to a `MultiMap`.
+pub fn to_multimap(src: &str) -> MultiMap {
+	let mut map = MultiMap::new();
+	for (k, v) in parse(src) {
+		map.append(&k, &v);
+	}
+	return map;
+}
+
+/// `from_str` deserialize the form string to `T`.
+pub fn from_str<T: DeserializeOwned>(src: &str) -> Result<T, FormedError> {
+	return from_pairs(&parse(src));
+}
+
+/// `from_pairs` deserialize the decoded pairs to `T`, the fields are taken by the keys.
+/// a repeated key is deserialized to a sequence(`Vec<T>` etc.), or the last value is taken for a scalar;
+/// an empty value is `None` for an `Option`.
+pub fn from_pairs<T: DeserializeOwned>(pairs: &[(String, String)]) -> Result<T, FormedError> {
+	let mut idxes: HashMap<&str, usize> = HashMap::new();
+	let mut groups: Vec<(&str, Vec<&str>)> = vec![];
+	for (k, v) in pairs {
+		match idxes.get(k.as_str()) {
+			Some(idx) => {
+				groups[*idx].1.push(v.as_str());
+			}
+			None => {
+				idxes.insert(k.as_str(), groups.len());
+				groups.push((k.as_str(), vec![v.as_str()]));
+			}
+		}
+	}
+	return T::deserialize(PairsDeserializer { groups: groups.into_iter(), current: None });
+}
+
+/// `Encoder` build an `application/x-www-form-urlencoded` string, like `Encoder::new().append("q", "a b").finish()`.
+pub struct Encoder {
+	buf: Vec<u8>,
+}
+
+impl Encoder {
+	pub fn new() -> Self {
+		return Self { buf: vec![] };
+	}
+
+	pub fn append(&mut self, k: &str, v: &str) -> &mut Self {
+		if !self.buf.is_empty() {
+			self.buf.push(b'&');
+		}
+		uricoding::encode_formed(&mut self.buf, k);
+		self.buf.push(b'=');
+		uricoding::encode_formed(&mut self.buf, v);
+		return self;
+	}
+
+	/// `extend` append all the values of `map`.
+	pub fn extend(&mut self, map: &MultiMap) -> &mut Self {
+		map.each(|k, v, _| {
+			self.append(k, v);
+			return true;
+		});
+		return self;
+	}
+
+	pub fn as_str(&self) -> &str {
+		// the escaped bytes are all ascii
+		return unsafe { std::str::from_utf8_unchecked(&self.buf) };
+	}
+
+	pub fn finish(&mut self) -> String {
+		let buf = std::mem::take(&mut self.buf);
+		return unsafe { String::from_utf8_unchecked(buf) };
+	}
+}
+
+impl Default for Encoder {
+	fn default() -> Self {
+		return Self::new();
+	}
+}
+
+/// `to_string` serialize a struct or a map to the form string, a sequence is the repeated key and
+/// a `None` is skipped.
+pub fn to_string<T: Serialize + ?Sized>(v: &T) -> Result<String, FormedError> {
+	let mut encoder = Encoder::new();
+	v.serialize(PairsSerializer { encoder: &mut encoder })?;
+	return Ok(encoder.finish());
+}
+
+pub struct FormedError {
+	msg: String,
+	data: bool,
+}
+
+impl FormedError {
+	fn invalid(key: &str, value: &str) -> Self {
+		return Self::data(format_args!("invalid value `{}` for `{}`", value, key));
+	}
+
+	fn data<T: fmt::Display>(msg: T) -> Self {
+		return Self { msg: msg.to_string(), data: true };
+	}
+
+	/// `is_data` return `true` if the pairs do not match the type, like an invalid value or a missing field.
+	pub fn is_data(&self) -> bool {
+		return self.data;
+	}
+}
+
+impl fmt::Debug for FormedError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "FormedError: {}", self.msg)
+	}
+}
+
+impl fmt::Display for FormedError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(&self.msg)
+	}
+}
+
+impl std::error::Error for FormedError {}
+
+impl de::Error for FormedError {
+	fn custom<T: fmt::Display>(msg: T) -> Self {
+		return Self { msg: msg.to_string(), data: false };
+	}
+
+	fn invalid_type(unexp: de::Unexpected, exp: &dyn de::Expected) -> Self {
+		return Self::data(<de::value::Error as de::Error>::invalid_type(unexp, exp));
+	}
+
+	fn invalid_value(unexp: de::Unexpected, exp: &dyn de::Expected) -> Self {
+		return Self::data(<de::value::Error as de::Error>::invalid_value(unexp, exp));
+	}
+
+	fn invalid_length(len: usize, exp: &dyn de::Expected) -> Self {
+		return Self::data(<de::value::Error as de::Error>::invalid_length(len, exp));
+	}
+
+	fn unknown_variant(variant: &str, expected: &'static [&'static str]) -> Self {
+		return Self::data(<de::value::Error as de::Error>::unknown_variant(variant, expected));
+	}
+
+	fn unknown_field(field: &str, expected: &'static [&'static str]) -> Self {
+		return Self::data(<de::value::Error as de::Error>::unknown_field(field, expected));
+	}
+
+	fn missing_field(field: &'static str) -> Self {
+		return Self::data(<de::value::Error as de::Error>::missing_field(field));
+	}
+
+	fn duplicate_field(field: &'static str) -> Self {
+		return Self::data(<de::value::Error as de::Error>::duplicate_field(field));
+	}
+}
+
+impl ser::Error for FormedError {
+	fn custom<T: fmt::Display>(msg: T) -> Self {
+		return Self { msg: msg.to_string(), data: false };
+	}
+}
+
+struct PairsDeserializer<'de> {
+	groups: std::vec::IntoIter<(&'de str, Vec<&'de str>)>,
+	current: Option<(&'de str, Vec<&'de str>)>,
+}
+
+impl<'de> de::Deserializer<'de> for PairsDeserializer<'de> {
+	type Error = FormedError;
+
+	fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+		return visitor.visit_map(self);
+	}
+
+	fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+		return visitor.visit_unit();
+	}
+
+	forward_to_deserialize_any! {
+		bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf option
+		unit_struct newtype_struct seq tuple tuple_struct map struct enum identifier ignored_any
+	}
+}
+
+impl<'de> MapAccess<'de> for PairsDeserializer<'de> {
+	type Error = FormedError;
+
+	fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>, Self::Error> {
+		return match self.groups.next() {
+			Some(group) => {
+				let key = group.0;
+				self.current = Some(group);
+				seed.deserialize(key.into_deserializer()).map(Some)
+			}
+			None => Ok(None),
+		};
+	}
+
+	fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Self::Error> {
+		return match self.current.take() {
+			Some((key, values)) => seed.deserialize(ValuesDeserializer { key, values }),
+			None => Err(de::Error::custom("value is missing")),
+		};
+	}
+}
+
+/// `ValuesDeserializer` is all the values of a key.
+struct ValuesDeserializer<'de> {
+	key: &'de str,
+	values: Vec<&'de str>,
+}
+
+impl<'de> ValuesDeserializer<'de> {
+	fn last(&self) -> ValueDeserializer<'de> {
+		return ValueDeserializer { key: self.key, value: self.values.last().copied().unwrap_or("") };
+	}
+}
+
+macro_rules! forward_to_last {
+	($($method:ident)*) => {
+		$(
+			fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+				return self.last().$method(visitor);
+			}
+		)*
+	};
+}
+
+impl<'de> de::Deserializer<'de> for ValuesDeserializer<'de> {
+	type Error = FormedError;
+
+	fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+		if self.values.len() == 1 {
+			return self.last().deserialize_any(visitor);
+		}
+		return self.deserialize_seq(visitor);
+	}
+
+	fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+		if self.values.iter().all(|v| v.is_empty()) {
+			return visitor.visit_none();
+		}
+		return visitor.visit_some(self);
+	}
+
+	fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+		return visitor.visit_seq(ValuesSeq { key: self.key, values: self.values.into_iter() });
+	}
+
+	fn deserialize_tuple<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, Self::Error> {
+		return self.deserialize_seq(visitor);
+	}
+
+	fn deserialize_tuple_struct<V: Visitor<'de>>(
+		self,
+		_name: &'static str,
+		_len: usize,
+		visitor: V,
+	) -> Result<V::Value, Self::Error> {
+		return self.deserialize_seq(visitor);
+	}
+
+	fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, Self::Error> {
+		return visitor.visit_newtype_struct(self);
+	}
+
+	fn deserialize_unit_struct<V: Visitor<'de>>(self, name: &'static str, visitor: V) -> Result<V::Value, Self::Error> {
+		return self.last().deserialize_unit_struct(name, visitor);
+	}
+
+	fn deserialize_enum<V: Visitor<'de>>(
+		self,
+		name: &'static str,
+		variants: &'static [&'static str],
+		visitor: V,
+	) -> Result<V::Value, Self::Error> {
+		return self.last().deserialize_enum(name, variants, visitor);
+	}
+
+	fn deserialize_struct<V: Visitor<'de>>(
+		self,
+		name: &'static str,
+		fields: &'static [&'static str],
+		visitor: V,
+	) -> Result<V::Value, Self::Error> {
+		return self.last().deserialize_struct(name, fields, visitor);
+	}
+
+	forward_to_last! {
+		deserialize_bool deserialize_i8 deserialize_i16 deserialize_i32 deserialize_i64 deserialize_i128
+		deserialize_u8 deserialize_u16 deserialize_u32 deserialize_u64 deserialize_u128 deserialize_f32 deserialize_f64
+		deserialize_char deserialize_str deserialize_string deserialize_bytes deserialize_byte_buf deserialize_unit
+		deserialize_map deserialize_identifier deserialize_ignored_any
+	}
+}
+
+struct ValuesSeq<'de> {
+	key: &'de str,
+	values: std::vec::IntoIter<&'de str>,
+}
+
+impl<'de> SeqAccess<'de> for ValuesSeq<'de> {
+	type Error = FormedError;
+
+	fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>, Self::Error> {
+		return match self.values.next() {
+			Some(value) => seed.deserialize(ValueDeserializer { key: self.key, value }).map(Some),
+			None => Ok(None),
+		};
+	}
+
+	fn size_hint(&self) -> Option<usize> {
+		return Some(self.values.len());
+	}
+}
+
+/// `ValueDeserializer` is a single value, the scalars are parsed from the string.
+struct ValueDeserializer<'de> {
+	key: &'de str,
+	value: &'de str,
+}
+
+macro_rules! parse_scalar {
+	($($method:ident => $visit:ident),*) => {
+		$(
+			fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+				return match self.value.trim().parse() {
+					Ok(v) => visitor.$visit(v),
+					Err(_) => Err(FormedError::invalid(self.key, self.value)),
+				};
+			}
+		)*
+	};
+}
+
+impl<'de> de::Deserializer<'de> for ValueDeserializer<'de> {
+	type Error = FormedError;
+
+	fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+		return visitor.visit_borrowed_str(self.value);
+	}
+
+	/// the checkbox values `on` and `off` are accepted too.
+	fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+		return match self.value {
+			"true" | "on" | "1" => visitor.visit_bool(true),
+			"false" | "off" | "0" => visitor.visit_bool(false),
+			_ => Err(FormedError::invalid(self.key, self.value)),
+		};
+	}
+
+	parse_scalar! {
+		deserialize_i8 => visit_i8, deserialize_i16 => visit_i16, deserialize_i32 => visit_i32,
+		deserialize_i64 => visit_i64, deserialize_i128 => visit_i128,
+		deserialize_u8 => visit_u8, deserialize_u16 => visit_u16, deserialize_u32 => visit_u32,
+		deserialize_u64 => visit_u64, deserialize_u128 => visit_u128,
+		deserialize_f32 => visit_f32, deserialize_f64 => visit_f64
+	}
+
+	fn deserialize_char<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+		let mut chars = self.value.chars();
+		return match (chars.next(), chars.next()) {
+			(Some(c), None) => visitor.visit_char(c),
+			_ => Err(FormedError::invalid(self.key, self.value)),
+		};
+	}
+
+	fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+		if self.value.is_empty() {
+			return visitor.visit_none();
+		}
+		return visitor.visit_some(self);
+	}
+
+	fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+		return visitor.visit_unit();
+	}
+
+	fn deserialize_unit_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, Self::Error> {
+		return visitor.visit_unit();
+	}
+
+	fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, Self::Error> {
+		return visitor.visit_newtype_struct(self);
+	}
+
+	fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+		return visitor.visit_seq(ValuesSeq { key: self.key, values: vec![self.value].into_iter() });
+	}
+
+	fn deserialize_enum<V: Visitor<'de>>(
+		self,
+		_name: &'static str,
+		_variants: &'static [&'static str],
+		visitor: V,
+	) -> Result<V::Value, Self::Error> {
+		return visitor.visit_enum(self.value.into_deserializer());
+	}
+
+	fn deserialize_map<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Self::Error> {
+		return Err(FormedError::data(format_args!("`{}` can not be a map", self.key)));
+	}
+
+	fn deserialize_struct<V: Visitor<'de>>(
+		self,
+		_name: &'static str,
+		_fields: &'static [&'static str],
+		visitor: V,
+	) -> Result<V::Value, Self::Error> {
+		return self.deserialize_map(visitor);
+	}
+
+	fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+		return visitor.visit_unit();
+	}
+
+	forward_to_deserialize_any! {
+		str string bytes byte_buf tuple tuple_struct identifier
+	}
+}
+
+macro_rules! serialize_err {
+	($err:expr; $($method:ident($ty:ty)),*) => {
+		$(
+			fn $method(self, _v: $ty) -> Result<Self::Ok, Self::Error> {
+				return Err(ser::Error::custom($err));
+			}
+		)*
+	};
+}
+
+macro_rules! serialize_compound_err {
+	($err:expr) => {
+		fn serialize_newtype_variant<T: Serialize + ?Sized>(
+			self,
+			_name: &'static str,
+			_idx: u32,
+			_variant: &'static str,
+			_v: &T,
+		) -> Result<Self::Ok, Self::Error> {
+			return Err(ser::Error::custom($err));
+		}
+
+		fn serialize_tuple_variant(
+			self,
+			_name: &'static str,
+			_idx: u32,
+			_variant: &'static str,
+			_len: usize,
+		) -> Result<Self::SerializeTupleVariant, Self::Error> {
+			return Err(ser::Error::custom($err));
+		}
+
+		fn serialize_struct_variant(
+			self,
+			_name: &'static str,
+			_idx: u32,
+			_variant: &'static str,
+			_len: usize,
+		) -> Result<Self::SerializeStructVariant, Self::Error> {
+			return Err(ser::Error::custom($err));
+		}
+	};
+}
+
+const TOP_LEVEL_ERR: &str = "the top level must be a struct or a map";
+
+/// `PairsSerializer` is the top level, the fields of a struct or the entries of a map.
+struct PairsSerializer<'e> {
+	encoder: &'e mut Encoder,
+}
+
+impl<'e> ser::Serializer for PairsSerializer<'e> {
+	type Ok = ();
+	type Error = FormedError;
+	type SerializeSeq = Impossible<(), FormedError>;
+	type SerializeTuple = Impossible<(), FormedError>;
+	type SerializeTupleStruct = Impossible<(), FormedError>;
+	type SerializeTupleVariant = Impossible<(), FormedError>;
+	type SerializeMap = PairsCompound<'e>;
+	type SerializeStruct = PairsCompound<'e>;
+	type SerializeStructVariant = Impossible<(), FormedError>;
+
+	serialize_err! {
+		TOP_LEVEL_ERR;
+		serialize_bool(bool), serialize_i8(i8), serialize_i16(i16), serialize_i32(i32), serialize_i64(i64),
+		serialize_u8(u8), serialize_u16(u16), serialize_u32(u32), serialize_u64(u64), serialize_f32(f32), serialize_f64(f64),
+		serialize_char(char), serialize_str(&str), serialize_bytes(&[u8]), serialize_unit_struct(&'static str)
+	}
+	serialize_compound_err!(TOP_LEVEL_ERR);
+
+	fn serialize_none(self) -> Result<Self::Ok, Self::Error> {
+		return Ok(());
+	}
+
+	fn serialize_some<T: Serialize + ?Sized>(self, v: &T) -> Result<Self::Ok, Self::Error> {
+		return v.serialize(self);
+	}
+
+	fn serialize_unit(self) -> Result<Self::Ok, Self::Error> {
+		return Ok(());
+	}
+
+	fn serialize_unit_variant(self, _name: &'static str, _idx: u32, _variant: &'static str) -> Result<Self::Ok, Self::Error> {
+		return Err(ser::Error::custom(TOP_LEVEL_ERR));
+	}
+
+	fn serialize_newtype_struct<T: Serialize + ?Sized>(self, _name: &'static str, v: &T) -> Result<Self::Ok, Self::Error> {
+		return v.serialize(self);
+	}
+
+	fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
+		return Err(ser::Error::custom(TOP_LEVEL_ERR));
+	}
+
+	fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, Self::Error> {
+		return Err(ser::Error::custom(TOP_LEVEL_ERR));
+	}
+
+	fn serialize_tuple_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeTupleStruct, Self::Error> {
+		return Err(ser::Error::custom(TOP_LEVEL_ERR));
+	}
+
+	fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
+		return Ok(PairsCompound { encoder: self.encoder, key: None });
+	}
+
+	fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeStruct, Self::Error> {
+		return Ok(PairsCompound { encoder: self.encoder, key: None });
+	}
+}
+
+struct PairsCompound<'e> {
+	encoder: &'e mut Encoder,
+	key: Option<String>,
+}
+
+impl<'e> ser::SerializeStruct for PairsCompound<'e> {
+	type Ok = ();
+	type Error = FormedError;
+
+	fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, v: &T) -> Result<(), Self::Error> {
+		return v.serialize(ValueSerializer { encoder: self.encoder, key });
+	}
+
+	fn end(self) -> Result<(), Self::Error> {
+		return Ok(());
+	}
+}
+
+impl<'e> ser::SerializeMap for PairsCompound<'e> {
+	type Ok = ();
+	type Error = FormedError;
+
+	fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Self::Error> {
+		self.key = Some(key.serialize(KeySerializer)?);
+		return Ok(());
+	}
+
+	fn serialize_value<T: Serialize + ?Sized>(&mut self, v: &T) -> Result<(), Self::Error> {
+		return match self.key.take() {
+			Some(key) => v.serialize(ValueSerializer { encoder: self.encoder, key: &key }),
+			None => Err(ser::Error::custom("key is missing")),
+		};
+	}
+
+	fn end(self) -> Result<(), Self::Error> {
+		return Ok(());
+	}
+}
+
+const VALUE_ERR: &str = "the value must be a scalar or a sequence of scalars";
+
+macro_rules! serialize_display {
+	($($method:ident($ty:ty)),*) => {
+		$(
+			fn $method(self, v: $ty) -> Result<Self::Ok, Self::Error> {
+				return self.ok(v.to_string());
+			}
+		)*
+	};
+}
+
+/// `ValueSerializer` is the value of a key, a sequence is appended as the repeated key.
+struct ValueSerializer<'e, 'k> {
+	encoder: &'e mut Encoder,
+	key: &'k str,
+}
+
+impl<'e, 'k> ValueSerializer<'e, 'k> {
+	fn ok(self, v: String) -> Result<(), FormedError> {
+		self.encoder.append(self.key, &v);
+		return Ok(());
+	}
+}
+
+impl<'e, 'k> ser::Serializer for ValueSerializer<'e, 'k> {
+	type Ok = ();
+	type Error = FormedError;
+	type SerializeSeq = ValueSeq<'e, 'k>;
+	type SerializeTuple = ValueSeq<'e, 'k>;
+	type SerializeTupleStruct = ValueSeq<'e, 'k>;
+	type SerializeTupleVariant = Impossible<(), FormedError>;
+	type SerializeMap = Impossible<(), FormedError>;
+	type SerializeStruct = Impossible<(), FormedError>;
+	type SerializeStructVariant = Impossible<(), FormedError>;
+
+	serialize_display! {
+		serialize_bool(bool), serialize_i8(i8), serialize_i16(i16), serialize_i32(i32), serialize_i64(i64),
+		serialize_u8(u8), serialize_u16(u16), serialize_u32(u32), serialize_u64(u64), serialize_f32(f32), serialize_f64(f64),
+		serialize_char(char)
+	}
+	serialize_compound_err!(VALUE_ERR);
+
+	fn serialize_str(self, v: &str) -> Result<Self::Ok, Self::Error> {
+		self.encoder.append(self.key, v);
+		return Ok(());
+	}
+
+	fn serialize_bytes(self, v: &[u8]) -> Result<Self::Ok, Self::Error> {
+		return match std::str::from_utf8(v) {
+			Ok(v) => self.serialize_str(v),
+			Err(_) => Err(ser::Error::custom(format_args!("`{}` is not valid utf-8", self.key))),
+		};
+	}
+
+	fn serialize_none(self) -> Result<Self::Ok, Self::Error> {
+		return Ok(());
+	}
+
+	fn serialize_some<T: Serialize + ?Sized>(self, v: &T) -> Result<Self::Ok, Self::Error> {
+		return v.serialize(self);
+	}
+
+	fn serialize_unit(self) -> Result<Self::Ok, Self::Error> {
+		return self.serialize_str("");
+	}
+
+	fn serialize_unit_struct(self, _name: &'static str) -> Result<Self::Ok, Self::Error> {
+		return self.serialize_str("");
+	}
+
+	fn serialize_unit_variant(self, _name: &'static str, _idx: u32, variant: &'static str) -> Result<Self::Ok, Self::Error> {
+		return self.serialize_str(variant);
+	}
+
+	fn serialize_newtype_struct<T: Serialize + ?Sized>(self, _name: &'static str, v: &T) -> Result<Self::Ok, Self::Error> {
+		return v.serialize(self);
+	}
+
+	fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
+		return Ok(ValueSeq { encoder: self.encoder, key: self.key });
+	}
+
+	fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, Self::Error> {
+		return Ok(ValueSeq { encoder: self.encoder, key: self.key });
+	}
+
+	fn serialize_tuple_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeTupleStruct, Self::Error> {
+		return Ok(ValueSeq { encoder: self.encoder, key: self.key });
+	}
+
+	fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
+		return Err(ser::Error::custom(VALUE_ERR));
+	}
+
+	fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeStruct, Self::Error> {
+		return Err(ser::Error::custom(VALUE_ERR));
+	}
+}
+
+struct ValueSeq<'e, 'k> {
+	encoder: &'e mut Encoder,
+	key: &'k str,
+}
+
+impl<'e, 'k> ValueSeq<'e, 'k> {
+	fn element<T: Serialize + ?Sized>(&mut self, v: &T) -> Result<(), FormedError> {
+		return v.serialize(ValueSerializer { encoder: self.encoder, key: self.key });
+	}
+}
+
+impl<'e, 'k> ser::SerializeSeq for ValueSeq<'e, 'k> {
+	type Ok = ();
+	type Error = FormedError;
+
+	fn serialize_element<T: Serialize + ?Sized>(&mut self, v: &T) -> Result<(), Self::Error> {
+		return self.element(v);
+	}
+
+	fn end(self) -> Result<(), Self::Error> {
+		return Ok(());
+	}
+}
+
+impl<'e, 'k> ser::SerializeTuple for ValueSeq<'e, 'k> {
+	type Ok = ();
+	type Error = FormedError;
+
+	fn serialize_element<T: Serialize + ?Sized>(&mut self, v: &T) -> Result<(), Self::Error> {
+		return self.element(v);
+	}
+
+	fn end(self) -> Result<(), Self::Error> {
+		return Ok(());
+	}
+}
+
+impl<'e, 'k> ser::SerializeTupleStruct for ValueSeq<'e, 'k> {
+	type Ok = ();
+	type Error = FormedError;
+
+	fn serialize_field<T: Serialize + ?Sized>(&mut self, v: &T) -> Result<(), Self::Error> {
+		return self.element(v);
+	}
+
+	fn end(self) -> Result<(), Self::Error> {
+		return Ok(());
+	}
+}
+
+const KEY_ERR: &str = "the key must be a string or a scalar";
+
+/// `KeySerializer` is the key of a map.
+struct KeySerializer;
+
+impl KeySerializer {
+	fn ok(self, v: String) -> Result<String, FormedError> {
+		return Ok(v);
+	}
+}
+
+impl ser::Serializer for KeySerializer {
+	type Ok = String;
+	type Error = FormedError;
+	type SerializeSeq = Impossible<String, FormedError>;
+	type SerializeTuple = Impossible<String, FormedError>;
+	type SerializeTupleStruct = Impossible<String, FormedError>;
+	type SerializeTupleVariant = Impossible<String, FormedError>;
+	type SerializeMap = Impossible<String, FormedError>;
+	type SerializeStruct = Impossible<String, FormedError>;
+	type SerializeStructVariant = Impossible<String, FormedError>;
+
+	serialize_display! {
+		serialize_bool(bool), serialize_i8(i8), serialize_i16(i16), serialize_i32(i32), serialize_i64(i64),
+		serialize_u8(u8), serialize_u16(u16), serialize_u32(u32), serialize_u64(u64), serialize_f32(f32), serialize_f64(f64),
+		serialize_char(char), serialize_str(&str)
+	}
+	serialize_err! {
+		KEY_ERR;
+		serialize_bytes(&[u8]), serialize_unit_struct(&'static str)
+	}
+	serialize_compound_err!(KEY_ERR);
+
+	fn serialize_none(self) -> Result<Self::Ok, Self::Error> {
+		return Err(ser::Error::custom(KEY_ERR));
+	}
+
+	fn serialize_some<T: Serialize + ?Sized>(self, v: &T) -> Result<Self::Ok, Self::Error> {
+		return v.serialize(self);
+	}
+
+	fn serialize_unit(self) -> Result<Self::Ok, Self::Error> {
+		return Err(ser::Error::custom(KEY_ERR));
+	}
+
+	fn serialize_unit_variant(self, _name: &'static str, _idx: u32, variant: &'static str) -> Result<Self::Ok, Self::Error> {
+		return Ok(variant.to_string());
+	}
+
+	fn serialize_newtype_struct<T: Serialize + ?Sized>(self, _name: &'static str, v: &T) -> Result<Self::Ok, Self::Error> {
+		return v.serialize(self);
+	}
+
+	fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
+		return Err(ser::Error::custom(KEY_ERR));
+	}
+
+	fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, Self::Error> {
+		return Err(ser::Error::custom(KEY_ERR));
+	}
+
+	fn serialize_tuple_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeTupleStruct, Self::Error> {
+		return Err(ser::Error::custom(KEY_ERR));
+	}
+
+	fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
+		return Err(ser::Error::custom(KEY_ERR));
+	}
+
+	fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeStruct, Self::Error> {
+		return Err(ser::Error::custom(KEY_ERR));
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::h2tp::utils::formed;
+	use serde::{Deserialize, Serialize};
+
+	#[derive(Deserialize, Serialize, Debug, PartialEq)]
+	#[serde(rename_all = "lowercase")]
+	enum Order {
+		Asc,
+		Desc,
+	}
+
+	#[derive(Deserialize, Debug, PartialEq)]
+	struct Search {
+		q: String,
+		page: Option<u32>,
+		size: Option<u32>,
+		tag: Vec<String>,
+		#[serde(default)]
+		exact: bool,
+		order: Order,
+	}
+
+	#[test]
+	fn test_from_str() {
+		let v: Search = formed::from_str("q=a+b%26c&page=2&size=&tag=x&tag=y&order=desc&other=1").unwrap();
+		assert_eq!(
+			v,
+			Search {
+				q: "a b&c".to_string(),
+				page: Some(2),
+				size: None,
+				tag: vec!["x".to_string(), "y".to_string()],
+				exact: false,
+				order: Order::Desc,
+			}
+		);
+
+		let v: Search = formed::from_str("q=&tag=z&exact=on&order=asc&q=last").unwrap();
+		assert_eq!(v.q, "last");
+		assert_eq!(v.tag, vec!["z".to_string()]);
+		assert!(v.exact);
+		assert_eq!(v.page, None);
+
+		let e = formed::from_str::<Search>("q=a&page=x&tag=&order=asc").unwrap_err();
+		assert_eq!(e.to_string(), "invalid value `x` for `page`");
+		assert!(e.is_data());
+		let e = formed::from_str::<Search>("page=1&tag=&order=asc").unwrap_err();
+		assert_eq!(e.to_string(), "missing field `q`");
+		assert!(e.is_data());
+		let e = formed::from_str::<Search>("q=a&tag=&order=up").unwrap_err();
+		assert_eq!(e.to_string(), "unknown variant `up`, expected `asc` or `desc`");
+		assert!(e.is_data());
+
+		let v: Vec<(String, u8)> = formed::parse("a=1&%FF=2&b&&c=%E6%88%91")
+			.into_iter()
+			.map(|(k, v)| (k, v.len() as u8))
+			.collect();
+		assert_eq!(v, vec![("a".to_string(), 1), ("b".to_string(), 0), ("c".to_string(), 3)]);
+
+		let v = formed::parse_bytes(b"a=%z1&b=%zz&c=%E9&d=%4", formed::Charset::Latin1);
+		assert_eq!(v, vec![("c".to_string(), "é".to_string())]);
+	}
+
+	#[derive(Serialize)]
+	struct Filter {
+		q: &'static str,
+		page: Option<u32>,
+		size: Option<u32>,
+		tag: Vec<&'static str>,
+		order: Order,
+	}
+
+	#[test]
+	fn test_to_string() {
+		let filter = Filter { q: "a b&c", page: Some(2), size: None, tag: vec!["x", "我"], order: Order::Asc };
+		let v = formed::to_string(&filter).unwrap();
+		assert_eq!(v, "q=a+b%26c&page=2&tag=x&tag=%E6%88%91&order=asc");
+		let back: Search = formed::from_str(&v).unwrap();
+		assert_eq!(back.tag, vec!["x".to_string(), "我".to_string()]);
+		assert!(formed::to_string(&vec![1, 2]).is_err());
+
+		let mut encoder = formed::Encoder::new();
+		encoder.append("k", "v=1").append("", "");
+		assert_eq!(encoder.finish(), "k=v%3D1&=");
+
+		let pairs = formed::parse_bytes(b"a=%E9&\xe9=x", formed::Charset::Latin1);
+		assert_eq!(pairs[0], ("a".to_string(), "é".to_string()));
+		assert_eq!(pairs[1], ("é".to_string(), "x".to_string()));
+	}
+}
@@ -0,0 +1,94 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const WEEKDAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+/// `civil` convert the days since the unix epoch to `(year, month, day)`.
+/// http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+fn civil(days: i64) -> (i64, u32, u32) {
+	let z = days + 719468;
+	let era = if z >= 0 { z } else { z - 146096 } / 146097;
+	let doe = z - era * 146097;
+	let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+	let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+	let mp = (5 * doy + 2) / 153;
+	let d = (doy - (153 * mp + 2) / 5 + 1) as u32;
+	let m = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+	let y = yoe + era * 400 + if m <= 2 { 1 } else { 0 };
+	return (y, m, d);
+}
+
+/// `days` convert `(year, month, day)` to the days since the unix epoch.
+fn days(y: i64, m: u32, d: u32) -> i64 {
+	let y = if m <= 2 { y - 1 } else { y };
+	let era = if y >= 0 { y } else { y - 399 } / 400;
+	let yoe = y - era * 400;
+	let m = m as i64;
+	let doy = (153 * (if m > 2 { m - 3 } else { m + 9 }) + 2) / 5 + d as i64 - 1;
+	let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+	return era * 146097 + doe - 719468;
+}
+
+fn split(t: SystemTime) -> (i64, u32, u32, u64, u64, u64, usize) {
+	let secs = match t.duration_since(UNIX_EPOCH) {
+		Ok(d) => d.as_secs(),
+		Err(_) => 0,
+	};
+	let days = (secs / 86400) as i64;
+	let rem = secs % 86400;
+	let (y, m, d) = civil(days);
+	return (y, m, d, rem / 3600, rem % 3600 / 60, rem % 60, (days % 7) as usize);
+}
+
+/// `format` return the IMF-fixdate of `t`, like `Sun, 06 Nov 1994 08:49:37 GMT`.
+pub fn format(t: SystemTime) -> String {
+	let (y, m, d, hh, mm, ss, wd) = split(t);
+	return format!(
+		"{}, {:02} {} {:04} {:02}:{:02}:{:02} GMT",
+		WEEKDAYS[wd], d, MONTHS[m as usize - 1], y, hh, mm, ss
+	);
+}
+
+/// `format_rfc3339` return the RFC 3339 date-time of `t` in UTC, like `1994-11-06T08:49:37Z`.
+pub fn format_rfc3339(t: SystemTime) -> String {
+	let (y, m, d, hh, mm, ss, _) = split(t);
+	return format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z", y, m, d, hh, mm, ss);
+}
+
+/// `parse` parse an IMF-fixdate, the obsolete formats are not supported.
+pub fn parse(v: &str) -> Option<SystemTime> {
+	let (_, v) = v.trim().split_once(", ")?;
+	let mut parts = v.split(' ');
+	let d: u32 = parts.next()?.parse().ok()?;
+	let mon = parts.next()?;
+	let m = MONTHS.iter().position(|e| *e == mon)? as u32 + 1;
+	let y: i64 = parts.next()?.parse().ok()?;
+	let mut hms = parts.next()?.split(':');
+	let hh: u64 = hms.next()?.parse().ok()?;
+	let mm: u64 = hms.next()?.parse().ok()?;
+	let ss: u64 = hms.next()?.parse().ok()?;
+	if parts.next()? != "GMT" || d < 1 || d > 31 || hh > 23 || mm > 59 || ss > 60 {
+		return None;
+	}
+	let days = days(y, m, d);
+	if days < 0 {
+		return None;
+	}
+	return Some(UNIX_EPOCH + Duration::from_secs(days as u64 * 86400 + hh * 3600 + mm * 60 + ss));
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::{Duration, UNIX_EPOCH};
+	use crate::h2tp::utils::httpdate::{format, format_rfc3339, parse};
+
+	#[test]
+	fn test_httpdate() {
+		let t = UNIX_EPOCH + Duration::from_secs(784111777);
+		assert_eq!(format(t), "Sun, 06 Nov 1994 08:49:37 GMT");
+		assert_eq!(format_rfc3339(t), "1994-11-06T08:49:37Z");
+		assert_eq!(parse("Sun, 06 Nov 1994 08:49:37 GMT"), Some(t));
+		assert_eq!(parse("Thu, 29 Feb 2024 00:00:00 GMT").map(format).unwrap(), "Thu, 29 Feb 2024 00:00:00 GMT");
+		assert_eq!(parse("Sunday, 06-Nov-94 08:49:37 GMT"), None);
+	}
+}
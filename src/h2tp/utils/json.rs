@@ -0,0 +1,20 @@
+use std::fmt::Write;
+
+/// `escape` append `v` as a quoted json string.
+pub fn escape(dest: &mut String, v: &str) {
+	dest.push('"');
+	for c in v.chars() {
+		match c {
+			'"' => dest.push_str("\\\""),
+			'\\' => dest.push_str("\\\\"),
+			'\n' => dest.push_str("\\n"),
+			'\r' => dest.push_str("\\r"),
+			'\t' => dest.push_str("\\t"),
+			c if c.is_control() => {
+				let _ = write!(dest, "\\u{:04x}", c as u32);
+			}
+			_ => dest.push(c),
+		}
+	}
+	dest.push('"');
+}
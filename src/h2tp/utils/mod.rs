@@ -1,3 +1,8 @@
 pub mod multi_map;
 pub mod uricoding;
+pub mod httpdate;
+pub mod xml;
+pub mod json;
+pub mod formed;
+pub mod sha256;
 mod uricoding_excepts;
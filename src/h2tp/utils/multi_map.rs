@@ -9,6 +9,7 @@ fn values(v: &str) -> Values {
 	return Values::from(vec![v.to_string()]);
 }
 
+#[derive(Clone)]
 struct AryMap {
 	keys: Vec<String>,
 	vals: Vec<Values>,
@@ -78,6 +79,7 @@ impl AryMap {
 	}
 }
 
+#[derive(Clone)]
 pub struct MultiMap {
 	ary: Option<AryMap>,
 	map: Option<HashMap<String, Values>>,
@@ -110,8 +112,9 @@ impl MultiMap {
 				}
 
 				let aryref = self.ary.as_mut().unwrap();
-				if aryref.keys.len() >= 12 {
-					self.swap_ary_to_hashmap()
+				if aryref.keys.len() >= 12 && aryref.idx(k).is_none() {
+					self.swap_ary_to_hashmap();
+					self.append(k, v);
 				} else {
 					aryref.append(k, v);
 				}
@@ -0,0 +1,143 @@
+use std::fmt::Write;
+
+const K: [u32; 64] = [
+	0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+	0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+	0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+	0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+	0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+	0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+	0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+	0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+const H: [u32; 8] = [
+	0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+];
+
+/// `Sha256` is an incremental SHA-256 digest(FIPS 180-4).
+pub struct Sha256 {
+	state: [u32; 8],
+	block: [u8; 64],
+	blocksize: usize,
+	total: u64,
+}
+
+impl Sha256 {
+	pub fn new() -> Self {
+		return Self {
+			state: H,
+			block: [0; 64],
+			blocksize: 0,
+			total: 0,
+		};
+	}
+
+	pub fn update(&mut self, data: &[u8]) {
+		self.total += data.len() as u64;
+		let mut data = data;
+		if self.blocksize > 0 {
+			let n = std::cmp::min(64 - self.blocksize, data.len());
+			self.block[self.blocksize..self.blocksize + n].copy_from_slice(&data[..n]);
+			self.blocksize += n;
+			data = &data[n..];
+			if self.blocksize < 64 {
+				return;
+			}
+			let block = self.block;
+			self.compress(&block);
+			self.blocksize = 0;
+		}
+		while data.len() >= 64 {
+			self.compress(&data[..64]);
+			data = &data[64..];
+		}
+		self.block[..data.len()].copy_from_slice(data);
+		self.blocksize = data.len();
+	}
+
+	pub fn finish(mut self) -> [u8; 32] {
+		let bits = self.total.wrapping_mul(8);
+		let mut padding = [0u8; 72];
+		padding[0] = 0x80;
+		let padsize = if self.blocksize < 56 { 56 - self.blocksize } else { 120 - self.blocksize };
+		padding[padsize..padsize + 8].copy_from_slice(&bits.to_be_bytes());
+		let total = self.total;
+		self.update(&padding[..padsize + 8]);
+		self.total = total;
+
+		let mut digest = [0u8; 32];
+		for (i, v) in self.state.iter().enumerate() {
+			digest[i * 4..i * 4 + 4].copy_from_slice(&v.to_be_bytes());
+		}
+		return digest;
+	}
+
+	/// `finish_hex` return the lowercase hex digest.
+	pub fn finish_hex(self) -> String {
+		let mut v = String::with_capacity(64);
+		for b in self.finish() {
+			let _ = write!(v, "{:02x}", b);
+		}
+		return v;
+	}
+
+	fn compress(&mut self, block: &[u8]) {
+		let mut w = [0u32; 64];
+		for i in 0..16 {
+			w[i] = u32::from_be_bytes([block[i * 4], block[i * 4 + 1], block[i * 4 + 2], block[i * 4 + 3]]);
+		}
+		for i in 16..64 {
+			let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+			let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+			w[i] = w[i - 16].wrapping_add(s0).wrapping_add(w[i - 7]).wrapping_add(s1);
+		}
+
+		let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = self.state;
+		for i in 0..64 {
+			let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+			let ch = (e & f) ^ (!e & g);
+			let t1 = h.wrapping_add(s1).wrapping_add(ch).wrapping_add(K[i]).wrapping_add(w[i]);
+			let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+			let maj = (a & b) ^ (a & c) ^ (b & c);
+			let t2 = s0.wrapping_add(maj);
+			h = g;
+			g = f;
+			f = e;
+			e = d.wrapping_add(t1);
+			d = c;
+			c = b;
+			b = a;
+			a = t1.wrapping_add(t2);
+		}
+		for (s, v) in self.state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+			*s = s.wrapping_add(v);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::h2tp::utils::sha256::Sha256;
+
+	#[test]
+	fn test_sha256() {
+		assert_eq!(Sha256::new().finish_hex(), "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+
+		let mut h = Sha256::new();
+		h.update(b"abc");
+		assert_eq!(h.finish_hex(), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+
+		let mut h = Sha256::new();
+		for _ in 0..1000 {
+			h.update(&[b'a'; 1000]);
+		}
+		assert_eq!(h.finish_hex(), "cdc76e5c9914fb9281a1c7e284d73e67f1809a48a497200e046d39ccc7112cd0");
+
+		let mut h = Sha256::new();
+		for c in b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq" {
+			h.update(&[*c]);
+		}
+		assert_eq!(h.finish_hex(), "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1");
+	}
+}
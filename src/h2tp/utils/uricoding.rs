@@ -57,10 +57,8 @@ macro_rules! write_percents {
 
 		let x2 = HEX_TO_INT_TABLE[$bytes[$idx + 2] as usize];
 		let x1 = HEX_TO_INT_TABLE[$bytes[$idx + 1] as usize];
-		if x1 == 16 && x2 == 16 {
-			$dest.push(b'%');
-			$idx += 3;
-			continue;
+		if x1 == 16 || x2 == 16 {
+			return false;
 		}
 		$dest.push(x1 << 4 | x2);
 		$idx += 3;
@@ -89,9 +87,39 @@ pub fn decode_uri(dest: &mut Vec<u8>, src: &str) -> bool {
 	}
 }
 
-pub fn decode_formed(dest: &mut Vec<u8>, src: &str) -> bool {
+/// `decode_uri_component` decode the `%XX` escapes, other bytes are kept as they are.
+/// return false if there is an incomplete or invalid escape.
+pub fn decode_uri_component(dest: &mut Vec<u8>, src: &str) -> bool {
 	let bytes = src.as_bytes();
 
+	let mut i = 0;
+	while i < bytes.len() {
+		let c = bytes[i];
+		if c != b'%' {
+			dest.push(c);
+			i += 1;
+			continue;
+		}
+		if i + 2 >= bytes.len() {
+			return false;
+		}
+		let x1 = HEX_TO_INT_TABLE[bytes[i + 1] as usize];
+		let x2 = HEX_TO_INT_TABLE[bytes[i + 2] as usize];
+		if x1 == 16 || x2 == 16 {
+			return false;
+		}
+		dest.push(x1 << 4 | x2);
+		i += 3;
+	}
+	return true;
+}
+
+pub fn decode_formed(dest: &mut Vec<u8>, src: &str) -> bool {
+	return decode_formed_bytes(dest, src.as_bytes());
+}
+
+/// `decode_formed_bytes` is the `decode_formed` of the raw bytes, the form bodies may be not utf-8.
+pub fn decode_formed_bytes(dest: &mut Vec<u8>, bytes: &[u8]) -> bool {
 	let mut i = 0;
 	loop {
 		if i >= bytes.len() {
@@ -117,7 +145,7 @@ pub fn decode_formed(dest: &mut Vec<u8>, src: &str) -> bool {
 
 #[cfg(test)]
 mod tests {
-	use crate::h2tp::utils::uricoding::{decode_uri, encode_uri};
+	use crate::h2tp::utils::uricoding::{decode_uri, decode_uri_component, encode_uri};
 
 	use super::decode_formed;
 
@@ -147,4 +175,21 @@ mod tests {
 		decode_formed(&mut dest, "dd+ddd");
 		println!("{}", vec2str!(dest));
 	}
+
+	#[test]
+	fn test_decode_uri_component() {
+		let mut dest = Vec::with_capacity(100);
+		assert!(decode_uri_component(&mut dest, "a%20b+c%2F%E6%88%91"));
+		assert_eq!(vec2str!(dest), "a b+c/我");
+		assert!(!decode_uri_component(&mut vec![], "a%2"));
+		assert!(!decode_uri_component(&mut vec![], "a%zz"));
+	}
+
+	#[test]
+	fn test_decode_bad_escape() {
+		for src in ["%z1", "%1z", "%zz", "a%4"] {
+			assert!(!decode_uri(&mut vec![], src));
+			assert!(!decode_formed(&mut vec![], src));
+		}
+	}
 }
@@ -0,0 +1,329 @@
+/// a minimal xml parser for the small documents in the request bodies(WebDAV etc.),
+/// DTDs and processing instructions are skipped, the namespaces are resolved.
+pub struct Element {
+	/// the namespace uri, empty if the element has no namespace.
+	pub ns: String,
+	pub name: String,
+	pub attrs: Vec<(String, String)>,
+	pub children: Vec<Node>,
+}
+
+pub enum Node {
+	Element(Element),
+	Text(String),
+}
+
+impl Element {
+	pub fn is(&self, ns: &str, name: &str) -> bool {
+		return self.ns == ns && self.name == name;
+	}
+
+	pub fn elements(&self) -> impl Iterator<Item=&Element> {
+		return self.children.iter().filter_map(|n| match n {
+			Node::Element(e) => Some(e),
+			Node::Text(_) => None,
+		});
+	}
+
+	pub fn child(&self, ns: &str, name: &str) -> Option<&Element> {
+		return self.elements().find(|e| e.is(ns, name));
+	}
+
+	/// `text` return the concatenated text of the direct children.
+	pub fn text(&self) -> String {
+		let mut v = String::new();
+		for node in self.children.iter() {
+			match node {
+				Node::Text(t) => {
+					v.push_str(t);
+				}
+				Node::Element(_) => {}
+			}
+		}
+		return v;
+	}
+}
+
+/// `escape` write `v` to `dest` with the xml special characters escaped.
+pub fn escape(dest: &mut String, v: &str) {
+	for c in v.chars() {
+		match c {
+			'<' => dest.push_str("&lt;"),
+			'>' => dest.push_str("&gt;"),
+			'&' => dest.push_str("&amp;"),
+			'"' => dest.push_str("&quot;"),
+			'\'' => dest.push_str("&apos;"),
+			_ => dest.push(c),
+		}
+	}
+}
+
+const UNEXPECTED_EOF: &str = "unexpected eof";
+const BAD_NAME: &str = "bad name";
+const BAD_ATTR: &str = "bad attribute";
+const BAD_ENTITY: &str = "bad entity";
+const UNKNOWN_PREFIX: &str = "unknown namespace prefix";
+const MISMATCHED_TAG: &str = "mismatched end tag";
+const TRAILING_CONTENT: &str = "trailing content";
+
+struct Parser<'a> {
+	src: &'a str,
+	pos: usize,
+	scopes: Vec<Vec<(String, String)>>,
+}
+
+fn unescape(v: &str) -> Result<String, &'static str> {
+	let mut dest = String::with_capacity(v.len());
+	let mut rv = v;
+	loop {
+		match rv.find('&') {
+			Some(idx) => {
+				dest.push_str(&rv[..idx]);
+				rv = &rv[idx + 1..];
+				let end = rv.find(';').ok_or(BAD_ENTITY)?;
+				let entity = &rv[..end];
+				rv = &rv[end + 1..];
+				match entity {
+					"lt" => dest.push('<'),
+					"gt" => dest.push('>'),
+					"amp" => dest.push('&'),
+					"quot" => dest.push('"'),
+					"apos" => dest.push('\''),
+					_ => {
+						let num = if let Some(hex) = entity.strip_prefix("#x") {
+							u32::from_str_radix(hex, 16)
+						} else if let Some(dec) = entity.strip_prefix('#') {
+							dec.parse::<u32>()
+						} else {
+							return Err(BAD_ENTITY);
+						};
+						let c = num.ok().and_then(char::from_u32).ok_or(BAD_ENTITY)?;
+						dest.push(c);
+					}
+				}
+			}
+			None => {
+				dest.push_str(rv);
+				return Ok(dest);
+			}
+		}
+	}
+}
+
+impl<'a> Parser<'a> {
+	fn rest(&self) -> &'a str {
+		return &self.src[self.pos..];
+	}
+
+	fn skip_ws(&mut self) {
+		let rest = self.rest();
+		self.pos += rest.len() - rest.trim_start().len();
+	}
+
+	fn skip_until(&mut self, end: &str) -> Result<&'a str, &'static str> {
+		let rest = self.rest();
+		let idx = rest.find(end).ok_or(UNEXPECTED_EOF)?;
+		self.pos += idx + end.len();
+		return Ok(&rest[..idx]);
+	}
+
+	/// `skip_misc` skip the whitespaces, comments, processing instructions and doctypes.
+	fn skip_misc(&mut self) -> Result<(), &'static str> {
+		loop {
+			self.skip_ws();
+			let rest = self.rest();
+			if rest.starts_with("<?") {
+				self.skip_until("?>")?;
+			} else if rest.starts_with("<!--") {
+				self.skip_until("-->")?;
+			} else if rest.starts_with("<!") {
+				self.skip_until(">")?;
+			} else {
+				return Ok(());
+			}
+		}
+	}
+
+	fn name(&mut self) -> Result<&'a str, &'static str> {
+		let rest = self.rest();
+		let end = rest
+			.find(|c: char| c.is_whitespace() || c == '/' || c == '>' || c == '=')
+			.ok_or(UNEXPECTED_EOF)?;
+		if end == 0 {
+			return Err(BAD_NAME);
+		}
+		self.pos += end;
+		return Ok(&rest[..end]);
+	}
+
+	fn resolve(&self, prefix: &str) -> Result<String, &'static str> {
+		for scope in self.scopes.iter().rev() {
+			for (p, uri) in scope.iter() {
+				if p == prefix {
+					return Ok(uri.clone());
+				}
+			}
+		}
+		if prefix.is_empty() {
+			return Ok(String::new());
+		}
+		if prefix == "xml" {
+			return Ok("http://www.w3.org/XML/1998/namespace".to_string());
+		}
+		return Err(UNKNOWN_PREFIX);
+	}
+
+	fn element(&mut self) -> Result<Element, &'static str> {
+		if !self.rest().starts_with('<') {
+			return Err(UNEXPECTED_EOF);
+		}
+		self.pos += 1;
+		let qname = self.name()?;
+
+		let mut rawattrs: Vec<(&str, String)> = vec![];
+		let mut scope: Vec<(String, String)> = vec![];
+		let selfclosed;
+		loop {
+			self.skip_ws();
+			let rest = self.rest();
+			if rest.starts_with("/>") {
+				self.pos += 2;
+				selfclosed = true;
+				break;
+			}
+			if rest.starts_with('>') {
+				self.pos += 1;
+				selfclosed = false;
+				break;
+			}
+			let key = self.name()?;
+			self.skip_ws();
+			if !self.rest().starts_with('=') {
+				return Err(BAD_ATTR);
+			}
+			self.pos += 1;
+			self.skip_ws();
+			let quote = match self.rest().chars().next() {
+				Some(c) if c == '"' || c == '\'' => c,
+				_ => {
+					return Err(BAD_ATTR);
+				}
+			};
+			self.pos += 1;
+			let val = unescape(self.skip_until(if quote == '"' { "\"" } else { "'" })?)?;
+			if key == "xmlns" {
+				scope.push((String::new(), val));
+			} else if let Some(prefix) = key.strip_prefix("xmlns:") {
+				scope.push((prefix.to_string(), val));
+			} else {
+				rawattrs.push((key, val));
+			}
+		}
+		self.scopes.push(scope);
+
+		let (prefix, name) = match qname.split_once(':') {
+			Some((p, n)) => (p, n),
+			None => ("", qname),
+		};
+		let mut element = Element {
+			ns: self.resolve(prefix)?,
+			name: name.to_string(),
+			attrs: vec![],
+			children: vec![],
+		};
+		for (key, val) in rawattrs {
+			let key = match key.split_once(':') {
+				Some((p, n)) => format!("{}{}", self.resolve(p)?, n),
+				None => key.to_string(),
+			};
+			element.attrs.push((key, val));
+		}
+
+		if !selfclosed {
+			loop {
+				let rest = self.rest();
+				if rest.is_empty() {
+					return Err(UNEXPECTED_EOF);
+				}
+				if let Some(rest) = rest.strip_prefix("</") {
+					self.pos += 2;
+					if !rest.starts_with(qname) {
+						return Err(MISMATCHED_TAG);
+					}
+					self.pos += qname.len();
+					self.skip_ws();
+					if !self.rest().starts_with('>') {
+						return Err(MISMATCHED_TAG);
+					}
+					self.pos += 1;
+					break;
+				}
+				if rest.starts_with("<![CDATA[") {
+					self.pos += 9;
+					let text = self.skip_until("]]>")?;
+					element.children.push(Node::Text(text.to_string()));
+				} else if rest.starts_with("<!--") {
+					self.skip_until("-->")?;
+				} else if rest.starts_with("<?") {
+					self.skip_until("?>")?;
+				} else if rest.starts_with('<') {
+					element.children.push(Node::Element(self.element()?));
+				} else {
+					let end = rest.find('<').unwrap_or(rest.len());
+					self.pos += end;
+					element.children.push(Node::Text(unescape(&rest[..end])?));
+				}
+			}
+		}
+
+		self.scopes.pop();
+		return Ok(element);
+	}
+}
+
+/// `parse` parse the document and return the root element.
+pub fn parse(src: &str) -> Result<Element, &'static str> {
+	let mut parser = Parser {
+		src,
+		pos: 0,
+		scopes: vec![],
+	};
+	parser.skip_misc()?;
+	let root = parser.element()?;
+	parser.skip_misc()?;
+	if !parser.rest().is_empty() {
+		return Err(TRAILING_CONTENT);
+	}
+	return Ok(root);
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::h2tp::utils::xml::{escape, parse};
+
+	#[test]
+	fn test_parse() {
+		let doc = parse(
+			r#"<?xml version="1.0" encoding="utf-8" ?>
+			<D:propertyupdate xmlns:D="DAV:" xmlns:Z="http://ns.example.com/z/">
+				<!-- comment -->
+				<D:set><D:prop><Z:Author>Jim &amp; Roy</Z:Author><color xmlns="urn:c"><![CDATA[<red>]]></color></D:prop></D:set>
+				<D:remove><D:prop><Z:Copyright-Owner/></D:prop></D:remove>
+			</D:propertyupdate>"#,
+		)
+		.unwrap();
+		assert!(doc.is("DAV:", "propertyupdate"));
+		let prop = doc.child("DAV:", "set").unwrap().child("DAV:", "prop").unwrap();
+		let author = prop.child("http://ns.example.com/z/", "Author").unwrap();
+		assert_eq!(author.text(), "Jim & Roy");
+		assert_eq!(prop.child("urn:c", "color").unwrap().text(), "<red>");
+		assert!(doc.child("DAV:", "remove").is_some());
+
+		assert!(parse("<a><b></a>").is_err());
+		assert!(parse("<x:a/>").is_err());
+
+		let mut dest = String::new();
+		escape(&mut dest, "<a href=\"x\">&</a>");
+		assert_eq!(dest, "&lt;a href=&quot;x&quot;&gt;&amp;&lt;/a&gt;");
+	}
+}
@@ -0,0 +1,212 @@
+use std::collections::HashMap;
+use async_trait::async_trait;
+use crate::h2tp::handler::Handler;
+use crate::h2tp::radix::RouteError;
+use crate::h2tp::router::{impl_handler_for_router, Middleware, Router, RouterFindError};
+use crate::h2tp::Request;
+
+/// the name of the request parameter that captures the wildcard part of the host, like `a.b` of `a.b.example.com`
+/// that matched by `*.example.com`.
+pub const SUBDOMAIN_PARAM: &str = "subdomain";
+
+/// `normalize_host` lowercase the host, and remove the port and the trailing dot.
+fn normalize_host(v: &str) -> String {
+	let v = v.trim();
+	let host = if v.starts_with('[') {
+		match v.find(']') {
+			Some(idx) => &v[..idx + 1],
+			None => v,
+		}
+	} else {
+		match v.rfind(':') {
+			Some(idx) => &v[..idx],
+			None => v,
+		}
+	};
+	return host.trim_end_matches('.').to_ascii_lowercase();
+}
+
+/// `HostRouter` dispatches the requests to the handlers by the `host` header, or the TLS SNI
+/// if there is no `host` header. the exact names win over the wildcards(`*.example.com`), the
+/// longer wildcards win over the shorter ones, and the fallback handles the rest.
+pub struct HostRouter {
+	exact: HashMap<String, Box<dyn Handler>>,
+	/// `(".example.com", handler)`, sorted by the length of the suffix, the longest first.
+	wildcards: Vec<(String, Box<dyn Handler>)>,
+	fallback: Option<Box<dyn Handler>>,
+	before: Vec<Box<dyn Middleware>>,
+	after: Vec<Box<dyn Middleware>>,
+}
+
+impl HostRouter {
+	pub fn new() -> Self {
+		return Self {
+			exact: HashMap::new(),
+			wildcards: vec![],
+			fallback: None,
+			before: vec![],
+			after: vec![],
+		};
+	}
+
+	/// `register` add a handler for the host `pattern`, like `example.com` or `*.example.com`.
+	pub fn register(&mut self, pattern: &str, handler: Box<dyn Handler>) -> Result<&mut Self, RouteError> {
+		let pattern = normalize_host(pattern);
+		match pattern.strip_prefix('*') {
+			Some(suffix) => {
+				if !suffix.starts_with('.') || suffix.len() < 2 || suffix.contains('*') {
+					return Err(RouteError::BadPattern(format!("`{}` is not a valid wildcard host", pattern)));
+				}
+				if self.wildcards.iter().any(|(s, _)| s == suffix) {
+					return Err(RouteError::Conflict(format!("`{}` is already registered", pattern)));
+				}
+				let idx = self.wildcards.iter().position(|(s, _)| s.len() < suffix.len()).unwrap_or(self.wildcards.len());
+				self.wildcards.insert(idx, (suffix.to_string(), handler));
+			}
+			None => {
+				if pattern.is_empty() || pattern.contains('*') {
+					return Err(RouteError::BadPattern(format!("`{}` is not a valid host", pattern)));
+				}
+				if self.exact.contains_key(&pattern) {
+					return Err(RouteError::Conflict(format!("`{}` is already registered", pattern)));
+				}
+				self.exact.insert(pattern, handler);
+			}
+		}
+		return Ok(self);
+	}
+
+	/// `set_fallback` set the handler for the hosts that not registered.
+	pub fn set_fallback(&mut self, handler: Box<dyn Handler>) -> &mut Self {
+		self.fallback = Some(handler);
+		return self;
+	}
+
+	pub fn add_before(&mut self, middleware: Box<dyn Middleware>) -> &mut Self {
+		self.before.push(middleware);
+		return self;
+	}
+
+	pub fn add_after(&mut self, middleware: Box<dyn Middleware>) -> &mut Self {
+		self.after.push(middleware);
+		return self;
+	}
+
+	/// `lookup` return the handler and the wildcard part of the host.
+	pub fn lookup(&self, host: &str) -> Option<(&dyn Handler, Option<String>)> {
+		let host = normalize_host(host);
+		match self.exact.get(&host) {
+			Some(h) => {
+				return Some((h.as_ref(), None));
+			}
+			None => {}
+		}
+		for (suffix, h) in self.wildcards.iter() {
+			if host.len() > suffix.len() && host.ends_with(suffix.as_str()) {
+				return Some((h.as_ref(), Some(host[..host.len() - suffix.len()].to_string())));
+			}
+		}
+		return self.fallback.as_ref().map(|h| (h.as_ref(), None));
+	}
+}
+
+#[async_trait]
+impl Router for HostRouter {
+	fn middleware<'a, 'c>(&self, _req: &'a Request<'c>) -> (&Vec<Box<dyn Middleware>>, &Vec<Box<dyn Middleware>>) {
+		return (&self.before, &self.after);
+	}
+
+	fn find<'a, 'c>(&self, req: &'a mut Request<'c>) -> Result<&dyn Handler, RouterFindError> {
+		let host = match req.headers().and_then(|h| h.host()) {
+			Some(v) => v.as_str(),
+			None => req.sni().unwrap_or(""),
+		};
+		let (handler, subdomain) = match self.lookup(host) {
+			Some(v) => v,
+			None => {
+				return Err(RouterFindError::NotFound);
+			}
+		};
+		match subdomain {
+			Some(v) => {
+				req.params.push((SUBDOMAIN_PARAM.to_string(), v));
+			}
+			None => {}
+		}
+		return Ok(handler);
+	}
+}
+
+impl_handler_for_router!(HostRouter);
+
+#[cfg(test)]
+mod tests {
+	use crate::h2tp::handler::Handler;
+	use crate::h2tp::radix::RouteError;
+	use crate::h2tp::vhost::HostRouter;
+	use crate::h2tp::{hns, ClosureHandler, Request, Response};
+
+	fn tagged(tag: &'static str) -> Box<dyn Handler> {
+		return Box::new(ClosureHandler::new(move |_, resp| {
+			Box::pin(async move {
+				resp.headers().append("x-host", tag);
+			})
+		}));
+	}
+
+	fn with_subdomain(tag: &'static str) -> Box<dyn Handler> {
+		return Box::new(ClosureHandler::new(move |req, resp| {
+			Box::pin(async move {
+				let v = format!("{} {}", tag, req.param("subdomain").unwrap_or(""));
+				resp.headers().append("x-host", &v);
+			})
+		}));
+	}
+
+	async fn call(router: &HostRouter, host: Option<&str>) -> String {
+		let mut req = Request::new();
+		req.msg.startline.0.push_str("GET");
+		req.msg.startline.1.push_str("/");
+		match host {
+			Some(v) => {
+				req.msg.headers_builder().append(hns::HOST, v);
+			}
+			None => {}
+		}
+		let mut resp = Response::new();
+		Handler::handle(router, &mut req, &mut resp).await;
+		return match resp.msg.headers.as_ref().and_then(|h| h.getone("x-host")) {
+			Some(v) => v.clone(),
+			None => resp.msg.startline.1.clone(),
+		};
+	}
+
+	#[tokio::test]
+	async fn test_vhost() {
+		let mut router = HostRouter::new();
+		router
+			.register("Example.com", tagged("exact"))
+			.unwrap()
+			.register("*.example.com", with_subdomain("wild"))
+			.unwrap()
+			.register("*.api.example.com", with_subdomain("api"))
+			.unwrap();
+
+		assert_eq!(call(&router, Some("example.com:8080")).await, "exact");
+		assert_eq!(call(&router, Some("EXAMPLE.com.")).await, "exact");
+		assert_eq!(call(&router, Some("www.example.com")).await, "wild www");
+		assert_eq!(call(&router, Some("a.b.example.com")).await, "wild a.b");
+		assert_eq!(call(&router, Some("v1.api.example.com")).await, "api v1");
+		assert_eq!(call(&router, Some("api.example.com")).await, "wild api");
+		assert_eq!(call(&router, Some("example.org")).await, "404");
+		assert_eq!(call(&router, None).await, "404");
+
+		router.set_fallback(tagged("fallback"));
+		assert_eq!(call(&router, Some("[::1]:8080")).await, "fallback");
+
+		assert!(matches!(router.register("example.com", tagged("x")), Err(RouteError::Conflict(_))));
+		assert!(matches!(router.register("*.example.com", tagged("x")), Err(RouteError::Conflict(_))));
+		assert!(matches!(router.register("a*.example.com", tagged("x")), Err(RouteError::BadPattern(_))));
+		assert!(matches!(router.register("*example.com", tagged("x")), Err(RouteError::BadPattern(_))));
+	}
+}
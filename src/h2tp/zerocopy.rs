@@ -0,0 +1,54 @@
+use std::io;
+use tokio::fs::File;
+use tokio::net::TcpStream;
+
+/// `sendfile` send `count` bytes of `file` from its current position to `tcp` by `sendfile(2)`,
+/// the data is copied in the kernel and never goes through the userspace buffers.
+/// return `Ok(false)` if nothing has been sent because zero-copy is not supported on this platform.
+#[cfg(target_os = "linux")]
+pub(crate) async fn sendfile(tcp: &TcpStream, file: &mut File, count: u64) -> io::Result<bool> {
+	use std::os::unix::io::AsRawFd;
+	use tokio::io::{AsyncSeekExt, Interest};
+
+	let mut offset = file.stream_position().await? as libc::off_t;
+	let sockfd = tcp.as_raw_fd();
+	let filefd = file.as_raw_fd();
+	let mut remain = count;
+
+	while remain > 0 {
+		tcp.writable().await?;
+		let result = tcp.try_io(Interest::WRITABLE, || {
+			// the kernel sends at most 0x7ffff000 bytes per call.
+			let size = std::cmp::min(remain, 0x7ffff000) as usize;
+			let n = unsafe { libc::sendfile(sockfd, filefd, &mut offset, size) };
+			if n < 0 {
+				return Err(io::Error::last_os_error());
+			}
+			return Ok(n as u64);
+		});
+		match result {
+			Ok(0) => {
+				return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "file is shorter than expected"));
+			}
+			Ok(n) => {
+				remain -= n;
+			}
+			Err(e) => {
+				if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::Interrupted {
+					continue;
+				}
+				// the file does not support `sendfile`, fallback if nothing has been sent.
+				if remain == count && matches!(e.raw_os_error(), Some(libc::EINVAL) | Some(libc::ENOSYS)) {
+					return Ok(false);
+				}
+				return Err(e);
+			}
+		}
+	}
+	return Ok(true);
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) async fn sendfile(_tcp: &TcpStream, _file: &mut File, _count: u64) -> io::Result<bool> {
+	return Ok(false);
+}
@@ -1,3 +1,5 @@
 #![allow(dead_code)]
 
+extern crate self as spk;
+
 pub mod h2tp;
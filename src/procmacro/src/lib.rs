@@ -1,64 +1,384 @@
 extern crate proc_macro;
-#[macro_use]
-extern crate quote;
 
-use proc_macro::{TokenStream};
-use syn::{Lit, MetaItem};
+use proc_macro::TokenStream;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use syn::parse::{Parse, ParseStream, Parser};
+use syn::punctuated::Punctuated;
+use syn::spanned::Spanned;
+use syn::{parse_macro_input, parse_quote, Attribute, DeriveInput, Expr, ExprLit, Item, Lit, LitStr, Meta, Token};
 
-#[proc_macro_derive(Handler, attributes(From))]
+/// `#[derive(Handler)]` implement `Handler` by forwarding to the `handle` of another trait, which is chosen by
+/// `#[From = "..."]`:
+///
+/// - `router`: `Router::handle`;
+/// - `fs::readable`: `Readable::handle`.
+///
+/// the generated code refers the items by `::spk`, use `#[spk(crate = "path")]` if the crate is renamed.
+#[proc_macro_derive(Handler, attributes(From, spk))]
 pub fn impl_handler(ts: TokenStream) -> TokenStream {
-	let raw = ts.to_string();
-	let ast = syn::parse_derive_input(&raw).unwrap();
-	let generics = ast.generics;
-	if !generics.lifetimes.is_empty() || !generics.ty_params.is_empty() {
-		panic!("item can not be generic typed currently");
+	let ast = parse_macro_input!(ts as DeriveInput);
+	return match derive_handler(ast) {
+		Ok(v) => v.into(),
+		Err(e) => e.to_compile_error().into(),
+	};
+}
+
+/// `crate_path` return the path of the `spk` crate that the generated code refers, `::spk` by default,
+/// or the one set by `#[spk(crate = "path")]` in `attrs`.
+fn crate_path(attrs: &[Attribute]) -> syn::Result<syn::Path> {
+	let mut krate: syn::Path = parse_quote!(::spk);
+	for attr in attrs.iter() {
+		if attr.path().is_ident("spk") {
+			attr.parse_nested_meta(|meta| {
+				if meta.path.is_ident("crate") {
+					let v: LitStr = meta.value()?.parse()?;
+					krate = v.parse()?;
+					return Ok(());
+				}
+				return Err(meta.error("unknown `spk` option, expected `crate = \"...\"`"));
+			})?;
+		}
 	}
+	return Ok(krate);
+}
 
-	let mut from: Option<String> = None;
-	for attr in ast.attrs {
-		if attr.name() == "From" {
-			match attr.value {
-				MetaItem::NameValue(_, l) => {
-					match l {
-						Lit::Str(v, _) => {
-							from = Some(v);
-						}
-						_ => {}
+fn derive_handler(ast: DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+	let mut from: Option<LitStr> = None;
+	let krate = crate_path(&ast.attrs)?;
+	for attr in ast.attrs.iter() {
+		if attr.path().is_ident("From") {
+			match &attr.meta {
+				Meta::NameValue(nv) => match &nv.value {
+					Expr::Lit(ExprLit { lit: Lit::Str(v), .. }) => {
+						from = Some(v.clone());
+					}
+					v => {
+						return Err(syn::Error::new(v.span(), "expected a string, like `#[From = \"router\"]`"));
 					}
+				},
+				_ => {
+					return Err(syn::Error::new(attr.span(), "expected `#[From = \"router\"]` or `#[From = \"fs::readable\"]`"));
 				}
-				_ => {}
 			}
 		}
 	}
 
-	let base_type: String;
-	let from = from.unwrap().to_lowercase();
-	match from.as_str() {
-		"fs::readable" => {
-			base_type = "spk::h2tp::fs::read::Readable".to_string();
+	let from = match from {
+		Some(v) => v,
+		None => {
+			return Err(syn::Error::new(
+				ast.ident.span(),
+				"missing `#[From = \"...\"]`, expected `router` or `fs::readable`",
+			));
+		}
+	};
+	let base: syn::Path = match from.value().to_lowercase().as_str() {
+		"fs::readable" => parse_quote!(#krate::h2tp::Readable),
+		"router" => parse_quote!(#krate::h2tp::Router),
+		v => {
+			return Err(syn::Error::new(
+				from.span(),
+				format!("unknown from `{}`, expected `router` or `fs::readable`", v),
+			));
+		}
+	};
+
+	let name = &ast.ident;
+	let mut generics = ast.generics.clone();
+	if !generics.params.is_empty() {
+		generics.make_where_clause().predicates.push(parse_quote!(Self: #base));
+	}
+	let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+	return Ok(quote::quote!(
+		#[#krate::h2tp::async_trait]
+		impl #impl_generics #krate::h2tp::Handler for #name #ty_generics #where_clause {
+			#[inline]
+			async fn handle<'a, 'c, 'h: 'a>(
+				&'h self,
+				req: &'a mut #krate::h2tp::Request<'c>,
+				resp: &'a mut #krate::h2tp::Response<'c>,
+			) -> () {
+				#base::handle(self, req, resp).await
+			}
+		}
+	));
+}
+
+fn fnv1a64(data: &[u8]) -> u64 {
+	let mut hash: u64 = 0xcbf29ce484222325;
+	for b in data {
+		hash ^= *b as u64;
+		hash = hash.wrapping_mul(0x100000001b3);
+	}
+	return hash;
+}
+
+/// `walk` collect the files under `dir`, the symlinked directories are skipped, so a link cycle can not
+/// recurse forever; the symlinked files are kept.
+fn walk(dir: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+	let mut entries: Vec<PathBuf> = vec![];
+	for entry in std::fs::read_dir(dir)? {
+		entries.push(entry?.path());
+	}
+	entries.sort();
+	for path in entries {
+		let meta = std::fs::symlink_metadata(&path)?;
+		if meta.is_dir() {
+			walk(&path, files)?;
+		} else if !(meta.file_type().is_symlink() && path.is_dir()) {
+			files.push(path);
+		}
+	}
+	return Ok(());
+}
+
+/// `EmbedArgs` is the input of `embed_dir!`: the directory, and an optional `crate = "path"`.
+struct EmbedArgs {
+	dir: LitStr,
+	krate: syn::Path,
+}
+
+impl Parse for EmbedArgs {
+	fn parse(input: ParseStream) -> syn::Result<Self> {
+		let dir: LitStr = input.parse()?;
+		let mut krate: syn::Path = parse_quote!(::spk);
+		if input.parse::<Option<Token![,]>>()?.is_some() && !input.is_empty() {
+			input.parse::<Token![crate]>()?;
+			input.parse::<Token![=]>()?;
+			let v: LitStr = input.parse()?;
+			krate = v.parse()?;
+			input.parse::<Option<Token![,]>>()?;
 		}
-		"router" => {
-			base_type = "spk::h2tp::router::Router".to_string();
+		return Ok(Self { dir, krate });
+	}
+}
+
+/// `embed_dir!("assets")` walk the directory(relative to the `Cargo.toml` of the calling crate) at compile time,
+/// and expand to a `Vec<spk::h2tp::EmbeddedFile>` that contains all the files in it.
+/// the entity-tags are the hashes of the file contents, and the sidecar files(`.br`, `.gz`) are embedded as-is.
+/// the generated code refers the items by `::spk`, use `embed_dir!("assets", crate = "path")` if the crate is renamed.
+///
+/// files added to the directory will not trigger a rebuild, only the changes of embedded files will.
+#[proc_macro]
+pub fn embed_dir(ts: TokenStream) -> TokenStream {
+	let args = parse_macro_input!(ts as EmbedArgs);
+	return match embed(args) {
+		Ok(v) => v.into(),
+		Err(e) => e.to_compile_error().into(),
+	};
+}
+
+fn embed(args: EmbedArgs) -> syn::Result<proc_macro2::TokenStream> {
+	let manifest_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap_or_else(|_| ".".to_string());
+	let root = Path::new(&manifest_dir).join(args.dir.value());
+
+	let mut paths = vec![];
+	match walk(&root, &mut paths) {
+		Ok(_) => {}
+		Err(e) => {
+			return Err(syn::Error::new(
+				args.dir.span(),
+				format!("embed_dir: can not read `{}`: {}", args.dir.value(), e),
+			));
+		}
+	}
+
+	let krate = &args.krate;
+	let mut files = vec![];
+	for path in paths {
+		let data = match std::fs::read(&path) {
+			Ok(v) => v,
+			Err(e) => {
+				return Err(syn::Error::new(
+					args.dir.span(),
+					format!("embed_dir: can not read `{}`: {}", path.display(), e),
+				));
+			}
+		};
+		let etag = format!("\"{:016x}-{:x}\"", fnv1a64(&data), data.len());
+		let modified = std::fs::metadata(&path)
+			.and_then(|m| m.modified())
+			.ok()
+			.and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+			.map(|d| d.as_secs())
+			.unwrap_or(0);
+		let name: Vec<String> = path
+			.strip_prefix(&root)
+			.unwrap()
+			.components()
+			.map(|c| c.as_os_str().to_string_lossy().to_string())
+			.collect();
+		let name = name.join("/");
+		let abs = path.to_string_lossy().to_string();
+		files.push(quote::quote!(
+			#krate::h2tp::EmbeddedFile::new(#name, include_bytes!(#abs), #etag, #modified)
+		));
+	}
+
+	return Ok(quote::quote!(
+		vec![#(#files),*]
+	));
+}
+
+/// `check_pattern` report the pattern that `RadixRouter` can not register, at the span of the literal.
+fn check_pattern(pattern: &LitStr) -> syn::Result<()> {
+	let v = pattern.value();
+	if !v.starts_with('/') {
+		return Err(syn::Error::new(pattern.span(), format!("route: `{}` does not start with `/`", v)));
+	}
+	let segs: Vec<&str> = v[1..].split('/').collect();
+	for (idx, seg) in segs.iter().enumerate() {
+		let name = match seg.strip_prefix(':') {
+			Some(name) => name,
+			None => match seg.strip_prefix('*') {
+				Some(name) => {
+					if idx != segs.len() - 1 {
+						return Err(syn::Error::new(
+							pattern.span(),
+							format!("route: `{}` has a catch-all parameter that is not at the end", v),
+						));
+					}
+					name
+				}
+				None => {
+					continue;
+				}
+			},
+		};
+		if name.is_empty() || name.contains(|c| c == ':' || c == '*') {
+			return Err(syn::Error::new(pattern.span(), format!("route: `{}` has a bad parameter name", v)));
 		}
-		_ => {
-			panic!("{}", format!("unknown from `{}`", from))
+	}
+	return Ok(());
+}
+
+/// `route_item` wrap the async fn into a unit struct of the same name, which implements `Handler` and `Route`.
+/// the `#[spk(crate = "path")]` of the fn is taken as the crate path like `#[derive(Handler)]`.
+fn route_item(method: String, pattern: LitStr, item: TokenStream) -> syn::Result<proc_macro2::TokenStream> {
+	check_pattern(&pattern)?;
+	let mut func = match syn::parse::<Item>(item)? {
+		Item::Fn(v) => v,
+		v => {
+			return Err(syn::Error::new(v.span(), "route: the attribute can only be used on functions"));
 		}
+	};
+	if func.sig.asyncness.is_none() {
+		return Err(syn::Error::new(
+			func.sig.fn_token.span,
+			format!("route: `{}` must be an async fn", func.sig.ident),
+		));
 	}
+	let krate = crate_path(&func.attrs)?;
+	func.attrs.retain(|attr| !attr.path().is_ident("spk"));
+
+	let vis = func.vis.clone();
+	let name = func.sig.ident.clone();
+	let pattern = pattern.value();
+	return Ok(quote::quote!(
+		#[allow(non_camel_case_types)]
+		#vis struct #name;
+
+		impl #name {
+			#func
+		}
 
-	let name = ast.ident;
-	let base_type = syn::Ident::new(base_type);
-	let code = quote::quote!(
-		#[async_trait]
-		impl spk::h2tp::handler::Handler for #name {
+		#[#krate::h2tp::async_trait]
+		impl #krate::h2tp::Handler for #name {
 			#[inline]
 			async fn handle<'a, 'c, 'h: 'a>(
 				&'h self,
-				req: &'a mut spk::h2tp::request::Request<'c>,
-				resp: &'a mut spk::h2tp::response::Response<'c>,
+				req: &'a mut #krate::h2tp::Request<'c>,
+				resp: &'a mut #krate::h2tp::Response<'c>,
 			) -> () {
-				#base_type::handle(self, req, resp).await
+				#name::#name(req, resp).await
+			}
+		}
+
+		impl #krate::h2tp::Route for #name {
+			fn method(&self) -> &'static str {
+				#method
 			}
+
+			fn pattern(&self) -> &'static str {
+				#pattern
+			}
+		}
+	));
+}
+
+fn method_route(method: &str, attr: TokenStream, item: TokenStream) -> TokenStream {
+	let pattern = match syn::parse::<LitStr>(attr) {
+		Ok(v) => v,
+		Err(e) => {
+			let msg = format!("route: expected `#[{}(\"/pattern\")]`", method.to_lowercase());
+			return syn::Error::new(e.span(), msg).to_compile_error().into();
 		}
-	);
-	return code.parse().unwrap();
-}
\ No newline at end of file
+	};
+	return match route_item(method.to_string(), pattern, item) {
+		Ok(v) => v.into(),
+		Err(e) => e.to_compile_error().into(),
+	};
+}
+
+/// `#[route("METHOD", "/pattern")]` turn an `async fn(&mut Request, &mut Response)` into a unit struct of the same name,
+/// which is a `Handler` and a `Route` that can be registered by `RadixRouter::route` or `routes!`.
+/// the method can be `*` for all the methods. the generated code refers the items by `::spk`,
+/// add `#[spk(crate = "path")]` to the fn if the crate is renamed.
+#[proc_macro_attribute]
+pub fn route(attr: TokenStream, item: TokenStream) -> TokenStream {
+	let parser = Punctuated::<LitStr, Token![,]>::parse_terminated;
+	let args = match parser.parse(attr) {
+		Ok(v) if v.len() == 2 => v,
+		Ok(v) => {
+			return syn::Error::new(v.span(), "route: expected `#[route(\"METHOD\", \"/pattern\")]`").to_compile_error().into();
+		}
+		Err(e) => {
+			return e.to_compile_error().into();
+		}
+	};
+	let method = args[0].value().to_uppercase();
+	if method != "*" && (method.is_empty() || !method.bytes().all(|b| b.is_ascii_alphabetic() || b == b'-')) {
+		return syn::Error::new(args[0].span(), format!("route: `{}` is not a method", args[0].value())).to_compile_error().into();
+	}
+	return match route_item(method, args[1].clone(), item) {
+		Ok(v) => v.into(),
+		Err(e) => e.to_compile_error().into(),
+	};
+}
+
+/// `#[get("/pattern")]` is the shorthand of `#[route("GET", "/pattern")]`.
+#[proc_macro_attribute]
+pub fn get(attr: TokenStream, item: TokenStream) -> TokenStream {
+	return method_route("GET", attr, item);
+}
+
+#[proc_macro_attribute]
+pub fn post(attr: TokenStream, item: TokenStream) -> TokenStream {
+	return method_route("POST", attr, item);
+}
+
+#[proc_macro_attribute]
+pub fn put(attr: TokenStream, item: TokenStream) -> TokenStream {
+	return method_route("PUT", attr, item);
+}
+
+#[proc_macro_attribute]
+pub fn delete(attr: TokenStream, item: TokenStream) -> TokenStream {
+	return method_route("DELETE", attr, item);
+}
+
+#[proc_macro_attribute]
+pub fn patch(attr: TokenStream, item: TokenStream) -> TokenStream {
+	return method_route("PATCH", attr, item);
+}
+
+#[proc_macro_attribute]
+pub fn head(attr: TokenStream, item: TokenStream) -> TokenStream {
+	return method_route("HEAD", attr, item);
+}
+
+#[proc_macro_attribute]
+pub fn options(attr: TokenStream, item: TokenStream) -> TokenStream {
+	return method_route("OPTIONS", attr, item);
+}
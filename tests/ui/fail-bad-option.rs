@@ -0,0 +1,6 @@
+#[derive(spk::h2tp::Handler)]
+#[From = "router"]
+#[spk(krate = "spk")]
+struct Empty;
+
+fn main() {}
@@ -0,0 +1,3 @@
+fn main() {
+	let _ = spk::h2tp::embed_dir!("no/such/dir");
+}
@@ -0,0 +1,4 @@
+#[derive(spk::h2tp::Handler)]
+struct Empty;
+
+fn main() {}
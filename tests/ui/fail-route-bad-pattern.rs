@@ -0,0 +1,7 @@
+#[spk::h2tp::get("/files/*path/raw")]
+async fn files(_req: &mut spk::h2tp::Request<'_>, _resp: &mut spk::h2tp::Response<'_>) {}
+
+#[spk::h2tp::route("GET", "users/:id")]
+async fn show_user(_req: &mut spk::h2tp::Request<'_>, _resp: &mut spk::h2tp::Response<'_>) {}
+
+fn main() {}
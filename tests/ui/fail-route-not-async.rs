@@ -0,0 +1,4 @@
+#[spk::h2tp::get("/users/:id")]
+fn show_user(_req: &mut spk::h2tp::Request<'_>, _resp: &mut spk::h2tp::Response<'_>) {}
+
+fn main() {}
@@ -0,0 +1,5 @@
+#[derive(spk::h2tp::Handler)]
+#[From = "websocket"]
+struct Empty;
+
+fn main() {}
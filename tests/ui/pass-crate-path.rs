@@ -0,0 +1,25 @@
+use renamed::h2tp::{Handler, Middleware, Request, Router, RouterFindError};
+use spk as renamed;
+
+#[derive(renamed::h2tp::Handler)]
+#[From = "router"]
+#[spk(crate = "renamed")]
+struct Empty {
+	mws: Vec<Box<dyn Middleware>>,
+}
+
+impl Router for Empty {
+	fn middleware<'a, 'c>(&self, _req: &'a Request<'c>) -> (&Vec<Box<dyn Middleware>>, &Vec<Box<dyn Middleware>>) {
+		return (&self.mws, &self.mws);
+	}
+
+	fn find<'a, 'c>(&self, _req: &'a mut Request<'c>) -> Result<&dyn Handler, RouterFindError> {
+		return Err(RouterFindError::NotFound);
+	}
+}
+
+fn assert_handler<H: Handler>(_: &H) {}
+
+fn main() {
+	assert_handler(&Empty { mws: vec![] });
+}
@@ -0,0 +1,34 @@
+use std::marker::PhantomData;
+use spk::h2tp::{Handler, Middleware, Request, Router, RouterFindError};
+
+#[derive(spk::h2tp::Handler)]
+#[From = "router"]
+struct Prefixed<'p, T: Send + Sync> {
+	prefix: &'p str,
+	mws: Vec<Box<dyn Middleware>>,
+	_t: PhantomData<T>,
+}
+
+impl<'p, T: Send + Sync> Router for Prefixed<'p, T> {
+	fn middleware<'a, 'c>(&self, _req: &'a Request<'c>) -> (&Vec<Box<dyn Middleware>>, &Vec<Box<dyn Middleware>>) {
+		return (&self.mws, &self.mws);
+	}
+
+	fn find<'a, 'c>(&self, req: &'a mut Request<'c>) -> Result<&dyn Handler, RouterFindError> {
+		if req.path().starts_with(self.prefix) {
+			return Err(RouterFindError::Undefined);
+		}
+		return Err(RouterFindError::NotFound);
+	}
+}
+
+fn assert_handler<H: Handler>(_: &H) {}
+
+fn main() {
+	let router = Prefixed::<u8> {
+		prefix: "/api",
+		mws: vec![],
+		_t: PhantomData,
+	};
+	assert_handler(&router);
+}
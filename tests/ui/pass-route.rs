@@ -0,0 +1,15 @@
+use renamed::h2tp::{Request, Response, Route};
+use spk as renamed;
+
+#[renamed::h2tp::get(r"/files/*path")]
+#[spk(crate = "renamed")]
+async fn files(_req: &mut Request<'_>, _resp: &mut Response<'_>) {}
+
+#[renamed::h2tp::route("post", "/say/\u{4f60}\u{597d}")]
+#[spk(crate = "renamed")]
+pub async fn hello(_req: &mut Request<'_>, _resp: &mut Response<'_>) {}
+
+fn main() {
+	assert_eq!((files.method(), files.pattern()), ("GET", "/files/*path"));
+	assert_eq!((hello.method(), hello.pattern()), ("POST", "/say/你好"));
+}